@@ -8,12 +8,21 @@ fn main() -> Result<(), anyhow::Error> {
     Abigen::new("erc20", "abis/ERC20.json")?
         .generate()?
         .write_to_file("src/abi/erc20.rs")?;
+    Abigen::new("erc20_name_bytes", "abis/ERC20NameBytes.json")?
+        .generate()?
+        .write_to_file("src/abi/erc20_name_bytes.rs")?;
+    Abigen::new("erc20_symbol_bytes", "abis/ERC20SymbolBytes.json")?
+        .generate()?
+        .write_to_file("src/abi/erc20_symbol_bytes.rs")?;
     Abigen::new("factory", "abis/factory.json")?
         .generate()?
         .write_to_file("src/abi/factory.rs")?;
     Abigen::new("positionmanager", "abis/NonfungiblePositionManager.json")?
         .generate()?
         .write_to_file("src/abi/positionmanager.rs")?;
+    Abigen::new("chainlink_aggregator", "abis/ChainlinkAggregator.json")?
+        .generate()?
+        .write_to_file("src/abi/chainlink_aggregator.rs")?;
 
     Ok(())
 }
@@ -0,0 +1,458 @@
+use crate::pb::uniswap::erc20_token::MetadataStatus;
+use crate::pb::uniswap::Erc20Token;
+use crate::{network, twap};
+use anyhow::{anyhow, Context};
+use std::str::FromStr;
+use substreams::errors::Error;
+use substreams::scalar::BigDecimal;
+use substreams::Hex;
+
+// Module params are a list of `key=value` pairs separated by `&` (or new lines), lists of addresses
+// are comma separated. The `network` key selects one of the built-in profiles (Ethereum Mainnet when
+// missing), every other key overrides a value of that profile, e.g.
+//
+//   network=arbitrum-one&start_block=165
+//
+// The factory address is used as is for the `Factory` entity id, so it keeps the casing given in the params.
+// Chainlink feeds are given as `name:aggregator:decimals`, e.g. `chainlink_feeds=ETH/USD:0x37bC...a9e6:8`.
+// Token overrides are given as `address:decimals:symbol:name`, the name being everything after the third `:`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    pub network: String,
+    pub factory: String,
+    pub position_manager: String,
+    pub error_pool: Option<String>,
+    pub start_block: u64,
+    pub wrapped_native_token: String,
+    pub minimum_native_locked: BigDecimal,
+    // manipulation guards of the prices used for pricing, see `guards`
+    pub minimum_active_native_liquidity: BigDecimal,
+    pub max_twap_tick_deviation: i32,
+    pub reversal_tick_tolerance: i32,
+    // windows, in seconds, of the TWAPs emitted by `map_pool_twaps`, see `twap`
+    pub twap_windows: Vec<u64>,
+    // TVL of a token valued in the native token for `whitelist_min_blocks` to whitelist it, see `whitelist`
+    pub whitelist_eth_tvl: BigDecimal,
+    pub whitelist_min_blocks: u64,
+    pub usd_reference_pool: String,
+    pub usd_reference_token: String,
+    pub stable_coins: Vec<String>,
+    pub usd_bundle_tokens: Vec<String>,
+    pub whitelist_tokens: Vec<String>,
+    pub chainlink_feeds: Vec<ChainlinkFeed>,
+    // name of the feed of the wrapped native token in USD, compared with the bundle price
+    pub chainlink_native_usd_feed: Option<String>,
+    // use the answer of `chainlink_native_usd_feed` as the bundle price instead of the pools
+    pub chainlink_as_bundle: bool,
+    // number of full days without a price update after which the price of a token is flagged as stale
+    pub stale_price_days: i64,
+    // metadata of the tokens whose contract can't be read, used instead of a placeholder
+    pub token_overrides: Vec<TokenOverride>,
+}
+
+// A Chainlink aggregator, the contract emitting the `AnswerUpdated` events behind a feed proxy. A feed
+// moves to a new aggregator when it is upgraded, so several aggregators can have the same name.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChainlinkFeed {
+    pub name: String,
+    pub aggregator: String,
+    pub decimals: u64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TokenOverride {
+    pub address: String,
+    pub decimals: u64,
+    pub symbol: String,
+    pub name: String,
+}
+
+impl TokenOverride {
+    pub fn token(&self) -> Erc20Token {
+        Erc20Token {
+            address: self.address.clone(),
+            name: self.name.clone(),
+            symbol: self.symbol.clone(),
+            decimals: self.decimals,
+            metadata_status: MetadataStatus::Overridden as i32,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        network::MAINNET.config()
+    }
+}
+
+impl Config {
+    pub fn from_params(params: &str) -> Result<Config, Error> {
+        let mut pairs = vec![];
+        for pair in params.split(|c| c == '&' || c == '\n') {
+            let pair = pair.trim();
+            if pair.is_empty() {
+                continue;
+            }
+
+            let (key, value) = pair
+                .split_once('=')
+                .ok_or_else(|| anyhow!("invalid param {pair:?}, expected key=value"))?;
+            pairs.push((key.trim(), value.trim()));
+        }
+
+        // the profile is selected first so the other keys override it whatever their order
+        let mut config = match pairs.iter().find(|(key, _)| *key == "network") {
+            Some((_, name)) => network::find_profile(name)
+                .ok_or_else(|| anyhow!("unknown network {name:?}"))?
+                .config(),
+            None => Config::default(),
+        };
+
+        for (key, value) in pairs {
+            match key {
+                "network" => {}
+                "factory" => {
+                    parse_address(value)?;
+                    config.factory = value.to_string();
+                }
+                "position_manager" => config.position_manager = parse_address(value)?,
+                "error_pool" => {
+                    config.error_pool = match value {
+                        "" => None,
+                        _ => Some(parse_address(value)?),
+                    }
+                }
+                "start_block" => {
+                    config.start_block = value
+                        .parse::<u64>()
+                        .with_context(|| format!("invalid start_block {value:?}"))?
+                }
+                "wrapped_native_token" => config.wrapped_native_token = parse_address(value)?,
+                "minimum_native_locked" => {
+                    config.minimum_native_locked =
+                        BigDecimal::from_str(value).map_err(|_| anyhow!("invalid minimum_native_locked {value:?}"))?
+                }
+                "minimum_active_native_liquidity" => {
+                    config.minimum_active_native_liquidity = BigDecimal::from_str(value)
+                        .map_err(|_| anyhow!("invalid minimum_active_native_liquidity {value:?}"))?
+                }
+                "max_twap_tick_deviation" => {
+                    config.max_twap_tick_deviation = value
+                        .parse::<u32>()
+                        .with_context(|| format!("invalid max_twap_tick_deviation {value:?}"))?
+                        as i32
+                }
+                "reversal_tick_tolerance" => {
+                    config.reversal_tick_tolerance = value
+                        .parse::<u32>()
+                        .with_context(|| format!("invalid reversal_tick_tolerance {value:?}"))?
+                        as i32
+                }
+                "twap_windows" => config.twap_windows = parse_twap_windows(value)?,
+                "whitelist_eth_tvl" => {
+                    config.whitelist_eth_tvl =
+                        BigDecimal::from_str(value).map_err(|_| anyhow!("invalid whitelist_eth_tvl {value:?}"))?
+                }
+                "whitelist_min_blocks" => {
+                    config.whitelist_min_blocks = value
+                        .parse::<u64>()
+                        .with_context(|| format!("invalid whitelist_min_blocks {value:?}"))?
+                }
+                "usd_reference_pool" => config.usd_reference_pool = parse_address(value)?,
+                "usd_reference_token" => config.usd_reference_token = parse_address(value)?,
+                "stable_coins" => config.stable_coins = parse_addresses(value)?,
+                "usd_bundle_tokens" => config.usd_bundle_tokens = parse_addresses(value)?,
+                "whitelist_tokens" => config.whitelist_tokens = parse_addresses(value)?,
+                "chainlink_feeds" => config.chainlink_feeds = parse_chainlink_feeds(value)?,
+                "chainlink_native_usd_feed" => {
+                    config.chainlink_native_usd_feed = match value {
+                        "" => None,
+                        _ => Some(value.to_string()),
+                    }
+                }
+                "chainlink_as_bundle" => {
+                    config.chainlink_as_bundle = value
+                        .parse::<bool>()
+                        .with_context(|| format!("invalid chainlink_as_bundle {value:?}"))?
+                }
+                "stale_price_days" => {
+                    config.stale_price_days = value
+                        .parse::<u32>()
+                        .with_context(|| format!("invalid stale_price_days {value:?}"))?
+                        as i64
+                }
+                "token_overrides" => config.token_overrides = parse_token_overrides(value)?,
+                unknown => return Err(anyhow!("unknown param {unknown:?}")),
+            }
+        }
+
+        Ok(config)
+    }
+
+    // The factory address as used in the `Factory` entity id
+    pub fn factory_id(&self) -> String {
+        let address = self.factory.trim_start_matches("0x");
+        format!("0x{address}")
+    }
+
+    pub fn factory_address(&self) -> Vec<u8> {
+        Hex::decode(self.factory.trim_start_matches("0x")).unwrap()
+    }
+
+    pub fn position_manager_address(&self) -> Vec<u8> {
+        Hex::decode(&self.position_manager).unwrap()
+    }
+
+    pub fn error_pool_address(&self) -> Option<Vec<u8>> {
+        self.error_pool.as_ref().map(|pool| Hex::decode(pool).unwrap())
+    }
+
+    pub fn is_whitelisted(&self, token_address: &str) -> bool {
+        self.whitelist_tokens.iter().any(|token| token == token_address)
+    }
+
+    pub fn is_stable_coin(&self, token_address: &str) -> bool {
+        self.stable_coins.iter().any(|token| token == token_address)
+    }
+
+    pub fn chainlink_feed(&self, aggregator: &str) -> Option<&ChainlinkFeed> {
+        self.chainlink_feeds.iter().find(|feed| feed.aggregator == aggregator)
+    }
+
+    pub fn token_override(&self, token_address: &str) -> Option<&TokenOverride> {
+        self.token_overrides.iter().find(|token| token.address == token_address)
+    }
+
+    pub fn chainlink_aggregator_addresses(&self) -> Vec<Vec<u8>> {
+        self.chainlink_feeds
+            .iter()
+            .map(|feed| Hex::decode(&feed.aggregator).unwrap())
+            .collect()
+    }
+}
+
+// Addresses are kept as lowercase hex without the `0x` prefix, like the keys of the stores
+fn parse_address(value: &str) -> Result<String, Error> {
+    let address = value.trim_start_matches("0x").to_lowercase();
+    let bytes = Hex::decode(&address).with_context(|| format!("invalid address {value:?}"))?;
+    if bytes.len() != 20 {
+        return Err(anyhow!("invalid address {value:?}, expected 20 bytes"));
+    }
+    Ok(address)
+}
+
+fn parse_addresses(value: &str) -> Result<Vec<String>, Error> {
+    value
+        .split(',')
+        .map(|address| address.trim())
+        .filter(|address| !address.is_empty())
+        .map(parse_address)
+        .collect()
+}
+
+// A window is split in `twap::BUCKETS_PER_WINDOW` buckets of at least a second
+fn parse_twap_windows(value: &str) -> Result<Vec<u64>, Error> {
+    value
+        .split(',')
+        .map(|window| window.trim())
+        .filter(|window| !window.is_empty())
+        .map(|window| match window.parse::<u64>() {
+            Ok(seconds) if seconds >= twap::BUCKETS_PER_WINDOW => Ok(seconds),
+            _ => Err(anyhow!(
+                "invalid twap window {window:?}, expected at least {} seconds",
+                twap::BUCKETS_PER_WINDOW
+            )),
+        })
+        .collect()
+}
+
+fn parse_chainlink_feeds(value: &str) -> Result<Vec<ChainlinkFeed>, Error> {
+    value
+        .split(',')
+        .map(|feed| feed.trim())
+        .filter(|feed| !feed.is_empty())
+        .map(|feed| {
+            let parts: Vec<&str> = feed.split(':').map(|part| part.trim()).collect();
+            match parts[..] {
+                [name, aggregator, decimals] if !name.is_empty() => Ok(ChainlinkFeed {
+                    name: name.to_string(),
+                    aggregator: parse_address(aggregator)?,
+                    decimals: decimals
+                        .parse::<u64>()
+                        .with_context(|| format!("invalid decimals in chainlink feed {feed:?}"))?,
+                }),
+                _ => Err(anyhow!(
+                    "invalid chainlink feed {feed:?}, expected name:aggregator:decimals"
+                )),
+            }
+        })
+        .collect()
+}
+
+fn parse_token_overrides(value: &str) -> Result<Vec<TokenOverride>, Error> {
+    value
+        .split(',')
+        .map(|token| token.trim())
+        .filter(|token| !token.is_empty())
+        .map(|token| {
+            let parts: Vec<&str> = token.splitn(4, ':').map(|part| part.trim()).collect();
+            match parts[..] {
+                [address, decimals, symbol, name] if !symbol.is_empty() => Ok(TokenOverride {
+                    address: parse_address(address)?,
+                    decimals: decimals
+                        .parse::<u64>()
+                        .with_context(|| format!("invalid decimals in token override {token:?}"))?,
+                    symbol: symbol.to_string(),
+                    name: name.to_string(),
+                }),
+                _ => Err(anyhow!(
+                    "invalid token override {token:?}, expected address:decimals:symbol:name"
+                )),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{ChainlinkFeed, Config, TokenOverride};
+    use std::str::FromStr;
+    use substreams::scalar::BigDecimal;
+
+    #[test]
+    fn test_empty_params_is_mainnet() {
+        assert_eq!(Config::default(), Config::from_params("").unwrap());
+        assert_eq!(
+            "0x1F98431c8aD98523631AE4a59f267346ea31F984",
+            Config::from_params("").unwrap().factory_id()
+        );
+    }
+
+    #[test]
+    fn test_params_override() {
+        let config = Config::from_params(
+            "start_block=165&wrapped_native_token=0x82aF49447D8a07e3bd95BD0d56f35241523fBab1\n\
+             stable_coins=0xFF970A61A04b1cA14834A43f5dE4533eBDDB5CC8, 0xDA10009cBd5D07dd0CeCc66161FC93D7c9000da1",
+        )
+        .unwrap();
+
+        assert_eq!(165, config.start_block);
+        assert_eq!("82af49447d8a07e3bd95bd0d56f35241523fbab1", config.wrapped_native_token);
+        assert!(config.is_stable_coin("ff970a61a04b1ca14834a43f5de4533ebddb5cc8"));
+        assert!(!config.is_stable_coin("a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"));
+        assert_eq!(Config::default().factory, config.factory);
+    }
+
+    #[test]
+    fn test_network_profile() {
+        let config = Config::from_params("start_block=42&network=base").unwrap();
+
+        assert_eq!("base", config.network);
+        assert_eq!(42, config.start_block);
+        assert_eq!("0x33128a8fC17869897dcE68Ed026d694621f6FDfD", config.factory_id());
+        assert_eq!(None, config.error_pool_address());
+        assert!(config.is_whitelisted("2ae3f1ec7f1f5012cfeab0185bfc7aa3cf0dec22"));
+    }
+
+    #[test]
+    fn test_usd_bundle_tokens() {
+        assert_eq!(3, Config::default().usd_bundle_tokens.len());
+
+        let config = Config::from_params("usd_bundle_tokens=0xA0b86991c6218b36c1d19d4a2e9eB0cE3606eB48").unwrap();
+        assert_eq!(
+            vec!["a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"],
+            config.usd_bundle_tokens
+        );
+    }
+
+    #[test]
+    fn test_native_token() {
+        let config = Config::from_params("network=polygon&minimum_native_locked=2500.5").unwrap();
+
+        assert_eq!("0d500b1d8e8ef31e21c99d1db9a6444d3adf1270", config.wrapped_native_token);
+        assert_eq!(BigDecimal::from_str("2500.5").unwrap(), config.minimum_native_locked);
+        assert!(Config::from_params("minimum_native_locked=lots").is_err());
+    }
+
+    #[test]
+    fn test_guards() {
+        let config = Config::from_params("network=bsc&max_twap_tick_deviation=500").unwrap();
+
+        assert_eq!(500, config.max_twap_tick_deviation);
+        assert_eq!(BigDecimal::from(70), config.minimum_active_native_liquidity);
+        assert!(Config::from_params("reversal_tick_tolerance=-1").is_err());
+    }
+
+    #[test]
+    fn test_twap_windows() {
+        let config = Config::from_params("twap_windows=60, 900").unwrap();
+
+        assert_eq!(vec![60, 900], config.twap_windows);
+        assert_eq!(vec![300, 1800, 3600, 86400], Config::default().twap_windows);
+        assert!(Config::from_params("twap_windows=5").is_err());
+        assert!(Config::from_params("twap_windows=1h").is_err());
+    }
+
+    #[test]
+    fn test_dynamic_whitelist() {
+        let config = Config::from_params("network=polygon&whitelist_min_blocks=100").unwrap();
+
+        assert_eq!(100, config.whitelist_min_blocks);
+        assert_eq!(BigDecimal::from(2000000), config.whitelist_eth_tvl);
+        assert!(Config::from_params("whitelist_eth_tvl=many").is_err());
+    }
+
+    #[test]
+    fn test_chainlink_feeds() {
+        let config = Config::from_params(
+            "network=arbitrum-one&chainlink_feeds=ETH/USD:0x3607e46698d218B3a5Cae44bF381475C0a5e2ca7:8\
+             &chainlink_native_usd_feed=ETH/USD&chainlink_as_bundle=true",
+        )
+        .unwrap();
+
+        assert_eq!(
+            Some(&ChainlinkFeed {
+                name: "ETH/USD".to_string(),
+                aggregator: "3607e46698d218b3a5cae44bf381475c0a5e2ca7".to_string(),
+                decimals: 8,
+            }),
+            config.chainlink_feed("3607e46698d218b3a5cae44bf381475c0a5e2ca7")
+        );
+        assert_eq!(Some("ETH/USD".to_string()), config.chainlink_native_usd_feed);
+        assert!(config.chainlink_as_bundle);
+
+        assert!(Config::from_params("chainlink_feeds=ETH/USD:0x3607e46698d218B3a5Cae44bF381475C0a5e2ca7").is_err());
+        assert!(Config::from_params("chainlink_as_bundle=yes").is_err());
+    }
+
+    #[test]
+    fn test_token_overrides() {
+        let config = Config::from_params(
+            "token_overrides=0xE0B7927c4aF23765Cb51314A0E0521A9645F0E2A:9:DGD:Digix: Gold, 0x0000000000000000000000000000000000000001:18:ONE:",
+        )
+        .unwrap();
+
+        assert_eq!(
+            Some(&TokenOverride {
+                address: "e0b7927c4af23765cb51314a0e0521a9645f0e2a".to_string(),
+                decimals: 9,
+                symbol: "DGD".to_string(),
+                name: "Digix: Gold".to_string(),
+            }),
+            config.token_override("e0b7927c4af23765cb51314a0e0521a9645f0e2a")
+        );
+        assert_eq!(2, config.token_overrides.len());
+        assert!(Config::from_params("token_overrides=0xE0B7927c4aF23765Cb51314A0E0521A9645F0E2A:9").is_err());
+    }
+
+    #[test]
+    fn test_invalid_params() {
+        assert!(Config::from_params("start_block=abc").is_err());
+        assert!(Config::from_params("factory=0x1234").is_err());
+        assert!(Config::from_params("unknown=1").is_err());
+        assert!(Config::from_params("stale_price_days=-1").is_err());
+        assert!(Config::from_params("start_block").is_err());
+        assert!(Config::from_params("network=goerli").is_err());
+    }
+}
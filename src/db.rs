@@ -1,3 +1,4 @@
+use std::collections::HashSet;
 use std::ops::Div;
 use substreams::key;
 use substreams::pb::substreams::store_delta::Operation;
@@ -10,12 +11,17 @@ use substreams::store::{
 use substreams::{log, Hex};
 use substreams_entity_change::tables::Tables;
 
-use crate::pb::uniswap::events::pool_event::Type::{Burn as BurnEvent, Mint as MintEvent, Swap as SwapEvent};
+use crate::pb::uniswap::events::pool_event::Type::{
+    Burn as BurnEvent, Collect as CollectEvent, Mint as MintEvent, Swap as SwapEvent,
+};
 use crate::pb::uniswap::events::position_event::Type;
 use crate::pb::uniswap::events::{IncreaseLiquidityPosition, PoolSqrtPrice, PositionEvent};
-use crate::pb::uniswap::{events, Events, Pool};
+use crate::pb::uniswap::{
+    events, factory_events, Events, Pool, PositionAmounts, PositionFees, PositionPnls, TokenPrice,
+};
 use crate::uniswap::{Erc20Token, Pools};
 use crate::utils::{self, pool_windows_id_fields, time_as_i64_address_as_str, token_windows_id_fields};
+use crate::whitelist::Whitelist;
 
 // -------------------
 //  Map Bundle Entities
@@ -38,13 +44,11 @@ pub fn bundle_store_eth_price_usd_bundle_entity_change(
 // -------------------
 //  Map Factory Entities
 // -------------------
-pub fn factory_created_factory_entity_change(tables: &mut Tables) {
-    let id = "0x1F98431c8aD98523631AE4a59f267346ea31F984";
-
+pub fn factory_created_factory_entity_change(tables: &mut Tables, factory_id: &str) {
     let bigint0 = BigInt::zero();
     let bigdecimal0 = BigDecimal::zero();
     tables
-        .create_row("Factory", id)
+        .create_row("Factory", factory_id)
         .set("poolCount", &bigint0)
         .set("txCount", &bigint0)
         .set("totalVolumeUSD", &bigdecimal0)
@@ -56,25 +60,35 @@ pub fn factory_created_factory_entity_change(tables: &mut Tables) {
         .set("totalValueLockedETH", &bigdecimal0)
         .set("totalValueLockedUSDUntracked", &bigdecimal0)
         .set("totalValueLockedETHUntracked", &bigdecimal0)
+        .set("collectedProtocolFeesUSD", &bigdecimal0)
         .set("owner", &format!("0x{}", Hex(utils::ZERO_ADDRESS).to_string()));
 }
 
-pub fn pool_created_factory_entity_change(tables: &mut Tables, pool_count_deltas: &Deltas<DeltaBigInt>) {
+pub fn pool_created_factory_entity_change(
+    tables: &mut Tables,
+    pool_count_deltas: &Deltas<DeltaBigInt>,
+    factory_id: &str,
+) {
     pool_count_deltas.iter().for_each(|delta| {
-        let id = "0x1F98431c8aD98523631AE4a59f267346ea31F984".to_string();
-        tables.update_row("Factory", &id).set("poolCount", &delta.new_value);
+        tables
+            .update_row("Factory", factory_id)
+            .set("poolCount", &delta.new_value);
     })
 }
 
-pub fn tx_count_factory_entity_change(tables: &mut Tables, tx_count_deltas: &Deltas<DeltaBigInt>) {
+pub fn tx_count_factory_entity_change(tables: &mut Tables, tx_count_deltas: &Deltas<DeltaBigInt>, factory_id: &str) {
     for delta in tx_count_deltas.iter().key_first_segment_eq("factory") {
         tables
-            .update_row("Factory", "0x1F98431c8aD98523631AE4a59f267346ea31F984")
+            .update_row("Factory", factory_id)
             .set("txCount", &delta.new_value);
     }
 }
 
-pub fn swap_volume_factory_entity_change(tables: &mut Tables, swaps_volume_deltas: &Deltas<DeltaBigDecimal>) {
+pub fn swap_volume_factory_entity_change(
+    tables: &mut Tables,
+    swaps_volume_deltas: &Deltas<DeltaBigDecimal>,
+    factory_id: &str,
+) {
     for delta in swaps_volume_deltas
         .iter()
         .key_first_segment_eq("factory")
@@ -84,15 +98,20 @@ pub fn swap_volume_factory_entity_change(tables: &mut Tables, swaps_volume_delta
             "totalFeesUSD",
             "totalVolumeETH",
             "totalFeesETH",
+            "collectedProtocolFeesUSD",
         ])
     {
         tables
-            .update_row("Factory", "0x1F98431c8aD98523631AE4a59f267346ea31F984")
+            .update_row("Factory", factory_id)
             .set(key::last_segment(&delta.key), &delta.new_value);
     }
 }
 
-pub fn tvl_factory_entity_change(tables: &mut Tables, derived_factory_tvl_deltas: &Deltas<DeltaBigDecimal>) {
+pub fn tvl_factory_entity_change(
+    tables: &mut Tables,
+    derived_factory_tvl_deltas: &Deltas<DeltaBigDecimal>,
+    factory_id: &str,
+) {
     for delta in derived_factory_tvl_deltas
         .iter()
         .key_first_segment_eq("factory")
@@ -104,11 +123,41 @@ pub fn tvl_factory_entity_change(tables: &mut Tables, derived_factory_tvl_deltas
         ])
     {
         tables
-            .update_row("Factory", "0x1F98431c8aD98523631AE4a59f267346ea31F984")
+            .update_row("Factory", factory_id)
             .set(key::last_segment(&delta.key), &delta.new_value);
     }
 }
 
+pub fn owner_changed_factory_entity_change(
+    tables: &mut Tables,
+    owners_changed: &Vec<factory_events::OwnerChanged>,
+    factory_id: &str,
+) {
+    for owner_changed in owners_changed {
+        tables
+            .update_row("Factory", factory_id)
+            .set("owner", &format!("0x{}", owner_changed.new_owner));
+    }
+}
+
+// -------------------
+//  Map FeeTier Entities
+// -------------------
+pub fn fee_amount_enabled_fee_tier_entity_change(
+    tables: &mut Tables,
+    fee_amounts_enabled: &Vec<factory_events::FeeAmountEnabled>,
+) {
+    for fee_amount_enabled in fee_amounts_enabled {
+        tables
+            .create_row("FeeTier", &fee_amount_enabled.fee)
+            .set_bigint("fee", &fee_amount_enabled.fee)
+            .set("tickSpacing", BigInt::from(fee_amount_enabled.tick_spacing))
+            .set("createdAtTimestamp", BigInt::from(fee_amount_enabled.timestamp))
+            .set("createdAtBlockNumber", BigInt::from(fee_amount_enabled.block_number))
+            .set("transaction", format!("0x{}", fee_amount_enabled.transaction_id));
+    }
+}
+
 // -------------------
 //  Map Pool Entities
 // -------------------
@@ -136,6 +185,8 @@ fn create_pool(tables: &mut Tables, pool: &Pool) {
         .set("token1Price", &bigdecimal0)
         .set("tick", &bigint0)
         .set("observationIndex", &bigint0)
+        .set("observationCardinality", &bigint0)
+        .set("observationCardinalityNext", &bigint0)
         .set("volumeToken0", &bigdecimal0)
         .set("volumeToken1", &bigdecimal0)
         .set("volumeUSD", &bigdecimal0)
@@ -145,8 +196,17 @@ fn create_pool(tables: &mut Tables, pool: &Pool) {
         .set("collectedFeesToken0", &bigdecimal0)
         .set("collectedFeesToken1", &bigdecimal0)
         .set("collectedFeesUSD", &bigdecimal0)
+        .set("feeProtocol0", &bigint0)
+        .set("feeProtocol1", &bigint0)
+        .set("protocolFeesToken0", &bigdecimal0)
+        .set("protocolFeesToken1", &bigdecimal0)
+        .set("collectedProtocolFeesToken0", &bigdecimal0)
+        .set("collectedProtocolFeesToken1", &bigdecimal0)
+        .set("collectedProtocolFeesUSD", &bigdecimal0)
         .set("totalValueLockedToken0", &bigdecimal0)
         .set("totalValueLockedToken1", &bigdecimal0)
+        .set("balanceToken0", &bigdecimal0)
+        .set("balanceToken1", &bigdecimal0)
         .set("totalValueLockedETH", &bigdecimal0)
         .set("totalValueLockedUSD", &bigdecimal0)
         .set("totalValueLockedUSDUntracked", &bigdecimal0)
@@ -171,6 +231,7 @@ fn create_pool_windows_entity(
         .set("tick", BigInt::zero())
         .set("feeGrowthGlobal0X128", BigInt::zero())
         .set("feeGrowthGlobal1X128", BigInt::zero())
+        .set("observationIndex", BigInt::zero())
         .set("totalValueLockedUSD", BigDecimal::zero())
         .set("volumeToken0", BigDecimal::zero())
         .set("volumeToken1", BigDecimal::zero())
@@ -262,6 +323,20 @@ pub fn total_value_locked_by_token_pool_entity_change(tables: &mut Tables, token
     }
 }
 
+pub fn balances_pool_entity_change(tables: &mut Tables, pool_balances_deltas: &Deltas<DeltaBigDecimal>) {
+    for delta in pool_balances_deltas.iter().key_first_segment_eq("pool") {
+        let pool_address = key::segment_at(&delta.key, 1);
+        let field_name = match key::last_segment(&delta.key) {
+            "token0" => "balanceToken0",
+            "token1" => "balanceToken1",
+            _ => continue,
+        };
+        tables
+            .update_row("Pool", &format!("0x{pool_address}"))
+            .set(field_name, &delta.new_value);
+    }
+}
+
 pub fn price_pool_entity_change(tables: &mut Tables, price_deltas: &Deltas<DeltaBigDecimal>) {
     for delta in price_deltas.iter().key_first_segment_eq("pool") {
         let pool_address = key::segment_at(&delta.key, 1);
@@ -297,6 +372,12 @@ pub fn swap_volume_pool_entity_change(tables: &mut Tables, swaps_volume_deltas:
             "volumeUSD" => "volumeUSD",
             "volumeUntrackedUSD" => "untrackedVolumeUSD",
             "feesUSD" => "feesUSD",
+            "collectedFeesToken0" => "collectedFeesToken0",
+            "collectedFeesToken1" => "collectedFeesToken1",
+            "collectedFeesUSD" => "collectedFeesUSD",
+            "collectedProtocolFeesToken0" => "collectedProtocolFeesToken0",
+            "collectedProtocolFeesToken1" => "collectedProtocolFeesToken1",
+            "collectedProtocolFeesUSD" => "collectedProtocolFeesUSD",
             "liquidityProviderCount" => "liquidityProviderCount",
             _ => continue,
         };
@@ -314,6 +395,37 @@ pub fn swap_volume_pool_entity_change(tables: &mut Tables, swaps_volume_deltas:
     }
 }
 
+pub fn protocol_fees_pool_entity_change(tables: &mut Tables, pool_protocol_fees_deltas: &Deltas<DeltaBigDecimal>) {
+    for delta in pool_protocol_fees_deltas.iter().key_first_segment_eq("pool") {
+        let pool_address = key::segment_at(&delta.key, 1);
+        let row = tables.update_row("Pool", &format!("0x{pool_address}"));
+        match key::last_segment(&delta.key) {
+            "feeProtocol0" => row.set("feeProtocol0", &delta.new_value.to_bigint()),
+            "feeProtocol1" => row.set("feeProtocol1", &delta.new_value.to_bigint()),
+            "protocolFeesToken0" => row.set("protocolFeesToken0", &delta.new_value),
+            "protocolFeesToken1" => row.set("protocolFeesToken1", &delta.new_value),
+            _ => continue,
+        };
+    }
+}
+
+pub fn oracle_pool_entity_change(tables: &mut Tables, pool_oracle_deltas: &Deltas<DeltaBigInt>) {
+    for delta in pool_oracle_deltas
+        .iter()
+        .key_first_segment_eq("pool")
+        .key_last_segment_in([
+            "observationIndex",
+            "observationCardinality",
+            "observationCardinalityNext",
+        ])
+    {
+        let pool_address = key::segment_at(&delta.key, 1);
+        tables
+            .update_row("Pool", &format!("0x{pool_address}"))
+            .set(key::last_segment(&delta.key), &delta.new_value);
+    }
+}
+
 // --------------------
 //  Map Token Entities
 // --------------------
@@ -370,7 +482,14 @@ fn add_token_entity_change(tables: &mut Tables, token: &Erc20Token) {
         .set("totalValueLockedUSD", &bigdecimal0)
         .set("totalValueLockedUSDUntracked", &bigdecimal0)
         .set("derivedETH", &bigdecimal0)
-        .set("whitelistPools", &whitelist);
+        .set("whitelistPools", &whitelist)
+        .set("metadataStatus", token.metadata_status().as_str_name())
+        .set("metadataFallbackReason", &token.metadata_fallback_reason)
+        .set("pricePath", Vec::<String>::new())
+        .set("priceETHLocked", &bigdecimal0)
+        .set("priceLastUpdatedBlock", &bigint0)
+        .set("priceConfidence", &bigdecimal0)
+        .set("priceStale", false);
 }
 
 fn create_token_windows_entity(
@@ -480,10 +599,60 @@ pub fn derived_eth_prices_token_entity_change(
     }
 }
 
+pub fn price_provenance_token_entity_change(tables: &mut Tables, token_prices_deltas: &Deltas<DeltaProto<TokenPrice>>) {
+    for delta in token_prices_deltas.iter().operation_not_eq(Operation::Delete) {
+        let token_price = &delta.new_value;
+        let path: Vec<_> = token_price.path.iter().map(|token| format!("0x{token}")).collect();
+
+        let row = tables
+            .update_row("Token", format!("0x{}", token_price.token_address))
+            .set("pricePath", &path)
+            .set("priceETHLocked", BigDecimal::try_from(&token_price.eth_locked).unwrap())
+            .set("priceLastUpdatedBlock", BigInt::from(token_price.block_number))
+            .set(
+                "priceConfidence",
+                BigDecimal::try_from(&token_price.confidence).unwrap(),
+            )
+            .set("priceStale", false);
+        if !token_price.pool_address.is_empty() {
+            row.set("priceSourcePool", format!("0x{}", token_price.pool_address));
+        }
+    }
+}
+
+// The `priceUpdated` keys of `store_eth_prices` deleted once they are more than `stale_price_days` old
+// are the tokens without a price update since then
+pub fn stale_price_token_entity_change(
+    tables: &mut Tables,
+    timestamp: i64,
+    stale_price_days: i64,
+    derived_eth_prices_deltas: &Deltas<DeltaBigDecimal>,
+) {
+    let stale_day_id = timestamp / 86400 - stale_price_days - 1;
+    for delta in derived_eth_prices_deltas
+        .iter()
+        .key_first_segment_eq("priceUpdated")
+        .operation_eq(Operation::Delete)
+    {
+        let day_id: i64 = key::segment_at(&delta.key, 1).parse().unwrap();
+        if day_id > stale_day_id {
+            continue;
+        }
+
+        let token_address = key::segment_at(&delta.key, 2);
+        tables
+            .update_row("Token", format!("0x{token_address}"))
+            .set("priceStale", true);
+    }
+}
+
 pub fn whitelist_token_entity_change(tables: &mut Tables, tokens_whitelist_pools_deltas: Deltas<DeltaArray<String>>) {
     for delta in tokens_whitelist_pools_deltas.into_iter() {
         let token_address = key::segment_at(&delta.key, 1);
-        let whitelist: Vec<_> = delta.new_value.into_iter().map(|item| format!("0x{}", item)).collect();
+        let whitelist: Vec<_> = utils::resolve_appended(delta.new_value.iter().map(String::as_str))
+            .into_iter()
+            .map(|item| format!("0x{}", item))
+            .collect();
 
         tables
             .update_row("Token", format!("0x{token_address}"))
@@ -491,6 +660,16 @@ pub fn whitelist_token_entity_change(tables: &mut Tables, tokens_whitelist_pools
     }
 }
 
+pub fn total_supply_token_entity_change(tables: &mut Tables, token_supply_deltas: &Deltas<DeltaBigInt>) {
+    for delta in token_supply_deltas.iter() {
+        let token_address = key::segment_at(&delta.key, 1);
+
+        tables
+            .update_row("Token", format!("0x{token_address}"))
+            .set("totalSupply", &delta.new_value);
+    }
+}
+
 // --------------------
 //  Map Tick Entities
 // --------------------
@@ -682,6 +861,20 @@ pub fn position_create_entity_change(tables: &mut Tables, positions: &Vec<events
             .set("withdrawnToken1", &bigdecimal0)
             .set("collectedFeesToken0", &bigdecimal0)
             .set("collectedFeesToken1", &bigdecimal0)
+            .set("amount0", &bigdecimal0)
+            .set("amount1", &bigdecimal0)
+            .set("amountUSD", &bigdecimal0)
+            .set("unclaimedFeesToken0", &bigdecimal0)
+            .set("unclaimedFeesToken1", &bigdecimal0)
+            .set("unclaimedFeesUSD", &bigdecimal0)
+            .set("costBasisUSD", &bigdecimal0)
+            .set("feesEarnedToken0", &bigdecimal0)
+            .set("feesEarnedToken1", &bigdecimal0)
+            .set("feesEarnedUSD", &bigdecimal0)
+            .set("hodlValueUSD", &bigdecimal0)
+            .set("impermanentLossUSD", &bigdecimal0)
+            .set("realisedPnlUSD", &bigdecimal0)
+            .set("closed", false)
             .set("transaction", format!("0x{}", position.transaction))
             .set_bigint(
                 "feeGrowthInside0LastX128",
@@ -743,6 +936,41 @@ pub fn decrease_liquidity_position_entity_change(
     }
 }
 
+pub fn amounts_position_entity_change(tables: &mut Tables, position_amounts: &PositionAmounts) {
+    for position_amount in &position_amounts.position_amounts {
+        tables
+            .update_row("Position", &position_amount.token_id)
+            .set_bigdecimal("amount0", &position_amount.amount0)
+            .set_bigdecimal("amount1", &position_amount.amount1)
+            .set_bigdecimal("amountUSD", &position_amount.amount_usd);
+    }
+}
+
+pub fn unclaimed_fees_position_entity_change(tables: &mut Tables, position_fees: &PositionFees) {
+    for position_fee in &position_fees.position_fees {
+        tables
+            .update_row("Position", &position_fee.token_id)
+            .set_bigdecimal("unclaimedFeesToken0", &position_fee.unclaimed_fees_token0)
+            .set_bigdecimal("unclaimedFeesToken1", &position_fee.unclaimed_fees_token1)
+            .set_bigdecimal("unclaimedFeesUSD", &position_fee.unclaimed_fees_usd);
+    }
+}
+
+pub fn pnl_position_entity_change(tables: &mut Tables, position_pnls: &PositionPnls) {
+    for position_pnl in &position_pnls.position_pnls {
+        tables
+            .update_row("Position", &position_pnl.token_id)
+            .set_bigdecimal("costBasisUSD", &position_pnl.cost_basis_usd)
+            .set_bigdecimal("feesEarnedToken0", &position_pnl.fees_earned_token0)
+            .set_bigdecimal("feesEarnedToken1", &position_pnl.fees_earned_token1)
+            .set_bigdecimal("feesEarnedUSD", &position_pnl.fees_earned_usd)
+            .set_bigdecimal("hodlValueUSD", &position_pnl.hodl_value_usd)
+            .set_bigdecimal("impermanentLossUSD", &position_pnl.impermanent_loss_usd)
+            .set_bigdecimal("realisedPnlUSD", &position_pnl.realised_pnl_usd)
+            .set("closed", position_pnl.closed);
+    }
+}
+
 pub fn collect_position_entity_change(tables: &mut Tables, positions: &Vec<events::CollectPosition>) {
     for position in positions {
         let token_id = position.token_id.clone();
@@ -800,6 +1028,15 @@ fn create_snapshot_position(tables: &mut Tables, id: &String, position: &events:
         .set_bigdecimal("withdrawnToken1", &"0".to_string())
         .set_bigdecimal("collectedFeesToken0", &"0".to_string())
         .set_bigdecimal("collectedFeesToken1", &"0".to_string())
+        .set_bigdecimal("costBasisUSD", &"0".to_string())
+        .set_bigdecimal("valueUSD", &"0".to_string())
+        .set_bigdecimal("feesEarnedToken0", &"0".to_string())
+        .set_bigdecimal("feesEarnedToken1", &"0".to_string())
+        .set_bigdecimal("feesEarnedUSD", &"0".to_string())
+        .set_bigdecimal("hodlValueUSD", &"0".to_string())
+        .set_bigdecimal("impermanentLossUSD", &"0".to_string())
+        .set_bigdecimal("realisedPnlUSD", &"0".to_string())
+        .set("closed", false)
         .set("transaction", &format!("0x{}", &position.transaction))
         .set_bigint(
             "feeGrowthInside0LastX128",
@@ -932,6 +1169,54 @@ fn transfer_snapshot_position(tables: &mut Tables, id: &String, position: &event
         .set("owner", &hex::decode(&position.owner).unwrap());
 }
 
+// Only the positions updated or transferred in the block have a snapshot
+pub fn pnl_snapshot_position_entity_change(
+    tables: &mut Tables,
+    block_number: u64,
+    position_pnls: &PositionPnls,
+    events: &Events,
+) {
+    let snapshot_token_ids: HashSet<&String> = events
+        .created_positions
+        .iter()
+        .map(|position| &position.token_id)
+        .chain(
+            events
+                .increase_liquidity_positions
+                .iter()
+                .map(|position| &position.token_id),
+        )
+        .chain(
+            events
+                .decrease_liquidity_positions
+                .iter()
+                .map(|position| &position.token_id),
+        )
+        .chain(events.collect_positions.iter().map(|position| &position.token_id))
+        .chain(events.transfer_positions.iter().map(|position| &position.token_id))
+        .collect();
+
+    for position_pnl in &position_pnls.position_pnls {
+        if !snapshot_token_ids.contains(&position_pnl.token_id) {
+            continue;
+        }
+        tables
+            .update_row(
+                "PositionSnapshot",
+                format!("{}#{}", position_pnl.token_id, block_number),
+            )
+            .set_bigdecimal("costBasisUSD", &position_pnl.cost_basis_usd)
+            .set_bigdecimal("valueUSD", &position_pnl.value_usd)
+            .set_bigdecimal("feesEarnedToken0", &position_pnl.fees_earned_token0)
+            .set_bigdecimal("feesEarnedToken1", &position_pnl.fees_earned_token1)
+            .set_bigdecimal("feesEarnedUSD", &position_pnl.fees_earned_usd)
+            .set_bigdecimal("hodlValueUSD", &position_pnl.hodl_value_usd)
+            .set_bigdecimal("impermanentLossUSD", &position_pnl.impermanent_loss_usd)
+            .set_bigdecimal("realisedPnlUSD", &position_pnl.realised_pnl_usd)
+            .set("closed", position_pnl.closed);
+    }
+}
+
 fn fetch_and_update_snapshot_position(
     tables: &mut Tables,
     token_id: &String,
@@ -999,8 +1284,9 @@ pub fn transaction_entity_change(tables: &mut Tables, transactions: &Vec<events:
 pub fn swaps_mints_burns_created_entity_change(
     tables: &mut Tables,
     pool_events: &Vec<events::PoolEvent>,
-    tx_count_store: StoreGetBigInt,
-    store_eth_prices: StoreGetBigDecimal,
+    tx_count_store: &StoreGetBigInt,
+    store_eth_prices: &StoreGetBigDecimal,
+    whitelist: &Whitelist,
 ) {
     for pool_event in pool_events {
         if pool_event.r#type.is_none() {
@@ -1048,6 +1334,7 @@ pub fn swaps_mints_burns_created_entity_change(
                         &amount0_abs,
                         &amount1_abs,
                         &bundle_eth_price, // get the value from the store_eth_price
+                        whitelist,
                     )
                     .div(BigDecimal::from(2 as i32));
 
@@ -1066,6 +1353,7 @@ pub fn swaps_mints_burns_created_entity_change(
                         .set("amountUSD", &amount_total_usd_tracked)
                         .set("sqrtPriceX96", &BigInt::try_from(swap.sqrt_price.to_string()).unwrap())
                         .set("tick", &BigInt::try_from(swap.tick.to_string()).unwrap())
+                        .set("ticksCrossed", swap.ticks_crossed)
                         .set("logIndex", pool_event.log_index);
                 }
                 MintEvent(mint) => {
@@ -1126,6 +1414,33 @@ pub fn swaps_mints_burns_created_entity_change(
                         .set_bigint("tickUpper", &burn.tick_upper)
                         .set("logIndex", pool_event.log_index);
                 }
+                CollectEvent(collect) => {
+                    let amount0: BigDecimal = BigDecimal::try_from(collect.amount_0.as_str()).unwrap();
+                    let amount1: BigDecimal = BigDecimal::try_from(collect.amount_1.as_str()).unwrap();
+
+                    let amount_usd: BigDecimal = utils::get_tracked_amount_usd(
+                        &pool_event.token0,
+                        &pool_event.token1,
+                        &token0_derived_eth_price,
+                        &token1_derived_eth_price,
+                        &amount0,
+                        &amount1,
+                        &bundle_eth_price,
+                        whitelist,
+                    );
+                    tables
+                        .create_row("Collect", &event_primary_key)
+                        .set("transaction", format!("0x{transaction_id}"))
+                        .set("timestamp", pool_event.timestamp)
+                        .set("pool", format!("0x{pool_address}"))
+                        .set("owner", &hex::decode(&collect.owner).unwrap())
+                        .set("amount0", amount0)
+                        .set("amount1", amount1)
+                        .set("amountUSD", amount_usd)
+                        .set_bigint("tickLower", &collect.tick_lower)
+                        .set_bigint("tickUpper", &collect.tick_upper)
+                        .set("logIndex", pool_event.log_index);
+                }
             };
         }
     }
@@ -1134,21 +1449,57 @@ pub fn swaps_mints_burns_created_entity_change(
 // --------------------
 //  Map Flashes Entities
 // --------------------
-// pub fn flashes_update_pool_fee_entity_change(tables: &mut Tables, flashes: Vec<events::Flash>) {
-//     // TODO: wut? flash updates would affect `fee_growth_global_0x_128` and `fee_growth_global_1x_128`?
-//     //  it's the business of TickUpdate, not of Flashes. Don't flashes produce some such updates?
-//     for flash in flashes {
-//         // tables.update_row("Pool", flash.pool_address.as_str());
-//         // .set(
-//         //     "feeGrowthGlobal0X128",
-//         //     BigInt::from(flash.fee_growth_global_0x_128.unwrap()),
-//         // )
-//         // .set(
-//         //     "feeGrowthGlobal1X128",
-//         //     BigInt::from(flash.fee_growth_global_1x_128.unwrap()),
-//         // );
-//     }
-// }
+pub fn flashes_created_entity_change(
+    tables: &mut Tables,
+    flashes: &Vec<events::Flash>,
+    store_eth_prices: &StoreGetBigDecimal,
+) {
+    for flash in flashes {
+        let ord = flash.log_ordinal;
+        let pool_address = &flash.pool_address;
+        let transaction_id = &flash.transaction_id;
+
+        let token0_addr = &flash.token0;
+        let token1_addr = &flash.token1;
+        let token0_derived_eth_price = store_eth_prices
+            .get_at(ord, format!("token:{token0_addr}:dprice:eth"))
+            .unwrap_or_default();
+        let token1_derived_eth_price = store_eth_prices
+            .get_at(ord, format!("token:{token1_addr}:dprice:eth"))
+            .unwrap_or_default();
+        let bundle_eth_price = store_eth_prices.get_at(ord, "bundle").unwrap_or_default();
+
+        let amount0 = BigDecimal::try_from(flash.amount_0.as_str()).unwrap();
+        let amount1 = BigDecimal::try_from(flash.amount_1.as_str()).unwrap();
+
+        let amount_usd = utils::calculate_amount_usd(
+            &amount0,
+            &amount1,
+            &token0_derived_eth_price,
+            &token1_derived_eth_price,
+            &bundle_eth_price,
+        );
+
+        tables
+            .create_row("Flash", flash_id(flash))
+            .set("transaction", format!("0x{transaction_id}"))
+            .set("timestamp", flash.timestamp)
+            .set("pool", format!("0x{pool_address}"))
+            .set("sender", &hex::decode(&flash.sender).unwrap())
+            .set("recipient", &hex::decode(&flash.recipient).unwrap())
+            .set("amount0", amount0)
+            .set("amount1", amount1)
+            .set("amountUSD", amount_usd)
+            .set_bigdecimal("amount0Paid", &flash.paid_0)
+            .set_bigdecimal("amount1Paid", &flash.paid_1)
+            .set("logIndex", flash.log_index);
+    }
+}
+
+// Flashes don't count as transactions of their pool: they are keyed by their log index in the transaction
+fn flash_id(flash: &events::Flash) -> String {
+    format!("0x{}#{}", flash.transaction_id, flash.log_index)
+}
 
 // --------------------
 //  Map Uniswap Day Data Entities
@@ -1284,6 +1635,7 @@ pub fn pool_windows_update(
     derived_tvl_deltas: &Deltas<DeltaBigDecimal>,
     min_windows_deltas: &Deltas<DeltaBigDecimal>,
     max_windows_deltas: &Deltas<DeltaBigDecimal>,
+    pool_oracle_deltas: &Deltas<DeltaBigInt>,
 ) {
     tx_count_pool_windows(&mut tables, &tx_count_deltas);
     mint_burn_prices_pool_windows(&mut tables, timestamp, &events.pool_events, &store_prices);
@@ -1296,6 +1648,7 @@ pub fn pool_windows_update(
     swap_volume_pool_windows(&mut tables, &swaps_volume_deltas);
     fee_growth_global_x128_pool_windows(&mut tables, timestamp, &events.fee_growth_global_updates);
     total_value_locked_usd_pool_windows(&mut tables, &derived_tvl_deltas);
+    observation_index_pool_windows(tables, pool_oracle_deltas);
 }
 
 pub fn tx_count_pool_windows(tables: &mut Tables, tx_count_deltas: &Deltas<DeltaBigInt>) {
@@ -1357,6 +1710,9 @@ pub fn mint_burn_prices_pool_windows(
                 events::pool_event::Type::Swap(_) => {
                     continue; // the swap event will be taken care of by the prices_pool_windows
                 }
+                events::pool_event::Type::Collect(_) => {
+                    continue; // a collect does not move the pool price
+                }
                 _ => {}
             }
 
@@ -1557,6 +1913,22 @@ pub fn fee_growth_global_x128_pool_windows(
     }
 }
 
+pub fn observation_index_pool_windows(tables: &mut Tables, pool_oracle_deltas: &Deltas<DeltaBigInt>) {
+    for delta in pool_oracle_deltas
+        .iter()
+        .key_first_segment_in(["PoolDayData", "PoolHourData"])
+        .key_last_segment_eq("observationIndex")
+    {
+        let table_name = key::segment_at(&delta.key, 0);
+        let time_id = key::segment_at(&delta.key, 1);
+        let pool_address = key::segment_at(&delta.key, 2);
+
+        tables
+            .update_row(table_name, format!("0x{pool_address}-{time_id}"))
+            .set("observationIndex", &delta.new_value);
+    }
+}
+
 pub fn total_value_locked_usd_pool_windows(tables: &mut Tables, derived_tvl_deltas: &Deltas<DeltaBigDecimal>) {
     for delta in derived_tvl_deltas
         .iter()
@@ -1753,3 +2125,23 @@ pub fn prices_close_token_windows(tables: &mut Tables, eth_prices_deltas: &Delta
             .set("close", &delta.old_value);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::db::flash_id;
+    use crate::pb::uniswap::events::Flash;
+
+    #[test]
+    fn test_flash_id() {
+        let flash = |log_index: u64| Flash {
+            pool_address: "8ad599c3a0ff1de082011efddc58f1908eb6e6d8".to_string(),
+            transaction_id: "a1b2".to_string(),
+            log_index,
+            ..Default::default()
+        };
+
+        // two flashes on the same pool in the same transaction
+        assert_eq!("0xa1b2#3", flash_id(&flash(3)));
+        assert_ne!(flash_id(&flash(3)), flash_id(&flash(7)));
+    }
+}
@@ -15,15 +15,38 @@ impl Display for DecodeError {
 
 impl Error for DecodeError {}
 
-pub fn read_string_from_bytes(input: &[u8]) -> String {
-    // we have to check if we have a valid utf8 representation and if we do
-    // we return the value if not we return a DecodeError
-    if let Some(last) = input.to_vec().iter().rev().position(|&pos| pos != 0) {
-        return String::from_utf8_lossy(&input[0..input.len() - last]).to_string();
+// Longest names and symbols kept, in characters
+pub const MAX_NAME_LENGTH: usize = 64;
+pub const MAX_SYMBOL_LENGTH: usize = 32;
+
+// Name or symbol returned as a `bytes32`, padded with zeros on the right
+pub fn decode_bytes32_string(input: &[u8; 32]) -> Result<String, DecodeError> {
+    let length = input.len() - input.iter().rev().take_while(|&&byte| byte == 0).count();
+    if length == 0 {
+        return Err(DecodeError {
+            msg: "empty bytes32".to_string(),
+        });
     }
 
-    // use case when all the bytes are set to 0
-    "".to_string()
+    String::from_utf8(input[0..length].to_vec()).map_err(|_| DecodeError {
+        msg: "invalid utf-8 in bytes32".to_string(),
+    })
+}
+
+// Strips the control characters and the replacement characters left by a lossy utf-8 decoding, trims the
+// whitespaces and caps the length at `max_length` characters. The boolean tells if the value was changed.
+pub fn sanitize_string(value: &str, max_length: usize) -> (String, bool) {
+    let sanitized: String = value
+        .chars()
+        .filter(|c| !c.is_control() && *c != char::REPLACEMENT_CHARACTER)
+        .collect::<String>()
+        .trim()
+        .chars()
+        .take(max_length)
+        .collect();
+    let changed = sanitized != value;
+
+    (sanitized, changed)
 }
 
 #[cfg(test)]
@@ -37,7 +60,7 @@ mod tests {
             77, 97, 107, 101, 114, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
         ];
 
-        assert_eq!(expected_name, read_string_from_bytes(name_bytes));
+        assert_eq!(Ok(expected_name.to_string()), decode_bytes32_string(name_bytes));
     }
 
     #[test]
@@ -47,7 +70,7 @@ mod tests {
             77, 75, 82, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
         ];
 
-        assert_eq!(expected_name, read_string_from_bytes(name_bytes));
+        assert_eq!(Ok(expected_name.to_string()), decode_bytes32_string(name_bytes));
     }
 
     #[test]
@@ -56,23 +79,36 @@ mod tests {
             0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
         ];
 
-        assert_eq!("".to_string(), read_string_from_bytes(bytes));
+        assert!(decode_bytes32_string(bytes).is_err());
     }
 
     #[test]
-    fn test_read_string_from_bytes64_all_zeros() {
-        let bytes: &[u8; 64] = &[
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-        ];
-
-        assert_eq!("".to_string(), read_string_from_bytes(bytes));
+    fn test_decode_bytes32_string() {
+        let mut bytes = [0u8; 32];
+        bytes[0..3].copy_from_slice(b"MKR");
+        assert_eq!(Ok("MKR".to_string()), decode_bytes32_string(&bytes));
+
+        bytes[1] = 0xff;
+        assert_eq!(
+            Err(DecodeError {
+                msg: "invalid utf-8 in bytes32".to_string()
+            }),
+            decode_bytes32_string(&bytes)
+        );
     }
 
     #[test]
-    fn test_read_string_from_bytes32_empty_bytes() {
-        let bytes: &[u8; 0] = &[];
-
-        assert_eq!("".to_string(), read_string_from_bytes(bytes));
+    fn test_sanitize_string() {
+        assert_eq!(("Maker".to_string(), false), sanitize_string("Maker", MAX_NAME_LENGTH));
+        assert_eq!(
+            ("Maker".to_string(), true),
+            sanitize_string(" Ma\u{0}ker\n", MAX_NAME_LENGTH)
+        );
+        assert_eq!(
+            ("MKR".to_string(), true),
+            sanitize_string("M\u{fffd}KR", MAX_SYMBOL_LENGTH)
+        );
+        assert_eq!(("ABC".to_string(), true), sanitize_string("ABCDEF", 3));
+        assert_eq!(("".to_string(), false), sanitize_string("", MAX_SYMBOL_LENGTH));
     }
 }
@@ -1,13 +1,28 @@
+use crate::config::Config;
 use crate::math::compute_price_from_tick_idx;
 use crate::pb::uniswap::events;
 use crate::storage::position_manager::PositionManagerStorage;
 use crate::storage::uniswap_v3_pool::UniswapPoolStorage;
-use crate::utils::NON_FUNGIBLE_POSITION_MANAGER;
-use crate::{abi, math, utils, BurnEvent, EventTrait, MintEvent, Pool, SwapEvent};
+use crate::{abi, math, utils, BurnEvent, CollectEvent, EventTrait, MintEvent, Pool, SwapEvent};
 use substreams::prelude::{BigDecimal, BigInt};
 use substreams::{log, Hex};
 use substreams_ethereum::block_view::CallView;
-use substreams_ethereum::pb::eth::v2::{Call, Log, StorageChange, TransactionTrace};
+use substreams_ethereum::pb::eth::v2::{Block, Call, Log, StorageChange, TransactionTrace};
+
+// Addresses of the tokens of the pools created by the factory in the block, with the ordinal of their
+// `PoolCreated` event. The `error_pool` is left out like in `map_pools_created`.
+pub fn extract_pool_created_tokens(block: &Block, config: &Config) -> Vec<(u64, String)> {
+    let error_pool = config.error_pool_address();
+    let mut tokens = vec![];
+    for (event, log) in block.events::<abi::factory::events::PoolCreated>(&[&config.factory_address()]) {
+        if Some(&event.pool) == error_pool.as_ref() {
+            continue;
+        }
+        tokens.push((log.ordinal(), Hex(&event.token0).to_string()));
+        tokens.push((log.ordinal(), Hex(&event.token1).to_string()));
+    }
+    tokens
+}
 
 pub fn extract_pool_events_and_positions(
     pool_events: &mut Vec<events::PoolEvent>,
@@ -25,6 +40,7 @@ pub fn extract_pool_events_and_positions(
     pool: &Pool,
     timestamp_seconds: u64,
     block_number: u64,
+    position_manager: &[u8],
 ) {
     let common_tick_updated = events::TickUpdated {
         log_ordinal: log.ordinal,
@@ -47,6 +63,14 @@ pub fn extract_pool_events_and_positions(
         let amount0 = swap.amount0.to_decimal(token0.decimals);
         let amount1 = swap.amount1.to_decimal(token1.decimals);
 
+        let storage = UniswapPoolStorage::new(&call_view.call.storage_changes, &log.address);
+        let crossed_ticks = match storage.slot0().tick() {
+            Some((tick_from, tick_to)) => {
+                storage.crossed_ticks(tick_from.to_i32(), tick_to.to_i32(), pool.tick_spacing)
+            }
+            None => vec![],
+        };
+
         pool_events.push(events::PoolEvent {
             log_ordinal: log.ordinal,
             log_index: log.block_index as u64,
@@ -66,9 +90,20 @@ pub fn extract_pool_events_and_positions(
                 sqrt_price: swap.sqrt_price_x96.into(),
                 liquidity: swap.liquidity.into(),
                 tick: swap.tick.into(),
+                ticks_crossed: crossed_ticks.len() as u64,
             })),
         });
 
+        for tick_idx in crossed_ticks {
+            let tick = storage.ticks(&BigInt::from(tick_idx));
+            ticks_updated.push(events::TickUpdated {
+                idx: tick_idx.to_string(),
+                fee_growth_outside_0x_128: bigint_if_some(tick.fee_growth_outside_0_x128()),
+                fee_growth_outside_1x_128: bigint_if_some(tick.fee_growth_outside_1_x128()),
+                ..common_tick_updated.clone()
+            });
+        }
+
         //TODO: verify if a swap changes the fee growth inside 0x128 and 1x128
         if let Some(position_manager_contract_call) = call_view.parent() {
             extract_positions(
@@ -78,6 +113,7 @@ pub fn extract_pool_events_and_positions(
                 collect_positions,
                 transfer_positions,
                 &position_manager_contract_call,
+                position_manager,
             );
         }
     } else if let Some(mint) = abi::pool::events::Mint::match_and_decode(log) {
@@ -152,7 +188,7 @@ pub fn extract_pool_events_and_positions(
         });
 
         if let Some(position_manager_contract_call) = call_view.parent() {
-            if position_manager_contract_call.address != NON_FUNGIBLE_POSITION_MANAGER {
+            if position_manager_contract_call.address != position_manager {
                 return;
             }
 
@@ -204,6 +240,7 @@ pub fn extract_pool_events_and_positions(
                 collect_positions,
                 transfer_positions,
                 &position_manager_contract_call,
+                position_manager,
             );
         }
     } else if let Some(burn) = abi::pool::events::Burn::match_and_decode(log) {
@@ -264,9 +301,40 @@ pub fn extract_pool_events_and_positions(
                 collect_positions,
                 transfer_positions,
                 &position_manager_contract_call,
+                position_manager,
             );
         }
-    } else if abi::pool::events::Collect::match_log(log) {
+    } else if let Some(collect) = abi::pool::events::Collect::match_and_decode(log) {
+        log::info!("COLLECT: transaction: {}", transaction_id.to_string());
+        if !pool.should_handle_mint_and_burn() {
+            return;
+        }
+
+        let token0 = pool.token0.as_ref().unwrap();
+        let token1 = pool.token1.as_ref().unwrap();
+        let amount0 = collect.amount0.to_decimal(token0.decimals);
+        let amount1 = collect.amount1.to_decimal(token1.decimals);
+
+        pool_events.push(events::PoolEvent {
+            log_ordinal: log.ordinal,
+            log_index: log.block_index as u64,
+            pool_address: pool.address.to_string(),
+            token0: token0.address.clone(),
+            token1: token1.address.clone(),
+            fee: pool.fee_tier.clone(),
+            transaction_id: transaction_id.to_string(),
+            timestamp: timestamp_seconds,
+            created_at_block_number: block_number,
+            r#type: Some(CollectEvent(events::pool_event::Collect {
+                owner: Hex(&collect.owner).to_string(),
+                recipient: Hex(&collect.recipient).to_string(),
+                amount_0: amount0.into(),
+                amount_1: amount1.into(),
+                tick_lower: collect.tick_lower.as_ref().into(),
+                tick_upper: collect.tick_upper.as_ref().into(),
+            })),
+        });
+
         if let Some(position_manager_contract_call) = call_view.parent() {
             extract_positions(
                 pool,
@@ -275,6 +343,7 @@ pub fn extract_pool_events_and_positions(
                 collect_positions,
                 transfer_positions,
                 &position_manager_contract_call,
+                position_manager,
             );
         };
     }
@@ -380,7 +449,8 @@ pub fn extract_fee_growth_update(
         }
         do_extract = true;
     } else if abi::pool::events::Flash::match_log(&log) {
-        do_extract = false; //TODO: handle this later when we process flashes
+        // flash fees paid to liquidity providers are folded into the fee growth globals
+        do_extract = true;
     }
     if do_extract {
         fee_growth_updates.append(&mut utils::extract_pool_fee_growth_global_updates(
@@ -391,7 +461,35 @@ pub fn extract_fee_growth_update(
     }
 }
 
-pub fn extract_pool_sqrt_prices(pool_sqrt_prices: &mut Vec<events::PoolSqrtPrice>, log: &Log, pool_address: &String) {
+// Fee growths outside of the ticks from their `TickUpdated`, the values left empty are unchanged
+pub fn extract_fee_growth_outside_updates(ticks_updated: &Vec<events::TickUpdated>) -> Vec<events::FeeGrowthOutside> {
+    let mut fee_growth_outside_updates = vec![];
+    for tick in ticks_updated {
+        for (token_idx, new_value) in [
+            (0, &tick.fee_growth_outside_0x_128),
+            (1, &tick.fee_growth_outside_1x_128),
+        ] {
+            if new_value.is_empty() {
+                continue;
+            }
+            fee_growth_outside_updates.push(events::FeeGrowthOutside {
+                pool_address: tick.pool_address.clone(),
+                tick_idx: tick.idx.parse::<i32>().unwrap(),
+                ordinal: tick.log_ordinal,
+                new_value: new_value.clone(),
+                token_idx,
+            });
+        }
+    }
+    fee_growth_outside_updates
+}
+
+pub fn extract_pool_sqrt_prices(
+    pool_sqrt_prices: &mut Vec<events::PoolSqrtPrice>,
+    log: &Log,
+    pool_address: &String,
+    transaction_id: &String,
+) {
     if let Some(event) = abi::pool::events::Initialize::match_and_decode(log) {
         pool_sqrt_prices.push(events::PoolSqrtPrice {
             pool_address: pool_address.to_string(),
@@ -399,6 +497,7 @@ pub fn extract_pool_sqrt_prices(pool_sqrt_prices: &mut Vec<events::PoolSqrtPrice
             sqrt_price: event.sqrt_price_x96.into(),
             tick: event.tick.into(),
             initialized: true,
+            transaction_id: transaction_id.to_string(),
         });
     } else if let Some(event) = abi::pool::events::Swap::match_and_decode(log) {
         pool_sqrt_prices.push(events::PoolSqrtPrice {
@@ -407,6 +506,7 @@ pub fn extract_pool_sqrt_prices(pool_sqrt_prices: &mut Vec<events::PoolSqrtPrice
             sqrt_price: event.sqrt_price_x96.to_string(),
             tick: event.tick.to_string(),
             initialized: false,
+            transaction_id: transaction_id.to_string(),
         });
     }
 }
@@ -422,6 +522,7 @@ pub fn extract_transactions(
     if abi::pool::events::Burn::match_log(log)
         || abi::pool::events::Mint::match_log(log)
         || abi::pool::events::Swap::match_log(log)
+        || abi::pool::events::Flash::match_log(log)
         || abi::positionmanager::events::IncreaseLiquidity::match_log(log)
         || abi::positionmanager::events::Collect::match_log(log)
         || abi::positionmanager::events::DecreaseLiquidity::match_log(log)
@@ -447,9 +548,10 @@ fn extract_positions(
     collect_positions: &mut Vec<events::CollectPosition>,
     transfer_positions: &mut Vec<events::TransferPosition>,
     call: &Call,
+    position_manager: &[u8],
 ) {
     for log in call.logs.iter() {
-        if log.address != NON_FUNGIBLE_POSITION_MANAGER {
+        if log.address != position_manager {
             return;
         }
 
@@ -471,6 +573,14 @@ fn extract_positions(
                 fee_growth_inside1_last_x128 = Some(new_value.to_string());
             }
 
+            let tokens_owed0 = manager_storage
+                .positions(&event.token_id)
+                .tokens_owed0()
+                .map(|(_old_value, new_value)| new_value.to_string());
+            let tokens_owed1 = manager_storage
+                .positions(&event.token_id)
+                .tokens_owed1()
+                .map(|(_old_value, new_value)| new_value.to_string());
             increase_liquidity_positions.push(events::IncreaseLiquidityPosition {
                 token_id: event.token_id.to_string(),
                 liquidity: event.liquidity.to_string(),
@@ -478,6 +588,8 @@ fn extract_positions(
                 deposited_token1: event.amount1.to_decimal(pool.token1().decimals).to_string(),
                 fee_growth_inside0_last_x128,
                 fee_growth_inside1_last_x128,
+                tokens_owed0,
+                tokens_owed1,
                 log_ordinal: log.ordinal,
             });
         } else if let Some(event) = abi::positionmanager::events::DecreaseLiquidity::match_and_decode(log) {
@@ -492,6 +604,14 @@ fn extract_positions(
             {
                 fee_growth_inside1_last_x128 = Some(new_value.to_string());
             }
+            let tokens_owed0 = manager_storage
+                .positions(&event.token_id)
+                .tokens_owed0()
+                .map(|(_old_value, new_value)| new_value.to_string());
+            let tokens_owed1 = manager_storage
+                .positions(&event.token_id)
+                .tokens_owed1()
+                .map(|(_old_value, new_value)| new_value.to_string());
             decrease_liquidity_positions.push(events::DecreaseLiquidityPosition {
                 token_id: event.token_id.to_string(),
                 liquidity: event.liquidity.to_string(),
@@ -499,6 +619,8 @@ fn extract_positions(
                 withdrawn_token1: event.amount1.to_decimal(pool.token1().decimals).to_string(),
                 fee_growth_inside0_last_x128,
                 fee_growth_inside1_last_x128,
+                tokens_owed0,
+                tokens_owed1,
                 log_ordinal: log.ordinal,
             });
         } else if let Some(event) = abi::positionmanager::events::Collect::match_and_decode(log) {
@@ -513,12 +635,22 @@ fn extract_positions(
             {
                 fee_growth_inside1_last_x128 = Some(new_value.to_string());
             }
+            let tokens_owed0 = manager_storage
+                .positions(&event.token_id)
+                .tokens_owed0()
+                .map(|(_old_value, new_value)| new_value.to_string());
+            let tokens_owed1 = manager_storage
+                .positions(&event.token_id)
+                .tokens_owed1()
+                .map(|(_old_value, new_value)| new_value.to_string());
             collect_positions.push(events::CollectPosition {
                 token_id: event.token_id.to_string(),
                 collected_fees_token0: event.amount0.to_decimal(pool.token0().decimals).to_string(),
                 collected_fees_token1: event.amount1.to_decimal(pool.token1().decimals).to_string(),
                 fee_growth_inside0_last_x128,
                 fee_growth_inside1_last_x128,
+                tokens_owed0,
+                tokens_owed1,
                 log_ordinal: log.ordinal,
             });
         } else if let Some(event) = abi::positionmanager::events::Transfer::match_and_decode(log) {
@@ -531,19 +663,173 @@ fn extract_positions(
     }
 }
 
-// pub fn extract_flashes(flashes: &mut Vec<events::Flash>, log: &Log) {
-//     if abi::pool::events::Flash::match_log(&log) {
-//         let pool_address: String = Hex(&log.address).to_string();
-//
-//         // FIXME: kill those `rpc` calls here!
-//         log::info!("pool_address: {}", pool_address);
-//         let (fee_growth_global_0x_128, fee_growth_global_1x_128) = rpc::fee_growth_global_x128_call(&pool_address);
-//
-//         flashes.push(events::Flash {
-//             pool_address,
-//             fee_growth_global_0x_128: fee_growth_global_0x_128.to_string(),
-//             fee_growth_global_1x_128: fee_growth_global_1x_128.to_string(),
-//             log_ordinal: log.ordinal,
-//         });
-//     }
-// }
+pub fn extract_flashes(
+    flashes: &mut Vec<events::Flash>,
+    log: &Log,
+    storage_changes: &Vec<StorageChange>,
+    pool: &Pool,
+    transaction_id: &String,
+    timestamp_seconds: u64,
+) {
+    if let Some(flash) = abi::pool::events::Flash::match_and_decode(log) {
+        log::info!("FLASH: transaction: {}", transaction_id.to_string());
+        let token0 = pool.token0_ref();
+        let token1 = pool.token1_ref();
+
+        // The fee growth globals are read from the storage changes of the flash call
+        // instead of querying the pool contract through an `eth_call`
+        let storage = UniswapPoolStorage::new(storage_changes, &log.address);
+
+        flashes.push(events::Flash {
+            pool_address: pool.address.to_string(),
+            fee_growth_global_0x_128: bigint_if_some(storage.fee_growth_global0x128()),
+            fee_growth_global_1x_128: bigint_if_some(storage.fee_growth_global1x128()),
+            log_ordinal: log.ordinal,
+            log_index: log.block_index as u64,
+            token0: token0.address.clone(),
+            token1: token1.address.clone(),
+            sender: Hex(&flash.sender).to_string(),
+            recipient: Hex(&flash.recipient).to_string(),
+            amount_0: flash.amount0.to_decimal(token0.decimals).into(),
+            amount_1: flash.amount1.to_decimal(token1.decimals).into(),
+            paid_0: flash.paid0.to_decimal(token0.decimals).into(),
+            paid_1: flash.paid1.to_decimal(token1.decimals).into(),
+            transaction_id: transaction_id.to_string(),
+            timestamp: timestamp_seconds,
+        });
+    }
+}
+
+pub fn extract_protocol_fee_events(
+    set_fee_protocols: &mut Vec<events::SetFeeProtocol>,
+    collect_protocols: &mut Vec<events::CollectProtocol>,
+    log: &Log,
+    pool: &Pool,
+    transaction_id: &String,
+    timestamp_seconds: u64,
+) {
+    if let Some(event) = abi::pool::events::SetFeeProtocol::match_and_decode(log) {
+        log::info!("SET FEE PROTOCOL: transaction: {}", transaction_id.to_string());
+        set_fee_protocols.push(events::SetFeeProtocol {
+            pool_address: pool.address.to_string(),
+            log_ordinal: log.ordinal,
+            fee_protocol_0_old: event.fee_protocol0_old.to_u64(),
+            fee_protocol_1_old: event.fee_protocol1_old.to_u64(),
+            fee_protocol_0_new: event.fee_protocol0_new.to_u64(),
+            fee_protocol_1_new: event.fee_protocol1_new.to_u64(),
+            transaction_id: transaction_id.to_string(),
+            timestamp: timestamp_seconds,
+        });
+    } else if let Some(event) = abi::pool::events::CollectProtocol::match_and_decode(log) {
+        log::info!("COLLECT PROTOCOL: transaction: {}", transaction_id.to_string());
+        let token0 = pool.token0_ref();
+        let token1 = pool.token1_ref();
+        collect_protocols.push(events::CollectProtocol {
+            pool_address: pool.address.to_string(),
+            log_ordinal: log.ordinal,
+            token0: token0.address.clone(),
+            token1: token1.address.clone(),
+            sender: Hex(&event.sender).to_string(),
+            recipient: Hex(&event.recipient).to_string(),
+            amount_0: event.amount0.to_decimal(token0.decimals).into(),
+            amount_1: event.amount1.to_decimal(token1.decimals).into(),
+            transaction_id: transaction_id.to_string(),
+            timestamp: timestamp_seconds,
+        });
+    }
+}
+
+pub fn extract_protocol_fees_update(
+    protocol_fees_updates: &mut Vec<events::ProtocolFees>,
+    log: &Log,
+    storage_changes: &Vec<StorageChange>,
+    pool: &Pool,
+) {
+    // protocol fees accrue on swaps and flashes and are drained by a protocol collect
+    if !abi::pool::events::Swap::match_log(log)
+        && !abi::pool::events::Flash::match_log(log)
+        && !abi::pool::events::CollectProtocol::match_log(log)
+    {
+        return;
+    }
+
+    let storage = UniswapPoolStorage::new(storage_changes, &log.address);
+
+    if let Some((_, new_value)) = storage.protocol_fees_token0() {
+        protocol_fees_updates.push(events::ProtocolFees {
+            pool_address: pool.address.to_string(),
+            ordinal: log.ordinal,
+            token_idx: 0,
+            new_value: new_value.to_decimal(pool.token0_ref().decimals).into(),
+        });
+    }
+
+    if let Some((_, new_value)) = storage.protocol_fees_token1() {
+        protocol_fees_updates.push(events::ProtocolFees {
+            pool_address: pool.address.to_string(),
+            ordinal: log.ordinal,
+            token_idx: 1,
+            new_value: new_value.to_decimal(pool.token1_ref().decimals).into(),
+        });
+    }
+}
+
+pub fn extract_oracle_updates(
+    oracle_updates: &mut Vec<events::OracleUpdate>,
+    observations: &mut Vec<events::Observation>,
+    log: &Log,
+    storage_changes: &Vec<StorageChange>,
+    pool: &Pool,
+) {
+    // the oracle is written on swaps, mints and burns, it is seeded on initialize
+    // and grown through increaseObservationCardinalityNext
+    let cardinality_next_increase = abi::pool::events::IncreaseObservationCardinalityNext::match_and_decode(log);
+    if cardinality_next_increase.is_none()
+        && !abi::pool::events::Swap::match_log(log)
+        && !abi::pool::events::Mint::match_log(log)
+        && !abi::pool::events::Burn::match_log(log)
+        && !abi::pool::events::Initialize::match_log(log)
+    {
+        return;
+    }
+
+    let storage = UniswapPoolStorage::new(storage_changes, &log.address);
+    let slot0 = storage.slot0();
+
+    let mut oracle_update = events::OracleUpdate {
+        pool_address: pool.address.to_string(),
+        ordinal: log.ordinal,
+        observation_index: slot0.observation_index().map(|(_, new_value)| new_value.to_u64()),
+        observation_cardinality: slot0.observation_cardinality().map(|(_, new_value)| new_value.to_u64()),
+        observation_cardinality_next: slot0
+            .observation_cardinality_next()
+            .map(|(_, new_value)| new_value.to_u64()),
+    };
+    if let Some(event) = cardinality_next_increase {
+        oracle_update.observation_cardinality_next = Some(event.observation_cardinality_next_new.to_u64());
+    }
+
+    if oracle_update.observation_index.is_some()
+        || oracle_update.observation_cardinality.is_some()
+        || oracle_update.observation_cardinality_next.is_some()
+    {
+        oracle_updates.push(oracle_update);
+    }
+
+    for (index, observation) in storage.observations() {
+        // growing the cardinality pre-fills the new slots with uninitialized observations
+        if !observation.initialized() {
+            continue;
+        }
+
+        observations.push(events::Observation {
+            pool_address: pool.address.to_string(),
+            ordinal: log.ordinal,
+            index,
+            block_timestamp: observation.block_timestamp().to_u64(),
+            tick_cumulative: observation.tick_cumulative().into(),
+            seconds_per_liquidity_cumulative_x128: observation.seconds_per_liquidity_cumulative_x128().into(),
+            initialized: true,
+        });
+    }
+}
@@ -0,0 +1,122 @@
+use crate::pb::uniswap::events::PoolSqrtPrice;
+use std::collections::{HashMap, HashSet};
+
+// Window, in seconds, of the TWAP the prices used for pricing are clamped against
+pub const GUARD_TWAP_WINDOW: u64 = 1800;
+
+// Maximum distance, in ticks, between the price used for pricing and the TWAP (about 10.5%)
+pub const DEFAULT_MAX_TWAP_TICK_DEVIATION: i32 = 1000;
+
+// Distance, in ticks, under which a transaction is considered to have moved the price back (about 0.1%)
+pub const DEFAULT_REVERSAL_TICK_TOLERANCE: i32 = 10;
+
+// Ordinals of the price updates which are reversed by the end of their transaction: when a transaction
+// updates the price of a pool several times and leaves it where it was before the transaction, every
+// update but the last one is a transient price (e.g. a flash-loan swap and its repayment).
+// `previous_tick` gives the tick of a pool before the block.
+pub fn reversed_ordinals<F>(pool_sqrt_prices: &[PoolSqrtPrice], previous_tick: F, tolerance: i32) -> HashSet<u64>
+where
+    F: Fn(&str) -> Option<i32>,
+{
+    // updates grouped by pool and transaction, in the order of the block
+    let mut groups: Vec<Vec<&PoolSqrtPrice>> = vec![];
+    let mut group_index: HashMap<(&str, &str), usize> = HashMap::new();
+    for update in pool_sqrt_prices {
+        let key = (update.pool_address.as_str(), update.transaction_id.as_str());
+        match group_index.get(&key) {
+            Some(index) => groups[*index].push(update),
+            None => {
+                group_index.insert(key, groups.len());
+                groups.push(vec![update]);
+            }
+        }
+    }
+
+    let mut reversed = HashSet::new();
+    let mut last_ticks: HashMap<&str, i32> = HashMap::new();
+    for updates in groups {
+        let pool_address = updates[0].pool_address.as_str();
+        let final_tick = parse_tick(updates.last().unwrap());
+        let tick_before = last_ticks
+            .get(pool_address)
+            .copied()
+            .or_else(|| previous_tick(pool_address));
+        last_ticks.insert(pool_address, final_tick);
+
+        let tick_before = match tick_before {
+            Some(tick) if updates.len() > 1 => tick,
+            _ => continue,
+        };
+        if (final_tick - tick_before).abs() > tolerance {
+            continue;
+        }
+
+        for update in &updates[..updates.len() - 1] {
+            if (parse_tick(update) - tick_before).abs() > tolerance {
+                reversed.insert(update.ordinal);
+            }
+        }
+    }
+
+    reversed
+}
+
+// Tick at most `max_deviation` away from the TWAP tick, `None` when `tick` is within it
+pub fn clamp_tick(tick: i32, twap_tick: i32, max_deviation: i32) -> Option<i32> {
+    if (tick - twap_tick).abs() <= max_deviation {
+        return None;
+    }
+    Some(tick.clamp(twap_tick - max_deviation, twap_tick + max_deviation))
+}
+
+pub fn parse_tick(pool_sqrt_price: &PoolSqrtPrice) -> i32 {
+    pool_sqrt_price.tick.parse::<i32>().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::guards::{clamp_tick, reversed_ordinals};
+    use crate::pb::uniswap::events::PoolSqrtPrice;
+    use std::collections::HashSet;
+
+    fn update(pool: &str, trx: &str, ordinal: u64, tick: i32) -> PoolSqrtPrice {
+        PoolSqrtPrice {
+            pool_address: pool.to_string(),
+            ordinal,
+            sqrt_price: "0".to_string(),
+            tick: tick.to_string(),
+            initialized: false,
+            transaction_id: trx.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_reversed_in_transaction() {
+        let updates = vec![
+            // pumped and dumped within the same transaction
+            update("pool", "trx1", 1, 5000),
+            update("other", "trx1", 2, 10),
+            update("pool", "trx1", 3, 105),
+            // a regular swap in the next transaction
+            update("pool", "trx2", 4, 300),
+            update("pool", "trx3", 5, 350),
+            update("pool", "trx3", 6, 400),
+        ];
+
+        let reversed = reversed_ordinals(&updates, |_| Some(100), 10);
+        assert_eq!(HashSet::from([1]), reversed);
+    }
+
+    #[test]
+    fn test_reversed_without_previous_tick() {
+        let updates = vec![update("pool", "trx1", 1, 5000), update("pool", "trx1", 2, 100)];
+        assert!(reversed_ordinals(&updates, |_| None, 10).is_empty());
+    }
+
+    #[test]
+    fn test_clamp_tick() {
+        assert_eq!(None, clamp_tick(1500, 1000, 1000));
+        assert_eq!(Some(2000), clamp_tick(9000, 1000, 1000));
+        assert_eq!(Some(0), clamp_tick(-9000, 1000, 1000));
+    }
+}
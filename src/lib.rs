@@ -1,30 +1,47 @@
+// the handlers taking module `params` receive them through a raw pointer in the generated code, the handler
+// macros drop the attributes of the functions so the lint can't be allowed on the handlers themselves
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
 extern crate core;
 
 pub mod abi;
 mod ast;
+mod config;
 mod db;
 mod eth;
 mod filtering;
+mod guards;
 mod math;
+mod network;
 mod pb;
+mod positions;
 mod price;
 mod rpc;
 mod storage;
-mod ticks_idx;
+mod transfers;
+mod twap;
 mod utils;
+mod whitelist;
 
+use crate::config::Config;
 use crate::ethpb::v2::{Block, StorageChange};
 use crate::pb::uniswap;
 use crate::pb::uniswap::events::pool_event::Type;
-use crate::pb::uniswap::events::pool_event::Type::{Burn as BurnEvent, Mint as MintEvent, Swap as SwapEvent};
+use crate::pb::uniswap::events::pool_event::Type::{
+    Burn as BurnEvent, Collect as CollectEvent, Mint as MintEvent, Swap as SwapEvent,
+};
 use crate::pb::uniswap::events::position_event::Type::{
     CollectPosition, CreatedPosition, DecreaseLiquidityPosition, IncreaseLiquidityPosition, TransferPosition,
 };
 use crate::pb::uniswap::events::{PoolSqrtPrice, PositionEvent};
+use crate::pb::uniswap::price_guard::Decision;
 use crate::pb::uniswap::{events, Events};
-use crate::pb::uniswap::{Erc20Token, Erc20Tokens, Pool, Pools};
-use crate::price::WHITELIST_TOKENS;
-use crate::utils::{ERROR_POOL, UNISWAP_V3_FACTORY};
+use crate::pb::uniswap::{
+    factory_events, ChainlinkAnswer, ChainlinkAnswers, Erc20Token, Erc20Tokens, FactoryEvents, Pool, PoolTwap,
+    PoolTwaps, Pools, PositionAmount, PositionAmounts, PositionFee, PositionFees, PositionPnl, PositionPnls,
+    PriceGuard, PriceGuards, TokenPrice, TokenPrices, TwapCheckpoint,
+};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::ops::{Div, Mul, Sub};
 use substreams::errors::Error;
 use substreams::key;
@@ -33,30 +50,135 @@ use substreams::prelude::*;
 use substreams::scalar::{BigDecimal, BigInt};
 use substreams::store::{
     DeltaArray, DeltaBigDecimal, DeltaBigInt, DeltaExt, DeltaProto, StoreAddBigDecimal, StoreAddBigInt, StoreAppend,
-    StoreGetBigDecimal, StoreGetBigInt, StoreGetProto, StoreGetRaw, StoreSetBigDecimal, StoreSetBigInt, StoreSetProto,
+    StoreGetBigDecimal, StoreGetBigInt, StoreGetProto, StoreGetRaw, StoreGetString, StoreSetBigDecimal, StoreSetBigInt,
+    StoreSetProto, StoreSetString,
 };
 use substreams::{log, Hex};
 use substreams_entity_change::pb::entity::EntityChanges;
 use substreams_entity_change::tables::Tables;
 use substreams_ethereum::{pb::eth as ethpb, Event as EventTrait};
 
+#[substreams::handlers::store]
+pub fn store_seen_tokens(params: String, block: Block, output: StoreSetIfNotExistsInt64) {
+    let config = Config::from_params(&params).unwrap();
+    for (ordinal, token_address) in filtering::extract_pool_created_tokens(&block, &config) {
+        output.set_if_not_exists(ordinal, format!("token:{token_address}"), &(block.number as i64));
+    }
+}
+
+#[substreams::handlers::map]
+pub fn map_token_metadata(
+    params: String,
+    block: Block,
+    seen_tokens_store: StoreGetInt64, /* store_seen_tokens */
+) -> Result<Erc20Tokens, Error> {
+    let config = Config::from_params(&params)?;
+    let mut resolved: HashSet<String> = HashSet::new();
+    let mut tokens = vec![];
+
+    // only the tokens seen for the first time in this block are queried, the others are in `store_token_metadata`
+    for (_, token_address) in filtering::extract_pool_created_tokens(&block, &config) {
+        let first_seen = seen_tokens_store.get_last(format!("token:{token_address}"));
+        if first_seen != Some(block.number as i64) || !resolved.insert(token_address.clone()) {
+            continue;
+        }
+
+        // the `token_overrides` metadata is used over the one read from the contract, tokens whose contract can't
+        // be read are kept with a placeholder
+        let calls = rpc::create_uniswap_token(&token_address);
+        let mut token = match (config.token_override(&token_address), calls.token) {
+            (Some(token_override), Ok(_)) => token_override.token(),
+            (Some(token_override), Err(reason)) => Erc20Token {
+                metadata_fallback_reason: reason,
+                ..token_override.token()
+            },
+            (None, Ok(token)) => token,
+            (None, Err(reason)) => {
+                log::info!(
+                    "unable to resolve the metadata of token {}, using a placeholder: {}",
+                    token_address,
+                    reason
+                );
+                Erc20Token {
+                    metadata_fallback_reason: reason,
+                    ..utils::placeholder_token(&token_address)
+                }
+            }
+        };
+        token.total_supply = calls.total_supply.to_string();
+        tokens.push(token);
+    }
+
+    Ok(Erc20Tokens { tokens })
+}
+
+#[substreams::handlers::store]
+pub fn store_token_metadata(tokens: Erc20Tokens, output: StoreSetProto<Erc20Token>) {
+    for token in tokens.tokens {
+        output.set(0, format!("token:{}", token.address), &token);
+    }
+}
+
+// The supply is seeded with the `totalSupply()` read when the token is first seen, which already includes
+// the mints and burns of that block, and follows them from the next block on
+#[substreams::handlers::store]
+pub fn store_token_supply(
+    block: Block,
+    tokens: Erc20Tokens,              /* map_token_metadata */
+    seen_tokens_store: StoreGetInt64, /* store_seen_tokens */
+    output: StoreAddBigInt,
+) {
+    for token in tokens.tokens {
+        let total_supply = BigInt::try_from(&token.total_supply).unwrap_or(BigInt::zero());
+        output.add(0, format!("token:{}", token.address), total_supply);
+    }
+
+    for log in block.logs() {
+        let Some(event) = abi::erc20::events::Transfer::match_and_decode(log) else {
+            continue;
+        };
+        let Some(delta) = transfers::supply_delta(&event.from, &event.to, &event.value) else {
+            continue;
+        };
+
+        let token_address = Hex(log.address()).to_string();
+        match seen_tokens_store.get_last(format!("token:{token_address}")) {
+            Some(first_seen) if (first_seen as u64) < block.number => {
+                output.add(log.ordinal(), format!("token:{token_address}"), delta);
+            }
+            _ => {}
+        }
+    }
+}
+
 #[substreams::handlers::map]
-pub fn map_pools_created(block: Block) -> Result<Pools, Error> {
+pub fn map_pools_created(
+    params: String,
+    block: Block,
+    token_metadata_store: StoreGetProto<Erc20Token>, /* store_token_metadata */
+) -> Result<Pools, Error> {
     use abi::factory::events::PoolCreated;
 
+    let config = Config::from_params(&params)?;
+    let factory = config.factory_address();
+    let error_pool = config.error_pool_address();
+
     Ok(Pools {
         pools: block
-            .events::<PoolCreated>(&[&UNISWAP_V3_FACTORY])
+            .events::<PoolCreated>(&[&factory])
             .filter_map(|(event, log)| {
                 log::info!("pool addr: {}", Hex(&event.pool));
 
-                if event.pool == ERROR_POOL {
+                if Some(&event.pool) == error_pool.as_ref() {
                     return None;
                 }
 
                 let token0_address = Hex(&event.token0).to_string();
                 let token1_address = Hex(&event.token1).to_string();
 
+                let token0 = token_metadata_store.must_get_last(format!("token:{token0_address}"));
+                let token1 = token_metadata_store.must_get_last(format!("token:{token1_address}"));
+
                 //todo: question regarding the ignore_pool line. In the
                 // uniswap-v3 subgraph, they seem to bail out when they
                 // match the addr, should we do the same ?
@@ -68,33 +190,9 @@ pub fn map_pools_created(block: Block) -> Result<Pools, Error> {
                     fee_tier: event.fee.to_string(),
                     tick_spacing: event.tick_spacing.into(),
                     log_ordinal: log.ordinal(),
-                    ignore_pool: event.pool == ERROR_POOL,
-                    token0: Some(match rpc::create_uniswap_token(&token0_address) {
-                        Some(mut token) => {
-                            token.total_supply = rpc::token_total_supply_call(&token0_address)
-                                .unwrap_or(BigInt::zero())
-                                .to_string();
-                            token
-                        }
-                        None => {
-                            // We were unable to create the uniswap token, so we discard this event entirely
-                            log::info!("ignoring creating of pool addr: {}", Hex(&event.pool));
-                            return None;
-                        }
-                    }),
-                    token1: Some(match rpc::create_uniswap_token(&token1_address) {
-                        Some(mut token) => {
-                            token.total_supply = rpc::token_total_supply_call(&token1_address)
-                                .unwrap_or(BigInt::zero())
-                                .to_string();
-                            token
-                        }
-                        None => {
-                            // We were unable to create the uniswap token, so we discard this event entirely
-                            log::info!("ignoring creating of pool addr: {}", Hex(&event.pool));
-                            return None;
-                        }
-                    }),
+                    ignore_pool: Some(&event.pool) == error_pool.as_ref(),
+                    token0: Some(token0),
+                    token1: Some(token1),
                     ..Default::default()
                 })
             })
@@ -102,6 +200,96 @@ pub fn map_pools_created(block: Block) -> Result<Pools, Error> {
     })
 }
 
+#[substreams::handlers::map]
+pub fn map_factory_events(params: String, block: Block) -> Result<FactoryEvents, Error> {
+    use abi::factory::events::{FeeAmountEnabled, OwnerChanged};
+
+    let config = Config::from_params(&params)?;
+    let factory = config.factory_address();
+
+    Ok(FactoryEvents {
+        fee_amounts_enabled: block
+            .events::<FeeAmountEnabled>(&[&factory])
+            .map(|(event, log)| {
+                log::info!("fee amount enabled: {} tick spacing: {}", event.fee, event.tick_spacing);
+
+                factory_events::FeeAmountEnabled {
+                    fee: event.fee.to_string(),
+                    tick_spacing: event.tick_spacing.to_i32(),
+                    log_ordinal: log.ordinal(),
+                    transaction_id: Hex(&log.receipt.transaction.hash).to_string(),
+                    timestamp: block.timestamp_seconds(),
+                    block_number: block.number,
+                }
+            })
+            .collect(),
+        owners_changed: block
+            .events::<OwnerChanged>(&[&factory])
+            .map(|(event, log)| {
+                log::info!(
+                    "owner changed from: {} to: {}",
+                    Hex(&event.old_owner),
+                    Hex(&event.new_owner)
+                );
+
+                factory_events::OwnerChanged {
+                    old_owner: Hex(&event.old_owner).to_string(),
+                    new_owner: Hex(&event.new_owner).to_string(),
+                    log_ordinal: log.ordinal(),
+                    transaction_id: Hex(&log.receipt.transaction.hash).to_string(),
+                    timestamp: block.timestamp_seconds(),
+                    block_number: block.number,
+                }
+            })
+            .collect(),
+    })
+}
+
+#[substreams::handlers::map]
+pub fn map_chainlink_answers(params: String, block: Block) -> Result<ChainlinkAnswers, Error> {
+    use abi::chainlink_aggregator::events::AnswerUpdated;
+
+    let config = Config::from_params(&params)?;
+    let aggregators = config.chainlink_aggregator_addresses();
+    if aggregators.is_empty() {
+        return Ok(ChainlinkAnswers::default());
+    }
+    let aggregators: Vec<&[u8]> = aggregators.iter().map(|aggregator| aggregator.as_slice()).collect();
+
+    Ok(ChainlinkAnswers {
+        answers: block
+            .events::<AnswerUpdated>(&aggregators)
+            .map(|(event, log)| {
+                let aggregator = Hex(log.address()).to_string();
+                let feed = config.chainlink_feed(&aggregator).unwrap();
+                log::info!(
+                    "chainlink {} answer {} round {}",
+                    feed.name,
+                    event.current,
+                    event.round_id
+                );
+
+                ChainlinkAnswer {
+                    feed: feed.name.clone(),
+                    aggregator,
+                    answer: event.current.to_decimal(feed.decimals).to_string(),
+                    round_id: event.round_id.to_string(),
+                    updated_at: event.updated_at.to_u64(),
+                    ordinal: log.ordinal(),
+                    block_number: block.number,
+                }
+            })
+            .collect(),
+    })
+}
+
+#[substreams::handlers::store]
+pub fn store_chainlink_answers(answers: ChainlinkAnswers, store: StoreSetProto<ChainlinkAnswer>) {
+    for answer in answers.answers {
+        store.set(answer.ordinal, format!("feed:{}", answer.feed), &answer);
+    }
+}
+
 #[substreams::handlers::store]
 pub fn store_pools_created(pools: Pools, store: StoreSetProto<Pool>) {
     for pool in pools.pools {
@@ -124,6 +312,54 @@ pub fn store_tokens(pools: Pools, store: StoreAddInt64) {
     }
 }
 
+#[substreams::handlers::store]
+pub fn store_token_pools(pools: Pools, output_append: StoreAppend<String>) {
+    for pool in pools.pools {
+        let token0_addr = pool.token0_ref().address();
+        let token1_addr = pool.token1_ref().address();
+
+        output_append.append(pool.log_ordinal, format!("token:{token0_addr}"), pool.address.clone());
+        output_append.append(pool.log_ordinal, format!("token:{token1_addr}"), pool.address.clone());
+    }
+}
+
+// Balances of the pools in their own tokens, from the `Transfer` events to and from them. Unlike the TVL
+// they include the donations, the fee-on-transfer losses and the fees not collected yet.
+#[substreams::handlers::store]
+pub fn store_pool_balances(
+    block: Block,
+    pools_store: StoreGetProto<Pool>, /* store_pools_created */
+    output: StoreAddBigDecimal,
+) {
+    for log in block.logs() {
+        let Some(event) = abi::erc20::events::Transfer::match_and_decode(log) else {
+            continue;
+        };
+        let token_address = Hex(log.address()).to_string();
+
+        for holder in [&event.from, &event.to] {
+            let Some(delta) = transfers::balance_delta(holder, &event.from, &event.to, &event.value) else {
+                continue;
+            };
+            let pool_address = Hex(holder).to_string();
+            let Some(pool) = pools_store.get_last(format!("pool:{pool_address}")) else {
+                continue;
+            };
+
+            let (token, token_index) = match token_address.as_str() {
+                address if address == pool.token0_ref().address() => (pool.token0_ref(), "token0"),
+                address if address == pool.token1_ref().address() => (pool.token1_ref(), "token1"),
+                _ => continue,
+            };
+            output.add(
+                log.ordinal(),
+                format!("pool:{pool_address}:{token_index}"),
+                delta.to_decimal(token.decimals),
+            );
+        }
+    }
+}
+
 #[substreams::handlers::store]
 pub fn store_pool_count(pools: Pools, store: StoreAddBigInt) {
     for pool in pools.pools {
@@ -132,29 +368,90 @@ pub fn store_pool_count(pools: Pools, store: StoreAddBigInt) {
 }
 
 #[substreams::handlers::map]
-pub fn map_tokens_whitelist_pools(pools: Pools) -> Result<Erc20Tokens, Error> {
-    let mut tokens = vec![];
-
-    for pool in pools.pools {
-        let mut token0 = pool.token0();
-        let mut token1 = pool.token1();
+pub fn map_tokens_whitelist_pools(
+    params: String,
+    clock: Clock,
+    pools: Pools,                     /* map_pools_created */
+    candidates_store: StoreGetString, /* store_whitelist_candidates */
+    pools_store: StoreGetProto<Pool>, /* store_pools_created */
+    token_pools_store: StoreGetRaw,   /* store_token_pools */
+) -> Result<Erc20Tokens, Error> {
+    let config = Config::from_params(&params)?;
+    let block_number = clock.number;
+
+    // tokens joining or leaving the whitelist in this block: the ones which went above the threshold
+    // `whitelist_min_blocks` ago and the ones which fell below it
+    let mut checked_tokens: Vec<String> = vec![];
+    for key in [
+        format!(
+            "block:{}:tokens",
+            block_number.saturating_sub(config.whitelist_min_blocks)
+        ),
+        format!("block:{block_number}:below"),
+    ] {
+        if let Some(tokens) = candidates_store.get_last(key) {
+            checked_tokens.extend(tokens.split(';').filter(|token| !token.is_empty()).map(str::to_string));
+        }
+    }
 
-        let token0_whitelisted = WHITELIST_TOKENS.contains(&token0.address.as_str());
-        let token1_whitelisted = WHITELIST_TOKENS.contains(&token1.address.as_str());
+    // the pools of a token joining or leaving the whitelist are added to or removed from the whitelist pools of
+    // the tokens they pair it with, and the new pools against a whitelisted token to the ones of the other token
+    let mut listed_tokens: BTreeMap<String, Erc20Token> = BTreeMap::new();
+    let mut list = |token: Erc20Token, entry: String| {
+        listed_tokens
+            .entry(token.address.clone())
+            .or_insert(Erc20Token {
+                whitelist_pools: vec![],
+                ..token
+            })
+            .whitelist_pools
+            .push(entry);
+    };
+    for token_address in checked_tokens {
+        if config.is_whitelisted(&token_address) {
+            continue;
+        }
+        let since_key = format!("token:{token_address}");
+        let was_whitelisted = whitelist::promoted(
+            whitelist::parse_since(candidates_store.get_at(0, &since_key)),
+            block_number.saturating_sub(1),
+            config.whitelist_min_blocks,
+        );
+        let is_whitelisted = whitelist::promoted(
+            whitelist::parse_since(candidates_store.get_last(&since_key)),
+            block_number,
+            config.whitelist_min_blocks,
+        );
+        if was_whitelisted == is_whitelisted {
+            continue;
+        }
 
-        if token0_whitelisted {
-            log::info!("adding pool: {} to token: {}", pool.address, token1.address);
-            token1.whitelist_pools.push(pool.address.to_string());
-            tokens.push(token1);
+        log::info!("token {token_address} whitelisted: {is_whitelisted}");
+        for pool_address in price::token_pools(&token_pools_store, &token_address) {
+            let pool = pools_store.must_get_last(format!("pool:{pool_address}"));
+            let other_token = match pool.token0_ref().address() == &token_address {
+                true => pool.token1(),
+                false => pool.token0(),
+            };
+            match is_whitelisted {
+                true => list(other_token, pool_address),
+                false => list(other_token, format!("{}{pool_address}", utils::REMOVED_ENTRY_PREFIX)),
+            }
         }
+    }
 
-        if token1_whitelisted {
-            log::info!("adding pool: {} to token: {}", pool.address, token0.address);
-            token0.whitelist_pools.push(pool.address.to_string());
-            tokens.push(token0);
+    for pool in pools.pools {
+        let token0 = pool.token0();
+        let token1 = pool.token1();
+        if whitelist::is_whitelisted(&token0.address, block_number, &candidates_store, &config) {
+            list(token1.clone(), pool.address.clone());
+        }
+        if whitelist::is_whitelisted(&token1.address, block_number, &candidates_store, &config) {
+            list(token0, pool.address.clone());
         }
     }
 
+    let tokens = listed_tokens.into_values().collect();
     Ok(Erc20Tokens { tokens })
 }
 
@@ -166,7 +463,9 @@ pub fn store_tokens_whitelist_pools(tokens: Erc20Tokens, output_append: StoreApp
 }
 
 #[substreams::handlers::map]
-pub fn map_extract_data_types(block: Block, pools_store: StoreGetProto<Pool>) -> Result<Events, Error> {
+pub fn map_extract_data_types(params: String, block: Block, pools_store: StoreGetProto<Pool>) -> Result<Events, Error> {
+    let config = Config::from_params(&params)?;
+    let position_manager = config.position_manager_address();
     let mut events = Events::default();
 
     let mut pool_sqrt_prices: Vec<events::PoolSqrtPrice> = vec![];
@@ -174,7 +473,12 @@ pub fn map_extract_data_types(block: Block, pools_store: StoreGetProto<Pool>) ->
     let mut fee_growth_global_updates: Vec<events::FeeGrowthGlobal> = vec![];
     let mut pool_events: Vec<events::PoolEvent> = vec![];
     let mut transactions: Vec<events::Transaction> = vec![];
-    // let mut flashes: Vec<events::Flash> = vec![];
+    let mut flashes: Vec<events::Flash> = vec![];
+    let mut set_fee_protocols: Vec<events::SetFeeProtocol> = vec![];
+    let mut collect_protocols: Vec<events::CollectProtocol> = vec![];
+    let mut protocol_fees_updates: Vec<events::ProtocolFees> = vec![];
+    let mut oracle_updates: Vec<events::OracleUpdate> = vec![];
+    let mut observations: Vec<events::Observation> = vec![];
     let mut ticks_created: Vec<events::TickCreated> = vec![];
     let mut ticks_updated: Vec<events::TickUpdated> = vec![];
 
@@ -196,7 +500,7 @@ pub fn map_extract_data_types(block: Block, pools_store: StoreGetProto<Pool>) ->
                 continue;
             }
             let pool = pool_opt.unwrap();
-            filtering::extract_pool_sqrt_prices(&mut pool_sqrt_prices, log, pool_address);
+            filtering::extract_pool_sqrt_prices(&mut pool_sqrt_prices, log, pool_address, &transactions_id);
             filtering::extract_pool_liquidities(&mut pool_liquidities, log, &call_view.call.storage_changes, &pool);
             filtering::extract_fee_growth_update(
                 &mut fee_growth_global_updates,
@@ -221,17 +525,48 @@ pub fn map_extract_data_types(block: Block, pools_store: StoreGetProto<Pool>) ->
                 &pool,
                 timestamp,
                 block.number,
+                &position_manager,
             );
 
-            filtering::extract_transactions(&mut transactions, log, &trx, timestamp, block.number);
+            filtering::extract_flashes(
+                &mut flashes,
+                log,
+                &call_view.call.storage_changes,
+                &pool,
+                &transactions_id,
+                timestamp,
+            );
 
-            // filtering::extract_flashes(&mut flashes, &log);
+            filtering::extract_protocol_fee_events(
+                &mut set_fee_protocols,
+                &mut collect_protocols,
+                log,
+                &pool,
+                &transactions_id,
+                timestamp,
+            );
+            filtering::extract_protocol_fees_update(
+                &mut protocol_fees_updates,
+                log,
+                &call_view.call.storage_changes,
+                &pool,
+            );
+            filtering::extract_oracle_updates(
+                &mut oracle_updates,
+                &mut observations,
+                log,
+                &call_view.call.storage_changes,
+                &pool,
+            );
+
+            filtering::extract_transactions(&mut transactions, log, trx, timestamp, block.number);
         }
     }
 
     events.pool_sqrt_prices = pool_sqrt_prices;
     events.pool_liquidities = pool_liquidities;
     events.fee_growth_global_updates = fee_growth_global_updates;
+    events.fee_growth_outside_updates = filtering::extract_fee_growth_outside_updates(&ticks_updated);
     events.pool_events = pool_events;
     events.transactions = transactions;
     events.created_positions = positions_created;
@@ -239,7 +574,12 @@ pub fn map_extract_data_types(block: Block, pools_store: StoreGetProto<Pool>) ->
     events.decrease_liquidity_positions = positions_decrease_liquidity;
     events.collect_positions = positions_collect;
     events.transfer_positions = positions_transfer;
-    // events.flashes = flashes;
+    events.flashes = flashes;
+    events.set_fee_protocols = set_fee_protocols;
+    events.collect_protocols = collect_protocols;
+    events.protocol_fees_updates = protocol_fees_updates;
+    events.oracle_updates = oracle_updates;
+    events.observations = observations;
     events.ticks_created = ticks_created;
     events.ticks_updated = ticks_updated;
 
@@ -254,8 +594,109 @@ pub fn store_pool_sqrt_price(events: Events, store: StoreSetProto<PoolSqrtPrice>
     }
 }
 
+#[substreams::handlers::map]
+pub fn map_price_guards(
+    params: String,
+    clock: Clock,
+    events: Events,                                      /* map_extract_data_types */
+    pools_store: StoreGetProto<Pool>,                    /* store_pools_created */
+    pool_liquidities_store: StoreGetBigInt,              /* store_pool_liquidities */
+    pool_sqrt_price_store: StoreGetProto<PoolSqrtPrice>, /* store_pool_sqrt_price */
+    twap_store: StoreGetProto<TwapCheckpoint>,           /* store_pool_twap */
+) -> Result<PriceGuards, Error> {
+    let config = Config::from_params(&params)?;
+    let now = clock.timestamp.unwrap().seconds as u64;
+
+    // at ordinal 0, the sqrt price of a pool is still the one of the previous block
+    let reversed = guards::reversed_ordinals(
+        &events.pool_sqrt_prices,
+        |pool_address| {
+            pool_sqrt_price_store
+                .get_at(0, format!("pool:{pool_address}"))
+                .map(|pool_sqrt_price| guards::parse_tick(&pool_sqrt_price))
+        },
+        config.reversal_tick_tolerance,
+    );
+
+    let mut price_guards = vec![];
+    for pool_sqrt_price in events.pool_sqrt_prices {
+        let ord = pool_sqrt_price.ordinal;
+        let pool_address = &pool_sqrt_price.pool_address;
+        let pool = match pools_store.get_last(format!("pool:{pool_address}")) {
+            None => continue,
+            Some(pool) => pool,
+        };
+        let tick = guards::parse_tick(&pool_sqrt_price);
+
+        let mut guard = PriceGuard {
+            pool_address: pool_address.clone(),
+            ordinal: ord,
+            transaction_id: pool_sqrt_price.transaction_id.clone(),
+            tick,
+            guarded_tick: tick,
+            ..Default::default()
+        };
+
+        // the active liquidity can only be valued in the pools of the wrapped native token
+        let mut low_liquidity = false;
+        let token0 = pool.token0_ref();
+        let token1 = pool.token1_ref();
+        let native = [token0, token1]
+            .into_iter()
+            .find(|token| token.address == config.wrapped_native_token);
+        if let Some(native) = native {
+            let liquidity = pool_liquidities_store
+                .get_at(ord, format!("pool:{pool_address}"))
+                .unwrap_or_else(BigInt::zero);
+            let sqrt_price = BigInt::try_from(pool_sqrt_price.sqrt_price.clone()).unwrap();
+            let active_native_liquidity = price::active_native_liquidity(
+                &liquidity,
+                &sqrt_price,
+                native.address == token0.address,
+                native.decimals,
+            );
+            low_liquidity = active_native_liquidity.lt(&config.minimum_active_native_liquidity);
+            guard.active_native_liquidity = active_native_liquidity.to_string();
+        }
+
+        if let Some(twap_tick) =
+            twap::previous_blocks_twap_tick(&twap_store, pool_address, guards::GUARD_TWAP_WINDOW, now)
+        {
+            guard.has_twap = true;
+            guard.twap_tick = twap_tick;
+        }
+
+        let decision = if reversed.contains(&ord) {
+            Decision::Reversed
+        } else if low_liquidity {
+            Decision::LowLiquidity
+        } else if let Some(clamped_tick) = guard
+            .has_twap
+            .then(|| guards::clamp_tick(tick, guard.twap_tick, config.max_twap_tick_deviation))
+            .flatten()
+        {
+            guard.guarded_tick = clamped_tick;
+            Decision::Clamped
+        } else {
+            Decision::Accepted
+        };
+        log::debug!("pool {pool_address} price update {ord} {}", decision.as_str_name());
+        guard.set_decision(decision);
+
+        price_guards.push(guard);
+    }
+
+    Ok(PriceGuards { price_guards })
+}
+
 #[substreams::handlers::store]
-pub fn store_prices(clock: Clock, events: Events, pools_store: StoreGetProto<Pool>, store: StoreSetBigDecimal) {
+pub fn store_prices(
+    clock: Clock,
+    events: Events,                   /* map_extract_data_types */
+    pools_store: StoreGetProto<Pool>, /* store_pools_created */
+    price_guards: PriceGuards,        /* map_price_guards */
+    store: StoreSetBigDecimal,
+) {
     let timestamp_seconds = clock.timestamp.unwrap().seconds;
     let day_id: i64 = timestamp_seconds / 86400;
     let hour_id: i64 = timestamp_seconds / 3600;
@@ -265,6 +706,12 @@ pub fn store_prices(clock: Clock, events: Events, pools_store: StoreGetProto<Poo
     store.delete_prefix(0, &format!("PoolDayData:{prev_day_id}:"));
     store.delete_prefix(0, &format!("PoolHourData:{prev_hour_id}:"));
 
+    let guards: HashMap<u64, PriceGuard> = price_guards
+        .price_guards
+        .into_iter()
+        .map(|guard| (guard.ordinal, guard))
+        .collect();
+
     for sqrt_price_update in events.pool_sqrt_prices {
         let pool_address = &sqrt_price_update.pool_address;
         match pools_store.get_last(format!("pool:{pool_address}")) {
@@ -279,6 +726,10 @@ pub fn store_prices(clock: Clock, events: Events, pools_store: StoreGetProto<Poo
                 }
                 // maybe check for this sqrt price also : 4295128739 -> on the other side
 
+                let decision = guards
+                    .get(&sqrt_price_update.ordinal)
+                    .map(|guard| guard.decision())
+                    .unwrap_or(Decision::Accepted);
                 let token0 = pool.token0.as_ref().unwrap();
                 let token1 = pool.token1.as_ref().unwrap();
                 log::debug!(
@@ -297,24 +748,47 @@ pub fn store_prices(clock: Clock, events: Events, pools_store: StoreGetProto<Poo
 
                 let token0_addr = &token0.address;
                 let token1_addr = &token1.address;
-                store.set_many(
+                store.set(
                     sqrt_price_update.ordinal,
-                    &vec![
-                        format!("pool:{pool_address}:{token0_addr}:token0"),
-                        format!("pair:{token0_addr}:{token1_addr}"), // used for find_eth_per_token
-                    ],
+                    format!("pool:{pool_address}:{token0_addr}:token0"),
                     &tokens_price.0,
                 );
-
-                store.set_many(
+                store.set(
                     sqrt_price_update.ordinal,
-                    &vec![
-                        format!("pool:{pool_address}:{token1_addr}:token1"),
-                        format!("pair:{token1_addr}:{token0_addr}"), // used for find_eth_per_token
-                    ],
+                    format!("pool:{pool_address}:{token1_addr}:token1"),
                     &tokens_price.1,
                 );
 
+                // The guarded prices are the ones used for find_eth_per_token, a pool without enough
+                // active liquidity is left out, a price too far from the TWAP is clamped and a reversed
+                // update keeps the previous guarded prices
+                if decision == Decision::LowLiquidity {
+                    store.delete_prefix(sqrt_price_update.ordinal as i64, &format!("guarded:{pool_address}:"));
+                } else if decision == Decision::Reversed {
+                    log::info!(
+                        "keeping guarded prices of reversed update {}",
+                        sqrt_price_update.ordinal
+                    );
+                } else {
+                    let guarded_price: (BigDecimal, BigDecimal) = match guards.get(&sqrt_price_update.ordinal) {
+                        Some(guard) if decision == Decision::Clamped => {
+                            price::tick_to_token_prices(guard.guarded_tick, token0, token1)
+                        }
+                        _ => tokens_price.clone(),
+                    };
+
+                    store.set(
+                        sqrt_price_update.ordinal,
+                        format!("guarded:{pool_address}:{token0_addr}:token0"),
+                        &guarded_price.0,
+                    );
+                    store.set(
+                        sqrt_price_update.ordinal,
+                        format!("guarded:{pool_address}:{token1_addr}:token1"),
+                        &guarded_price.1,
+                    );
+                }
+
                 // We only want to set the prices of PoolDayData and PoolHourData when
                 // the pool is post-initialized, not on the initialized event.
                 if sqrt_price_update.initialized {
@@ -357,14 +831,10 @@ pub fn store_pool_liquidities(clock: Clock, events: Events, store: StoreSetBigIn
 
     for pool_liquidity in events.pool_liquidities {
         let pool_address = &pool_liquidity.pool_address;
-        let token0_address = &pool_liquidity.token0;
-        let token1_address = &pool_liquidity.token1;
         store.set_many(
             pool_liquidity.log_ordinal,
             &vec![
                 format!("pool:{pool_address}"),
-                format!("pair:{token0_address}:{token1_address}"),
-                format!("pair:{token1_address}:{token0_address}"),
                 format!("PoolDayData:{day_id}:{pool_address}"),
                 format!("PoolHourData:{hour_id}:{pool_address}"),
             ],
@@ -373,6 +843,181 @@ pub fn store_pool_liquidities(clock: Clock, events: Events, store: StoreSetBigIn
     }
 }
 
+#[substreams::handlers::store]
+pub fn store_pool_protocol_fees(events: Events, store: StoreSetBigDecimal) {
+    for set_fee_protocol in events.set_fee_protocols {
+        let pool_address = &set_fee_protocol.pool_address;
+        store.set(
+            set_fee_protocol.log_ordinal,
+            format!("pool:{pool_address}:feeProtocol0"),
+            &BigDecimal::from(set_fee_protocol.fee_protocol_0_new),
+        );
+        store.set(
+            set_fee_protocol.log_ordinal,
+            format!("pool:{pool_address}:feeProtocol1"),
+            &BigDecimal::from(set_fee_protocol.fee_protocol_1_new),
+        );
+    }
+
+    for update in events.protocol_fees_updates {
+        let pool_address = &update.pool_address;
+        let field_name = match update.token_idx {
+            0 => "protocolFeesToken0",
+            1 => "protocolFeesToken1",
+            _ => continue,
+        };
+        store.set(
+            update.ordinal,
+            format!("pool:{pool_address}:{field_name}"),
+            &BigDecimal::try_from(update.new_value).unwrap(),
+        );
+    }
+}
+
+#[substreams::handlers::store]
+pub fn store_pool_oracle(clock: Clock, events: Events, store: StoreSetBigInt) {
+    let timestamp_seconds = clock.timestamp.unwrap().seconds;
+    let day_id: i64 = timestamp_seconds / 86400;
+    let hour_id: i64 = timestamp_seconds / 3600;
+    let prev_day_id = day_id - 1;
+    let prev_hour_id = hour_id - 1;
+
+    store.delete_prefix(0, &format!("PoolDayData:{prev_day_id}:"));
+    store.delete_prefix(0, &format!("PoolHourData:{prev_hour_id}:"));
+
+    for oracle_update in events.oracle_updates {
+        let ord = oracle_update.ordinal;
+        let pool_address = &oracle_update.pool_address;
+
+        if let Some(observation_index) = oracle_update.observation_index {
+            store.set_many(
+                ord,
+                &vec![
+                    format!("pool:{pool_address}:observationIndex"),
+                    format!("PoolDayData:{day_id}:{pool_address}:observationIndex"),
+                    format!("PoolHourData:{hour_id}:{pool_address}:observationIndex"),
+                ],
+                &BigInt::from(observation_index),
+            );
+        }
+        if let Some(observation_cardinality) = oracle_update.observation_cardinality {
+            store.set(
+                ord,
+                format!("pool:{pool_address}:observationCardinality"),
+                &BigInt::from(observation_cardinality),
+            );
+        }
+        if let Some(observation_cardinality_next) = oracle_update.observation_cardinality_next {
+            store.set(
+                ord,
+                format!("pool:{pool_address}:observationCardinalityNext"),
+                &BigInt::from(observation_cardinality_next),
+            );
+        }
+    }
+}
+
+#[substreams::handlers::store]
+pub fn store_pool_twap(
+    params: String,
+    clock: Clock,
+    events: Events,
+    pool_sqrt_price_store: StoreGetProto<PoolSqrtPrice>,
+    store: StoreSetProto<TwapCheckpoint>,
+) {
+    let config = Config::from_params(&params).unwrap();
+    let windows = twap::stored_windows(&config);
+    let timestamp_seconds = clock.timestamp.unwrap().seconds as u64;
+
+    // the slot of the next bucket only holds checkpoints older than the window, the slots skipped by a gap
+    // between the blocks are ignored until they are reused
+    for window in &windows {
+        let next_slot = twap::bucket_slot(twap::bucket_id(*window, timestamp_seconds) + 1);
+        store.delete_prefix(0, &format!("twap:{window}:{next_slot}:"));
+    }
+
+    for observation in events.observations {
+        let ord = observation.ordinal;
+        let pool_address = &observation.pool_address;
+
+        // the observation accumulates the tick which was active before the first swap of the block
+        let tick_after = match pool_sqrt_price_store.get_last(format!("pool:{pool_address}")) {
+            Some(sqrt_price) => sqrt_price.tick.parse::<i32>().unwrap(),
+            None => continue,
+        };
+        let tick_before = match pool_sqrt_price_store.get_at(0, format!("pool:{pool_address}")) {
+            Some(sqrt_price) => sqrt_price.tick.parse::<i32>().unwrap(),
+            None => tick_after,
+        };
+
+        let checkpoint = TwapCheckpoint {
+            pool_address: pool_address.clone(),
+            timestamp: observation.block_timestamp,
+            tick_cumulative: observation.tick_cumulative.parse::<i64>().unwrap(),
+            tick_before,
+            tick_after,
+        };
+
+        for window in &windows {
+            let bucket = twap::bucket_id(*window, checkpoint.timestamp);
+            store.set(ord, twap::bucket_key(*window, bucket, pool_address), &checkpoint);
+        }
+        store.set(ord, format!("pool:{pool_address}:latest"), &checkpoint);
+    }
+}
+
+// TWAPs over the `twap_windows` of the pools which wrote an oracle observation in the block, the other pools
+// aren't emitted: their TWAPs are refreshed with their next observation
+#[substreams::handlers::map]
+pub fn map_pool_twaps(
+    params: String,
+    clock: Clock,
+    events: Events,
+    pools_store: StoreGetProto<Pool>,
+    twap_store: StoreGetProto<TwapCheckpoint>,
+) -> Result<PoolTwaps, Error> {
+    let config = Config::from_params(&params)?;
+    let timestamp_seconds = clock.timestamp.unwrap().seconds as u64;
+    let mut pool_twaps = PoolTwaps { pool_twaps: vec![] };
+    let mut processed_pools: Vec<String> = vec![];
+
+    for observation in events.observations {
+        let pool_address = observation.pool_address;
+        if processed_pools.contains(&pool_address) {
+            continue;
+        }
+
+        let pool = pools_store.must_get_last(format!("pool:{pool_address}"));
+        let tick_cumulative_now = observation.tick_cumulative.parse::<i64>().unwrap();
+
+        for window in config.twap_windows.iter().copied() {
+            let target = timestamp_seconds.saturating_sub(window);
+            let tick_cumulative_start =
+                match twap::tick_cumulative_at(&twap_store, &pool_address, window, target, timestamp_seconds) {
+                    Some(tick_cumulative) => tick_cumulative,
+                    None => continue,
+                };
+
+            let tick = twap::arithmetic_mean_tick(tick_cumulative_now, tick_cumulative_start, window);
+            let (token0_price, token1_price) = price::tick_to_token_prices(tick, pool.token0_ref(), pool.token1_ref());
+
+            pool_twaps.pool_twaps.push(PoolTwap {
+                pool_address: pool_address.clone(),
+                window,
+                tick,
+                token0_price: token0_price.to_string(),
+                token1_price: token1_price.to_string(),
+                timestamp: timestamp_seconds,
+                block_number: clock.number,
+            });
+        }
+
+        processed_pools.push(pool_address);
+    }
+
+    Ok(pool_twaps)
+}
+
 #[substreams::handlers::store]
 pub fn store_total_tx_counts(clock: Clock, events: Events, output: StoreAddBigInt) {
     let timestamp_seconds = clock.timestamp.unwrap().seconds;
@@ -380,7 +1025,6 @@ pub fn store_total_tx_counts(clock: Clock, events: Events, output: StoreAddBigIn
     let hour_id = timestamp_seconds / 3600;
     let prev_day_id = day_id - 1;
     let prev_hour_id = hour_id - 1;
-    let factory_addr = Hex(UNISWAP_V3_FACTORY);
 
     output.delete_prefix(0, &format!("UniswapDayData:{prev_day_id}:"));
     output.delete_prefix(0, &format!("PoolDayData:{prev_day_id}:"));
@@ -399,7 +1043,7 @@ pub fn store_total_tx_counts(clock: Clock, events: Events, output: StoreAddBigIn
                 format!("pool:{pool_address}"),
                 format!("token:{token0_addr}"),
                 format!("token:{token1_addr}"),
-                format!("factory:{factory_addr}"),
+                format!("factory:txCount"),
                 format!("UniswapDayData:{day_id}"),
                 format!("PoolDayData:{day_id}:{pool_address}"),
                 format!("PoolHourData:{hour_id}:{pool_address}"),
@@ -413,15 +1057,26 @@ pub fn store_total_tx_counts(clock: Clock, events: Events, output: StoreAddBigIn
     }
 }
 
+/**
+ * STORE NATIVE AMOUNTS -> spits out any mint, swap and burn amounts
+ */
 #[substreams::handlers::store]
 pub fn store_swaps_volume(
+    params: String,
     clock: Clock,
     events: Events,
     store_pool: StoreGetProto<Pool>,
     store_total_tx_counts: StoreGetBigInt,
     store_eth_prices: StoreGetBigDecimal,
+    candidates_store: StoreGetString, /* store_whitelist_candidates */
     output: StoreAddBigDecimal,
 ) {
+    let config = Config::from_params(&params).unwrap();
+    let whitelist = whitelist::Whitelist {
+        block_number: clock.number,
+        candidates_store: &candidates_store,
+        config: &config,
+    };
     let timestamp_seconds = clock.timestamp.unwrap().seconds;
     let day_id = timestamp_seconds / 86400;
     let hour_id = timestamp_seconds / 3600;
@@ -488,6 +1143,7 @@ pub fn store_swaps_volume(
                     &token0_derived_eth_price,
                     &token1_derived_eth_price,
                     &eth_price_in_usd,
+                    &whitelist,
                 );
 
                 log::info!("volumeAmounts.eth {}", volume_amounts.delta_tvl_eth);
@@ -604,14 +1260,83 @@ pub fn store_swaps_volume(
                 );
                 output.add(ord, format!("factory:totalFeesETH"), &fee_eth);
             }
+            CollectEvent(collect) => {
+                let eth_price_in_usd = store_eth_prices.get_at(ord, "bundle").unwrap_or_default();
+                let token0_derived_eth_price = store_eth_prices
+                    .get_at(ord, format!("token:{token0_addr}:dprice:eth"))
+                    .unwrap_or_default();
+                let token1_derived_eth_price = store_eth_prices
+                    .get_at(ord, format!("token:{token1_addr}:dprice:eth"))
+                    .unwrap_or_default();
+
+                let amount0 = BigDecimal::try_from(collect.amount_0).unwrap();
+                let amount1 = BigDecimal::try_from(collect.amount_1).unwrap();
+
+                let collected_usd = utils::get_tracked_amount_usd(
+                    token0_addr,
+                    token1_addr,
+                    &token0_derived_eth_price,
+                    &token1_derived_eth_price,
+                    &amount0,
+                    &amount1,
+                    &eth_price_in_usd,
+                    &whitelist,
+                );
+
+                output.add(ord, format!("pool:{pool_address}:collectedFeesToken0"), &amount0);
+                output.add(ord, format!("pool:{pool_address}:collectedFeesToken1"), &amount1);
+                output.add(ord, format!("pool:{pool_address}:collectedFeesUSD"), &collected_usd);
+            }
             _ => {}
         }
     }
+
+    for collect_protocol in events.collect_protocols {
+        let ord = collect_protocol.log_ordinal;
+        let pool_address = &collect_protocol.pool_address;
+        let token0_addr = &collect_protocol.token0;
+        let token1_addr = &collect_protocol.token1;
+
+        let eth_price_in_usd = store_eth_prices.get_at(ord, "bundle").unwrap_or_default();
+        let token0_derived_eth_price = store_eth_prices
+            .get_at(ord, format!("token:{token0_addr}:dprice:eth"))
+            .unwrap_or_default();
+        let token1_derived_eth_price = store_eth_prices
+            .get_at(ord, format!("token:{token1_addr}:dprice:eth"))
+            .unwrap_or_default();
+
+        let amount0 = BigDecimal::try_from(collect_protocol.amount_0).unwrap();
+        let amount1 = BigDecimal::try_from(collect_protocol.amount_1).unwrap();
+
+        let collected_usd = utils::calculate_amount_usd(
+            &amount0,
+            &amount1,
+            &token0_derived_eth_price,
+            &token1_derived_eth_price,
+            &eth_price_in_usd,
+        );
+
+        output.add(
+            ord,
+            format!("pool:{pool_address}:collectedProtocolFeesToken0"),
+            &amount0,
+        );
+        output.add(
+            ord,
+            format!("pool:{pool_address}:collectedProtocolFeesToken1"),
+            &amount1,
+        );
+        output.add_many(
+            ord,
+            &vec![
+                format!("pool:{pool_address}:collectedProtocolFeesUSD"),
+                format!("factory:collectedProtocolFeesUSD"),
+            ],
+            &collected_usd,
+        );
+    }
 }
 
-/**
- * STORE NATIVE AMOUNTS -> spits out any mint, swap and burn amounts
- */
 #[substreams::handlers::store]
 pub fn store_native_amounts(events: Events, store: StoreSetBigDecimal) {
     for pool_event in events.pool_events {
@@ -649,25 +1374,38 @@ pub fn store_native_amounts(events: Events, store: StoreSetBigDecimal) {
     }
 }
 
-#[substreams::handlers::store]
-pub fn store_eth_prices(
+#[substreams::handlers::map]
+pub fn map_token_prices(
+    params: String,
     clock: Clock,
-    events: Events,                                /* map_extract_data_types */
-    pools_store: StoreGetProto<Pool>,              /* store_pools_created */
-    prices_store: StoreGetBigDecimal,              /* store_prices */
-    tokens_whitelist_pools_store: StoreGetRaw,     /* store_tokens_whitelist_pools */
-    total_native_amount_store: StoreGetBigDecimal, /* store_native_amounts */
-    pool_liquidities_store: StoreGetBigInt,        /* store_pool_liquidities */
-    output: StoreSetBigDecimal,
-) {
-    let timestamp_seconds = clock.timestamp.unwrap().seconds;
-    let day_id = timestamp_seconds / 86400;
-    let hour_id = timestamp_seconds / 3600;
-    let prev_day_id = day_id - 1;
-    let prev_hour_id = hour_id - 1;
-
-    output.delete_prefix(0, &format!("TokenDayData:{prev_day_id}:"));
-    output.delete_prefix(0, &format!("TokenHourData:{prev_hour_id}:"));
+    events: Events,                                      /* map_extract_data_types */
+    pools_store: StoreGetProto<Pool>,                    /* store_pools_created */
+    prices_store: StoreGetBigDecimal,                    /* store_prices */
+    tokens_whitelist_pools_store: StoreGetRaw,           /* store_tokens_whitelist_pools */
+    token_pools_store: StoreGetRaw,                      /* store_token_pools */
+    total_native_amount_store: StoreGetBigDecimal,       /* store_native_amounts */
+    pool_liquidities_store: StoreGetBigInt,              /* store_pool_liquidities */
+    pool_sqrt_price_store: StoreGetProto<PoolSqrtPrice>, /* store_pool_sqrt_price */
+    token_tvl_store: StoreGetBigDecimal,                 /* store_token_tvl */
+    candidates_store: StoreGetString,                    /* store_whitelist_candidates */
+) -> Result<TokenPrices, Error> {
+    let config = Config::from_params(&params)?;
+    let bundle_pools = price::usd_bundle_pools(&pools_store, &tokens_whitelist_pools_store, &config);
+    let pricing_stores = price::PricingStores {
+        pools_store: &pools_store,
+        pool_liquidities_store: &pool_liquidities_store,
+        tokens_whitelist_pools_store: &tokens_whitelist_pools_store,
+        token_pools_store: &token_pools_store,
+        total_native_amounts_store: &total_native_amount_store,
+        token_tvl_store: &token_tvl_store,
+        prices_store: &prices_store,
+    };
+    let whitelist = whitelist::Whitelist {
+        block_number: clock.number,
+        candidates_store: &candidates_store,
+        config: &config,
+    };
+    let mut token_prices = vec![];
 
     for pool_sqrt_price in events.pool_sqrt_prices {
         let ord = pool_sqrt_price.ordinal;
@@ -680,82 +1418,145 @@ pub fn store_eth_prices(
         let pool = pools_store.must_get_last(format!("pool:{pool_address}"));
         let token0 = pool.token0.as_ref().unwrap();
         let token1 = pool.token1.as_ref().unwrap();
-        let token0_addr = &token0.address;
-        let token1_addr = &token1.address;
 
         token0.log();
         token1.log();
 
-        let bundle_eth_price_usd = price::get_eth_price_in_usd(&prices_store, ord);
-        log::info!("bundle_eth_price_usd: {}", bundle_eth_price_usd);
-
-        let token0_derived_eth_price: BigDecimal = price::find_eth_per_token(
+        let bundle_eth_price_usd = price::get_eth_price_in_usd(
             ord,
-            &pool.address,
-            token0_addr,
-            &pools_store,
+            &bundle_pools,
             &pool_liquidities_store,
-            &tokens_whitelist_pools_store,
-            &total_native_amount_store,
+            &pool_sqrt_price_store,
             &prices_store,
+            &config,
         );
-        log::info!(format!(
-            "token 0 {token0_addr} derived eth price: {token0_derived_eth_price}"
-        ));
+        log::info!("bundle_eth_price_usd: {}", bundle_eth_price_usd);
 
-        let token1_derived_eth_price: BigDecimal = price::find_eth_per_token(
-            ord,
-            &pool.address,
-            token1_addr,
-            &pools_store,
-            &pool_liquidities_store,
-            &tokens_whitelist_pools_store,
-            &total_native_amount_store,
-            &prices_store,
-        );
-        log::info!(format!(
-            "token 1 {token1_addr} derived eth price: {token1_derived_eth_price}"
-        ));
+        for token_addr in [&token0.address, &token1.address] {
+            let derived_eth_price = price::find_eth_per_token(
+                ord,
+                &pool.address,
+                token_addr,
+                &pricing_stores,
+                &whitelist,
+                &bundle_eth_price_usd,
+                &config,
+            );
+            let confidence = price::price_confidence(token_addr, &derived_eth_price, &config);
+            log::info!(
+                "token {token_addr} derived eth price: {} through {:?} with confidence {}",
+                derived_eth_price.price,
+                derived_eth_price.path,
+                confidence
+            );
 
-        output.set(ord, "bundle", &bundle_eth_price_usd);
-        output.set(
-            ord,
-            format!("token:{token0_addr}:dprice:eth"),
-            &token0_derived_eth_price,
-        );
+            token_prices.push(TokenPrice {
+                token_address: token_addr.clone(),
+                ordinal: ord,
+                pool_address: derived_eth_price.pool_address.clone(),
+                derived_eth_price: derived_eth_price.price.to_string(),
+                eth_price_usd: bundle_eth_price_usd.to_string(),
+                depth: derived_eth_price.depth(),
+                path: derived_eth_price.path,
+                eth_locked: derived_eth_price.eth_locked.to_string(),
+                initialized: pool_sqrt_price.initialized,
+                block_number: clock.number,
+                timestamp: clock.timestamp.as_ref().unwrap().seconds,
+                confidence: confidence.to_string(),
+            });
+        }
+    }
+
+    Ok(TokenPrices { token_prices })
+}
+
+#[substreams::handlers::store]
+pub fn store_eth_prices(
+    params: String,
+    clock: Clock,
+    token_prices: TokenPrices,                               /* map_token_prices */
+    chainlink_answers_store: StoreGetProto<ChainlinkAnswer>, /* store_chainlink_answers */
+    output: StoreSetBigDecimal,
+) {
+    let config = Config::from_params(&params).unwrap();
+    let timestamp_seconds = clock.timestamp.unwrap().seconds;
+    let day_id = timestamp_seconds / 86400;
+    let hour_id = timestamp_seconds / 3600;
+    let prev_day_id = day_id - 1;
+    let prev_hour_id = hour_id - 1;
+
+    output.delete_prefix(0, &format!("TokenDayData:{prev_day_id}:"));
+    output.delete_prefix(0, &format!("TokenHourData:{prev_hour_id}:"));
+
+    // `priceUpdated:{day_id}:{token}` holds the block of the last price update of a token on the day it
+    // happened. The keys of the day which just went past `stale_price_days` are the tokens without a
+    // price update since then, their deletion flags the price of the tokens as stale in `graph_out`.
+    output.delete_prefix(0, &format!("priceUpdated:{}:", day_id - config.stale_price_days - 1));
+
+    for token_price in token_prices.token_prices {
+        let ord = token_price.ordinal;
+        let token_addr = &token_price.token_address;
+
+        for previous_day_id in day_id - config.stale_price_days..day_id {
+            output.delete_prefix(ord as i64, &format!("priceUpdated:{previous_day_id}:{token_addr}"));
+        }
         output.set(
             ord,
-            format!("token:{token1_addr}:dprice:eth"),
-            &token1_derived_eth_price,
+            format!("priceUpdated:{day_id}:{token_addr}"),
+            &BigDecimal::from(token_price.block_number),
         );
 
-        let token0_price_usd = token0_derived_eth_price.clone().mul(bundle_eth_price_usd.clone());
-        let token1_price_usd = token1_derived_eth_price.clone().mul(bundle_eth_price_usd);
+        let mut bundle_eth_price_usd = BigDecimal::try_from(&token_price.eth_price_usd).unwrap();
+        let derived_eth_price = BigDecimal::try_from(&token_price.derived_eth_price).unwrap();
+
+        // the Chainlink answer is kept next to the pools price with their deviation, and replaces it when
+        // `chainlink_as_bundle` is set
+        let chainlink_answer = config
+            .chainlink_native_usd_feed
+            .as_ref()
+            .and_then(|feed| chainlink_answers_store.get_at(ord, format!("feed:{feed}")));
+        if let Some(answer) = chainlink_answer {
+            let chainlink_eth_price_usd = BigDecimal::try_from(&answer.answer).unwrap();
+            let deviation = price::price_deviation(&bundle_eth_price_usd, &chainlink_eth_price_usd);
+            log::info!("bundle deviation from chainlink {}: {}", answer.feed, deviation);
+
+            output.set(ord, "chainlink:bundle", &chainlink_eth_price_usd);
+            output.set(ord, "chainlink:deviation", &deviation);
+            if config.chainlink_as_bundle {
+                bundle_eth_price_usd = chainlink_eth_price_usd;
+            }
+        }
+
+        output.set(ord, "bundle", &bundle_eth_price_usd);
+        output.set(ord, format!("token:{token_addr}:dprice:eth"), &derived_eth_price);
 
-        log::info!("token0 price usd: {}", token0_price_usd);
-        log::info!("token1 price usd: {}", token1_price_usd);
+        let price_usd = derived_eth_price.mul(bundle_eth_price_usd);
+        log::info!("token {} price usd: {}", token_addr, price_usd);
 
         // We only want to set the prices of TokenDayData and TokenHourData when
         // the pool is post-initialized, not on the initialized event.
-        if pool_sqrt_price.initialized {
+        if token_price.initialized {
             continue;
         }
 
         output.set_many(
             ord,
             &vec![
-                format!("TokenDayData:{day_id}:{token0_addr}"),
-                format!("TokenHourData:{hour_id}:{token0_addr}"),
+                format!("TokenDayData:{day_id}:{token_addr}"),
+                format!("TokenHourData:{hour_id}:{token_addr}"),
             ],
-            &token0_price_usd,
+            &price_usd,
         );
-        output.set_many(
-            ord,
-            &vec![
-                format!("TokenDayData:{day_id}:{token1_addr}"),
-                format!("TokenHourData:{hour_id}:{token1_addr}"),
-            ],
-            &token1_price_usd,
+    }
+}
+
+#[substreams::handlers::store]
+pub fn store_token_prices(token_prices: TokenPrices, store: StoreSetProto<TokenPrice>) {
+    for token_price in token_prices.token_prices {
+        store.set(
+            token_price.ordinal,
+            format!("token:{}", token_price.token_address),
+            &token_price,
         );
     }
 }
@@ -763,7 +1564,10 @@ pub fn store_eth_prices(
 #[substreams::handlers::store]
 pub fn store_token_tvl(events: Events, output: StoreAddBigDecimal) {
     for pool_event in events.pool_events {
-        let token_amounts = pool_event.get_amounts().unwrap();
+        let token_amounts = match pool_event.get_amounts() {
+            None => continue,
+            Some(amounts) => amounts,
+        };
         let pool_address = pool_event.pool_address.to_string();
         let token0_addr = pool_event.token0.to_string();
         let token1_addr = pool_event.token1.to_string();
@@ -789,15 +1593,112 @@ pub fn store_token_tvl(events: Events, output: StoreAddBigDecimal) {
     }
 }
 
+#[substreams::handlers::store]
+pub fn store_whitelist_candidates(
+    params: String,
+    clock: Clock,
+    events: Events,                         /* map_extract_data_types */
+    token_tvl_store: StoreGetBigDecimal,    /* store_token_tvl */
+    pools_store: StoreGetProto<Pool>,       /* store_pools_created */
+    pool_liquidities_store: StoreGetBigInt, /* store_pool_liquidities */
+    token_pools_store: StoreGetRaw,         /* store_token_pools */
+    prices_store: StoreGetBigDecimal,       /* store_prices */
+    output: StoreSetString,
+) {
+    let config = Config::from_params(&params).unwrap();
+    let block_number = clock.number;
+    let stores = whitelist::TvlStores {
+        token_tvl_store: &token_tvl_store,
+        pools_store: &pools_store,
+        pool_liquidities_store: &pool_liquidities_store,
+        token_pools_store: &token_pools_store,
+        prices_store: &prices_store,
+    };
+
+    // `block:{block_number}:tokens` lists the tokens which went above the threshold at that block, it is read by
+    // `map_tokens_whitelist_pools` `whitelist_min_blocks` later, and `block:{block_number}:below` the ones which
+    // fell below it, read at the same block
+    output.delete_prefix(
+        0,
+        &format!(
+            "block:{}:",
+            block_number.saturating_sub(config.whitelist_min_blocks + 1)
+        ),
+    );
+
+    // the TVL or the native price of the tokens of the pools with events may have changed, with the ordinal of
+    // their last change
+    let mut changed_tokens: HashMap<String, u64> = HashMap::new();
+    let mut changed = |token_address: &str, ord: u64| {
+        let last_ord = changed_tokens.entry(token_address.to_string()).or_default();
+        *last_ord = (*last_ord).max(ord);
+    };
+    for pool_event in &events.pool_events {
+        changed(&pool_event.token0, pool_event.log_ordinal);
+        changed(&pool_event.token1, pool_event.log_ordinal);
+    }
+    for sqrt_price_update in &events.pool_sqrt_prices {
+        if let Some(pool) = pools_store.get_last(format!("pool:{}", sqrt_price_update.pool_address)) {
+            changed(pool.token0_ref().address(), sqrt_price_update.ordinal);
+            changed(pool.token1_ref().address(), sqrt_price_update.ordinal);
+        }
+    }
+    let mut changed_tokens: Vec<(u64, String)> = changed_tokens
+        .into_iter()
+        .filter(|(token_address, _)| {
+            token_address != &config.wrapped_native_token && !config.is_whitelisted(token_address)
+        })
+        .map(|(token_address, ord)| (ord, token_address))
+        .collect();
+    changed_tokens.sort();
+
+    let mut went_above: Vec<String> = vec![];
+    let mut went_below: Vec<String> = vec![];
+    let mut last_ordinal = 0;
+    for (ord, token_address) in changed_tokens {
+        let old_eth_tvl = whitelist::eth_tvl(0, &token_address, &stores, &config);
+        let new_eth_tvl = whitelist::eth_tvl(ord, &token_address, &stores, &config);
+        match whitelist::threshold_crossing(&old_eth_tvl, &new_eth_tvl, &config.whitelist_eth_tvl) {
+            Some(whitelist::Crossing::Above) => {
+                output.set(ord, format!("token:{token_address}"), &block_number.to_string());
+                went_above.push(token_address);
+            }
+            Some(whitelist::Crossing::Below) => {
+                output.delete_prefix(ord as i64, &format!("token:{token_address}"));
+                went_below.push(token_address);
+            }
+            None => continue,
+        }
+        last_ordinal = ord;
+    }
+
+    if !went_above.is_empty() {
+        let tokens: String = went_above.iter().map(|token| token.clone() + ";").collect();
+        output.set(last_ordinal, format!("block:{block_number}:tokens"), &tokens);
+    }
+    if !went_below.is_empty() {
+        let tokens: String = went_below.iter().map(|token| token.clone() + ";").collect();
+        output.set(last_ordinal, format!("block:{block_number}:below"), &tokens);
+    }
+}
+
 #[substreams::handlers::store]
 pub fn store_derived_tvl(
+    params: String,
     clock: Clock,
     events: Events,
     token_total_value_locked: StoreGetBigDecimal, /* store_token_tvl  */
     pools_store: StoreGetProto<Pool>,
     eth_prices_store: StoreGetBigDecimal,
+    candidates_store: StoreGetString, /* store_whitelist_candidates */
     output: StoreSetBigDecimal,
 ) {
+    let config = Config::from_params(&params).unwrap();
+    let whitelist = whitelist::Whitelist {
+        block_number: clock.number,
+        candidates_store: &candidates_store,
+        config: &config,
+    };
     let timestamp_seconds = clock.timestamp.unwrap().seconds;
     let day_id: i64 = timestamp_seconds / 86400;
     let hour_id: i64 = timestamp_seconds / 3600;
@@ -856,6 +1757,7 @@ pub fn store_derived_tvl(
             &token0_derive_eth,
             &token1_derive_eth,
             &eth_price_usd,
+            &whitelist,
         );
         // let amounts_for_token = utils::get_adjusted_amounts(
         //     token0_addr,
@@ -1051,6 +1953,564 @@ pub fn store_ticks_liquidities(clock: Clock, events: Events, output: StoreAddBig
     }
 }
 
+// Liquidity of every position, from the liquidity added and removed by `IncreaseLiquidity` and `DecreaseLiquidity`
+#[substreams::handlers::store]
+pub fn store_position_liquidities(events: Events, output: StoreAddBigInt) {
+    for position in events.increase_liquidity_positions {
+        let liquidity = BigInt::try_from(&position.liquidity).unwrap();
+        output.add(
+            position.log_ordinal,
+            format!("position:{}", position.token_id),
+            liquidity,
+        );
+    }
+
+    for position in events.decrease_liquidity_positions {
+        let liquidity = BigInt::try_from(&position.liquidity).unwrap();
+        output.add(
+            position.log_ordinal,
+            format!("position:{}", position.token_id),
+            liquidity.neg(),
+        );
+    }
+}
+
+// Open positions of the pools as bitsets of 256 token ids under `pool:{pool}:{chunk}`: the bit of a position is
+// set when its liquidity goes above zero, and cleared when it goes back to zero
+#[substreams::handlers::store]
+pub fn store_pool_positions(
+    events: Events,
+    positions_store: StoreGetProto<PositionEvent>, /* store_positions */
+    position_liquidities_store: StoreGetBigInt,    /* store_position_liquidities */
+    output: StoreAddBigInt,
+) {
+    let mut liquidity_updates: Vec<(u64, &String, BigInt)> = vec![];
+    for position in &events.increase_liquidity_positions {
+        let liquidity = BigInt::try_from(&position.liquidity).unwrap();
+        liquidity_updates.push((position.log_ordinal, &position.token_id, liquidity));
+    }
+    for position in &events.decrease_liquidity_positions {
+        let liquidity = BigInt::try_from(&position.liquidity).unwrap();
+        liquidity_updates.push((position.log_ordinal, &position.token_id, liquidity.neg()));
+    }
+    liquidity_updates.sort_by_key(|(ordinal, _, _)| *ordinal);
+
+    for (ordinal, token_id, liquidity_delta) in liquidity_updates {
+        let liquidity = position_liquidities_store
+            .get_at(ordinal, format!("position:{token_id}"))
+            .unwrap_or(BigInt::zero());
+        let previous_liquidity = liquidity.clone() - liquidity_delta;
+        let opened = match (previous_liquidity.is_zero(), liquidity.is_zero()) {
+            (true, false) => true,
+            (false, true) => false,
+            _ => continue,
+        };
+        let Some(PositionEvent {
+            r#type: Some(CreatedPosition(position)),
+        }) = positions_store.get_last(format!("position_created:{token_id}"))
+        else {
+            continue;
+        };
+        let token_id = token_id.parse::<u64>().unwrap();
+        let (chunk, bit) = positions::bitset_position(token_id);
+        let value = if opened { bit } else { bit.neg() };
+        output.add(ordinal, format!("pool:{}:{chunk}", position.pool), value);
+    }
+}
+
+// Chunks of the pools holding open positions in `store_pool_positions`, as a bitset under `pool:{pool}`: the bit of
+// a chunk is set when its bitset goes above zero, and cleared when it goes back to zero
+#[substreams::handlers::store]
+pub fn store_pool_position_chunks(
+    pool_positions_deltas: Deltas<DeltaBigInt>, /* store_pool_positions */
+    output: StoreAddBigInt,
+) {
+    for delta in pool_positions_deltas.deltas {
+        let opened = match (delta.old_value.is_zero(), delta.new_value.is_zero()) {
+            (true, false) => true,
+            (false, true) => false,
+            _ => continue,
+        };
+        let (pool_key, chunk) = delta.key.rsplit_once(':').unwrap();
+        let bit = BigInt::one() << chunk.parse::<u32>().unwrap();
+        let value = if opened { bit } else { bit.neg() };
+        output.add(delta.ordinal, pool_key, value);
+    }
+}
+
+// Positions whose liquidity changed in the block, and the open positions whose range the tick of their pool swept
+// through in the block, get their token amounts recomputed at the price of their pool at the end of the block
+#[substreams::handlers::map]
+pub fn map_position_amounts(
+    events: Events,                                      /* map_extract_data_types */
+    pools_store: StoreGetProto<Pool>,                    /* store_pools_created */
+    pool_sqrt_price_store: StoreGetProto<PoolSqrtPrice>, /* store_pool_sqrt_price */
+    positions_store: StoreGetProto<PositionEvent>,       /* store_positions */
+    position_liquidities_store: StoreGetBigInt,          /* store_position_liquidities */
+    pool_positions_store: StoreGetBigInt,                /* store_pool_positions */
+    pool_position_chunks_store: StoreGetBigInt,          /* store_pool_position_chunks */
+    eth_prices_store: StoreGetBigDecimal,                /* store_eth_prices */
+) -> Result<PositionAmounts, Error> {
+    let mut token_ids: BTreeSet<String> = BTreeSet::new();
+    token_ids.extend(
+        events
+            .increase_liquidity_positions
+            .iter()
+            .map(|position| position.token_id.clone()),
+    );
+    token_ids.extend(
+        events
+            .decrease_liquidity_positions
+            .iter()
+            .map(|position| position.token_id.clone()),
+    );
+    let liquidity_changed = token_ids.clone();
+
+    let swept_ticks = positions::swept_ticks(&events.pool_sqrt_prices, &pool_sqrt_price_store);
+    for pool_address in swept_ticks.keys() {
+        token_ids.extend(positions::pool_positions(
+            &pool_positions_store,
+            &pool_position_chunks_store,
+            pool_address,
+        ));
+    }
+
+    let bundle_eth_price = eth_prices_store.get_last("bundle").unwrap_or_default();
+    let mut position_amounts = vec![];
+    for token_id in token_ids {
+        let position = match positions_store.get_last(format!("position_created:{token_id}")) {
+            Some(PositionEvent {
+                r#type: Some(CreatedPosition(position)),
+            }) => position,
+            _ => continue,
+        };
+        let liquidity = position_liquidities_store
+            .get_last(format!("position:{token_id}"))
+            .unwrap_or(BigInt::zero());
+        if !liquidity_changed.contains(&token_id) && !positions::range_swept(&position, swept_ticks.get(&position.pool))
+        {
+            continue;
+        }
+        let Some(sqrt_price) = pool_sqrt_price_store.get_last(format!("pool:{}", position.pool)) else {
+            continue;
+        };
+        let pool = pools_store.must_get_last(format!("pool:{}", position.pool));
+
+        let sqrt_price = BigInt::try_from(&sqrt_price.sqrt_price).unwrap();
+        let (amount0, amount1) = positions::position_amounts(&position, &pool, &liquidity, &sqrt_price);
+        let token0_derived_eth_price = eth_prices_store
+            .get_last(format!("token:{}:dprice:eth", position.token0))
+            .unwrap_or_default();
+        let token1_derived_eth_price = eth_prices_store
+            .get_last(format!("token:{}:dprice:eth", position.token1))
+            .unwrap_or_default();
+        let amount_usd = utils::calculate_amount_usd(
+            &amount0,
+            &amount1,
+            &token0_derived_eth_price,
+            &token1_derived_eth_price,
+            &bundle_eth_price,
+        );
+
+        position_amounts.push(PositionAmount {
+            token_id,
+            pool: position.pool,
+            amount0: amount0.to_string(),
+            amount1: amount1.to_string(),
+            amount_usd: amount_usd.to_string(),
+        });
+    }
+
+    Ok(PositionAmounts { position_amounts })
+}
+
+// Fee growths global of the pools under `pool:{pool}:token{i}`, outside of the ticks under
+// `tick:{pool}:{tick}:token{i}` and inside the range of the positions when they were last updated under
+// `position:{token_id}:token{i}`, with the tokens owed to the positions (`tokensOwed`) under
+// `position:{token_id}:owed{i}`
+#[substreams::handlers::store]
+pub fn store_fee_growth(events: Events, output: StoreSetBigInt) {
+    for update in events.fee_growth_global_updates {
+        output.set(
+            update.ordinal,
+            format!("pool:{}:token{}", update.pool_address, update.token_idx),
+            &BigInt::try_from(&update.new_value).unwrap(),
+        );
+    }
+
+    for update in events.fee_growth_outside_updates {
+        output.set(
+            update.ordinal,
+            format!(
+                "tick:{}:{}:token{}",
+                update.pool_address, update.tick_idx, update.token_idx
+            ),
+            &BigInt::try_from(&update.new_value).unwrap(),
+        );
+    }
+
+    let set_position_values = |ordinal: u64, token_id: &String, name: &str, values: [&Option<String>; 2]| {
+        for (token_idx, value) in values.into_iter().enumerate() {
+            if let Some(value) = value {
+                output.set(
+                    ordinal,
+                    format!("position:{token_id}:{name}{token_idx}"),
+                    &BigInt::try_from(value).unwrap(),
+                );
+            }
+        }
+    };
+    for position in &events.created_positions {
+        set_position_values(
+            position.log_ordinal,
+            &position.token_id,
+            "token",
+            [
+                &position.fee_growth_inside0_last_x128,
+                &position.fee_growth_inside1_last_x128,
+            ],
+        );
+    }
+    for position in &events.increase_liquidity_positions {
+        set_position_values(
+            position.log_ordinal,
+            &position.token_id,
+            "token",
+            [
+                &position.fee_growth_inside0_last_x128,
+                &position.fee_growth_inside1_last_x128,
+            ],
+        );
+        set_position_values(
+            position.log_ordinal,
+            &position.token_id,
+            "owed",
+            [&position.tokens_owed0, &position.tokens_owed1],
+        );
+    }
+    for position in &events.decrease_liquidity_positions {
+        set_position_values(
+            position.log_ordinal,
+            &position.token_id,
+            "token",
+            [
+                &position.fee_growth_inside0_last_x128,
+                &position.fee_growth_inside1_last_x128,
+            ],
+        );
+        set_position_values(
+            position.log_ordinal,
+            &position.token_id,
+            "owed",
+            [&position.tokens_owed0, &position.tokens_owed1],
+        );
+    }
+    for position in &events.collect_positions {
+        set_position_values(
+            position.log_ordinal,
+            &position.token_id,
+            "token",
+            [
+                &position.fee_growth_inside0_last_x128,
+                &position.fee_growth_inside1_last_x128,
+            ],
+        );
+        set_position_values(
+            position.log_ordinal,
+            &position.token_id,
+            "owed",
+            [&position.tokens_owed0, &position.tokens_owed1],
+        );
+    }
+}
+
+// Positions updated in the block, and the open positions whose range the tick of their pool went through while
+// its fee growth changed, get their fees not collected yet recomputed from their tokens owed and the fee growth
+// inside their range
+#[substreams::handlers::map]
+pub fn map_position_fees(
+    events: Events,                                      /* map_extract_data_types */
+    pools_store: StoreGetProto<Pool>,                    /* store_pools_created */
+    pool_sqrt_price_store: StoreGetProto<PoolSqrtPrice>, /* store_pool_sqrt_price */
+    fee_growth_store: StoreGetBigInt,                    /* store_fee_growth */
+    positions_store: StoreGetProto<PositionEvent>,       /* store_positions */
+    position_liquidities_store: StoreGetBigInt,          /* store_position_liquidities */
+    pool_positions_store: StoreGetBigInt,                /* store_pool_positions */
+    pool_position_chunks_store: StoreGetBigInt,          /* store_pool_position_chunks */
+    position_flows_store: StoreGetBigDecimal,            /* store_position_flows */
+    eth_prices_store: StoreGetBigDecimal,                /* store_eth_prices */
+) -> Result<PositionFees, Error> {
+    // ordinal of the last update of every position and pool
+    let mut ordinals: BTreeMap<String, u64> = BTreeMap::new();
+    for (token_id, ordinal) in events
+        .created_positions
+        .iter()
+        .map(|position| (&position.token_id, position.log_ordinal))
+        .chain(
+            events
+                .increase_liquidity_positions
+                .iter()
+                .map(|position| (&position.token_id, position.log_ordinal)),
+        )
+        .chain(
+            events
+                .decrease_liquidity_positions
+                .iter()
+                .map(|position| (&position.token_id, position.log_ordinal)),
+        )
+        .chain(
+            events
+                .collect_positions
+                .iter()
+                .map(|position| (&position.token_id, position.log_ordinal)),
+        )
+    {
+        ordinals.insert(token_id.clone(), ordinal);
+    }
+    let updated_positions: BTreeSet<String> = ordinals.keys().cloned().collect();
+
+    let swept_ticks = positions::swept_ticks(&events.pool_sqrt_prices, &pool_sqrt_price_store);
+    let mut pool_ordinals: BTreeMap<&String, u64> = BTreeMap::new();
+    for update in &events.fee_growth_global_updates {
+        if swept_ticks.contains_key(&update.pool_address) {
+            pool_ordinals.insert(&update.pool_address, update.ordinal);
+        }
+    }
+    for (pool_address, ordinal) in &pool_ordinals {
+        for token_id in positions::pool_positions(&pool_positions_store, &pool_position_chunks_store, pool_address) {
+            let position_ordinal = ordinals.entry(token_id).or_insert(*ordinal);
+            *position_ordinal = (*position_ordinal).max(*ordinal);
+        }
+    }
+
+    let bundle_eth_price = eth_prices_store.get_last("bundle").unwrap_or_default();
+    let mut ranges: BTreeSet<(String, String, String)> = BTreeSet::new();
+    let mut position_fees = PositionFees::default();
+    for (token_id, ordinal) in ordinals {
+        let position = match positions_store.get_last(format!("position_created:{token_id}")) {
+            Some(PositionEvent {
+                r#type: Some(CreatedPosition(position)),
+            }) => position,
+            _ => continue,
+        };
+        let liquidity = position_liquidities_store
+            .get_last(format!("position:{token_id}"))
+            .unwrap_or(BigInt::zero());
+        if !updated_positions.contains(&token_id) && !positions::range_swept(&position, swept_ticks.get(&position.pool))
+        {
+            continue;
+        }
+        let Some(sqrt_price) = pool_sqrt_price_store.get_last(format!("pool:{}", position.pool)) else {
+            continue;
+        };
+        let pool = pools_store.must_get_last(format!("pool:{}", position.pool));
+
+        let tick_current = sqrt_price.tick.parse::<i32>().unwrap();
+        let new_range = ranges.insert((
+            position.pool.clone(),
+            position.tick_lower.clone(),
+            position.tick_upper.clone(),
+        ));
+        let tokens_owed = positions::earned_fees(&position, &liquidity, tick_current, &fee_growth_store);
+        if new_range {
+            for (token_idx, (fee_growth_inside, _)) in tokens_owed.iter().enumerate() {
+                position_fees.fee_growth_inside_updates.push(events::FeeGrowthInside {
+                    pool_address: position.pool.clone(),
+                    tick_lower: position.tick_lower.parse::<i32>().unwrap(),
+                    tick_upper: position.tick_upper.parse::<i32>().unwrap(),
+                    ordinal,
+                    new_value: fee_growth_inside.to_string(),
+                    token_idx: token_idx as i32,
+                });
+            }
+        }
+
+        let flows = positions::PositionFlows::load(&position_flows_store, &token_id);
+        let [unclaimed_fees_token0, unclaimed_fees_token1] = positions::unclaimed_fees(&tokens_owed, &pool, &flows);
+        let token0_derived_eth_price = eth_prices_store
+            .get_last(format!("token:{}:dprice:eth", position.token0))
+            .unwrap_or_default();
+        let token1_derived_eth_price = eth_prices_store
+            .get_last(format!("token:{}:dprice:eth", position.token1))
+            .unwrap_or_default();
+        let unclaimed_fees_usd = utils::calculate_amount_usd(
+            &unclaimed_fees_token0,
+            &unclaimed_fees_token1,
+            &token0_derived_eth_price,
+            &token1_derived_eth_price,
+            &bundle_eth_price,
+        );
+
+        position_fees.position_fees.push(PositionFee {
+            token_id,
+            pool: position.pool,
+            unclaimed_fees_token0: unclaimed_fees_token0.to_string(),
+            unclaimed_fees_token1: unclaimed_fees_token1.to_string(),
+            unclaimed_fees_usd: unclaimed_fees_usd.to_string(),
+        });
+    }
+
+    Ok(position_fees)
+}
+
+// Cumulated deposits, withdrawals and collects of the positions under `position:{token_id}:{flow}`, with the
+// USD value of the deposits and of the collects at the prices of their block
+#[substreams::handlers::store]
+pub fn store_position_flows(
+    events: Events,
+    positions_store: StoreGetProto<PositionEvent>,
+    eth_prices_store: StoreGetBigDecimal,
+    output: StoreAddBigDecimal,
+) {
+    let bundle_eth_price = eth_prices_store.get_last("bundle").unwrap_or_default();
+    let amount_usd = |token_id: &String, amount0: &BigDecimal, amount1: &BigDecimal| {
+        let Some(PositionEvent {
+            r#type: Some(CreatedPosition(position)),
+        }) = positions_store.get_last(format!("position_created:{token_id}"))
+        else {
+            return BigDecimal::zero();
+        };
+        let token0_derived_eth_price = eth_prices_store
+            .get_last(format!("token:{}:dprice:eth", position.token0))
+            .unwrap_or_default();
+        let token1_derived_eth_price = eth_prices_store
+            .get_last(format!("token:{}:dprice:eth", position.token1))
+            .unwrap_or_default();
+        utils::calculate_amount_usd(
+            amount0,
+            amount1,
+            &token0_derived_eth_price,
+            &token1_derived_eth_price,
+            &bundle_eth_price,
+        )
+    };
+
+    for position in events.increase_liquidity_positions {
+        let amount0 = BigDecimal::try_from(position.deposited_token0.as_str()).unwrap();
+        let amount1 = BigDecimal::try_from(position.deposited_token1.as_str()).unwrap();
+        let token_id = &position.token_id;
+        output.add(
+            position.log_ordinal,
+            format!("position:{token_id}:depositedUSD"),
+            amount_usd(token_id, &amount0, &amount1),
+        );
+        output.add(position.log_ordinal, format!("position:{token_id}:deposited0"), amount0);
+        output.add(position.log_ordinal, format!("position:{token_id}:deposited1"), amount1);
+    }
+
+    for position in events.decrease_liquidity_positions {
+        let token_id = &position.token_id;
+        output.add(
+            position.log_ordinal,
+            format!("position:{token_id}:withdrawn0"),
+            BigDecimal::try_from(position.withdrawn_token0.as_str()).unwrap(),
+        );
+        output.add(
+            position.log_ordinal,
+            format!("position:{token_id}:withdrawn1"),
+            BigDecimal::try_from(position.withdrawn_token1.as_str()).unwrap(),
+        );
+    }
+
+    for position in events.collect_positions {
+        let amount0 = BigDecimal::try_from(position.collected_fees_token0.as_str()).unwrap();
+        let amount1 = BigDecimal::try_from(position.collected_fees_token1.as_str()).unwrap();
+        let token_id = &position.token_id;
+        output.add(
+            position.log_ordinal,
+            format!("position:{token_id}:collectedUSD"),
+            amount_usd(token_id, &amount0, &amount1),
+        );
+        output.add(position.log_ordinal, format!("position:{token_id}:collected0"), amount0);
+        output.add(position.log_ordinal, format!("position:{token_id}:collected1"), amount1);
+    }
+}
+
+// Profit and loss of the positions whose amounts or fees changed in the block. Positions present in only one of
+// `map_position_amounts` and `map_position_fees` get the other side computed here.
+#[substreams::handlers::map]
+pub fn map_position_pnls(
+    position_amounts: PositionAmounts,                   /* map_position_amounts */
+    position_fees: PositionFees,                         /* map_position_fees */
+    pools_store: StoreGetProto<Pool>,                    /* store_pools_created */
+    pool_sqrt_price_store: StoreGetProto<PoolSqrtPrice>, /* store_pool_sqrt_price */
+    fee_growth_store: StoreGetBigInt,                    /* store_fee_growth */
+    positions_store: StoreGetProto<PositionEvent>,       /* store_positions */
+    position_liquidities_store: StoreGetBigInt,          /* store_position_liquidities */
+    position_flows_store: StoreGetBigDecimal,            /* store_position_flows */
+    eth_prices_store: StoreGetBigDecimal,                /* store_eth_prices */
+) -> Result<PositionPnls, Error> {
+    let decimal = |value: &String| BigDecimal::try_from(value.as_str()).unwrap();
+    let mut amounts: HashMap<String, [BigDecimal; 2]> = position_amounts
+        .position_amounts
+        .into_iter()
+        .map(|amount| {
+            let amounts = [decimal(&amount.amount0), decimal(&amount.amount1)];
+            (amount.token_id, amounts)
+        })
+        .collect();
+    let mut unclaimed_fees: HashMap<String, [BigDecimal; 2]> = position_fees
+        .position_fees
+        .into_iter()
+        .map(|fee| {
+            let fees = [decimal(&fee.unclaimed_fees_token0), decimal(&fee.unclaimed_fees_token1)];
+            (fee.token_id, fees)
+        })
+        .collect();
+
+    let token_ids: BTreeSet<String> = amounts.keys().chain(unclaimed_fees.keys()).cloned().collect();
+
+    let bundle_eth_price = eth_prices_store.get_last("bundle").unwrap_or_default();
+    let mut position_pnls = vec![];
+    for token_id in token_ids {
+        let position = match positions_store.get_last(format!("position_created:{token_id}")) {
+            Some(PositionEvent {
+                r#type: Some(CreatedPosition(position)),
+            }) => position,
+            _ => continue,
+        };
+        let pool = pools_store.must_get_last(format!("pool:{}", position.pool));
+        let liquidity = position_liquidities_store
+            .get_last(format!("position:{token_id}"))
+            .unwrap_or(BigInt::zero());
+        let sqrt_price = pool_sqrt_price_store.get_last(format!("pool:{}", position.pool));
+
+        let position_amounts = match (amounts.remove(&token_id), &sqrt_price) {
+            (Some(amounts), _) => amounts,
+            (None, Some(sqrt_price)) => {
+                let sqrt_price = BigInt::try_from(&sqrt_price.sqrt_price).unwrap();
+                let (amount0, amount1) = positions::position_amounts(&position, &pool, &liquidity, &sqrt_price);
+                [amount0, amount1]
+            }
+            (None, None) => [BigDecimal::zero(), BigDecimal::zero()],
+        };
+        let flows = positions::PositionFlows::load(&position_flows_store, &token_id);
+        let position_unclaimed_fees = match (unclaimed_fees.remove(&token_id), &sqrt_price) {
+            (Some(fees), _) => fees,
+            (None, Some(sqrt_price)) => {
+                let tick_current = sqrt_price.tick.parse::<i32>().unwrap();
+                let tokens_owed = positions::earned_fees(&position, &liquidity, tick_current, &fee_growth_store);
+                positions::unclaimed_fees(&tokens_owed, &pool, &flows)
+            }
+            (None, None) => [BigDecimal::zero(), BigDecimal::zero()],
+        };
+        let prices = [&position.token0, &position.token1].map(|token_address| {
+            eth_prices_store
+                .get_last(format!("token:{token_address}:dprice:eth"))
+                .unwrap_or_default()
+                .mul(bundle_eth_price.clone())
+        });
+
+        position_pnls.push(PositionPnl {
+            token_id,
+            pool: position.pool,
+            ..positions::position_pnl(&flows, &liquidity, &position_amounts, &position_unclaimed_fees, &prices)
+        });
+    }
+
+    Ok(PositionPnls { position_pnls })
+}
+
 #[substreams::handlers::store]
 pub fn store_positions(events: Events, output: StoreSetProto<PositionEvent>) {
     let mut positions_events: Vec<PositionEvent> = vec![];
@@ -1237,6 +2697,7 @@ pub fn store_max_windows(
 
 #[substreams::handlers::map]
 pub fn graph_out(
+    params: String,
     clock: Clock,
     pool_count_deltas: Deltas<DeltaBigInt>,              /* store_pool_count */
     tx_count_deltas: Deltas<DeltaBigInt>,                /* store_total_tx_counts deltas */
@@ -1260,14 +2721,29 @@ pub fn graph_out(
     store_positions: StoreGetProto<PositionEvent>,       /* store_positions */
     min_windows_deltas: Deltas<DeltaBigDecimal>,         /* store_min_windows */
     max_windows_deltas: Deltas<DeltaBigDecimal>,         /* store_max_windows */
+    pool_protocol_fees_deltas: Deltas<DeltaBigDecimal>,  /* store_pool_protocol_fees */
+    factory_events: FactoryEvents,                       /* map_factory_events */
+    pool_oracle_deltas: Deltas<DeltaBigInt>,             /* store_pool_oracle */
+    token_prices_deltas: Deltas<DeltaProto<TokenPrice>>, /* store_token_prices */
+    token_supply_deltas: Deltas<DeltaBigInt>,            /* store_token_supply */
+    pool_balances_deltas: Deltas<DeltaBigDecimal>,       /* store_pool_balances */
+    position_amounts: PositionAmounts,                   /* map_position_amounts */
+    position_fees: PositionFees,                         /* map_position_fees */
+    position_pnls: PositionPnls,                         /* map_position_pnls */
+    candidates_store: StoreGetString,                    /* store_whitelist_candidates */
 ) -> Result<EntityChanges, Error> {
+    let config = Config::from_params(&params)?;
+    let factory_id = config.factory_id();
+    let whitelist = whitelist::Whitelist {
+        block_number: clock.number,
+        candidates_store: &candidates_store,
+        config: &config,
+    };
     let mut tables = Tables::new();
     let timestamp = clock.timestamp.unwrap().seconds;
 
-    if clock.number == 12369621 {
-        // FIXME: Hard-coded start block, how could we pull that from the manifest?
-        // FIXME: ideally taken from the params of the module
-        db::factory_created_factory_entity_change(&mut tables);
+    if clock.number == config.start_block {
+        db::factory_created_factory_entity_change(&mut tables, &factory_id);
         db::created_bundle_entity_change(&mut tables);
     }
 
@@ -1275,10 +2751,14 @@ pub fn graph_out(
     db::bundle_store_eth_price_usd_bundle_entity_change(&mut tables, &derived_eth_prices_deltas);
 
     // Factory:
-    db::pool_created_factory_entity_change(&mut tables, &pool_count_deltas);
-    db::tx_count_factory_entity_change(&mut tables, &tx_count_deltas);
-    db::swap_volume_factory_entity_change(&mut tables, &swaps_volume_deltas);
-    db::tvl_factory_entity_change(&mut tables, &derived_factory_tvl_deltas);
+    db::pool_created_factory_entity_change(&mut tables, &pool_count_deltas, &factory_id);
+    db::tx_count_factory_entity_change(&mut tables, &tx_count_deltas, &factory_id);
+    db::swap_volume_factory_entity_change(&mut tables, &swaps_volume_deltas, &factory_id);
+    db::tvl_factory_entity_change(&mut tables, &derived_factory_tvl_deltas, &factory_id);
+    db::owner_changed_factory_entity_change(&mut tables, &factory_events.owners_changed, &factory_id);
+
+    // FeeTier:
+    db::fee_amount_enabled_fee_tier_entity_change(&mut tables, &factory_events.fee_amounts_enabled);
 
     // Pool:
     db::pools_created_pool_entity_changes(&mut tables, &pools_created);
@@ -1287,9 +2767,12 @@ pub fn graph_out(
     db::fee_growth_global_pool_entity_change(&mut tables, &events.fee_growth_global_updates);
     db::total_value_locked_pool_entity_change(&mut tables, &derived_tvl_deltas);
     db::total_value_locked_by_token_pool_entity_change(&mut tables, &token_tvl_deltas);
+    db::balances_pool_entity_change(&mut tables, &pool_balances_deltas);
     db::price_pool_entity_change(&mut tables, &price_deltas);
     db::tx_count_pool_entity_change(&mut tables, &tx_count_deltas);
     db::swap_volume_pool_entity_change(&mut tables, &swaps_volume_deltas);
+    db::protocol_fees_pool_entity_change(&mut tables, &pool_protocol_fees_deltas);
+    db::oracle_pool_entity_change(&mut tables, &pool_oracle_deltas);
 
     // Tokens:
     db::tokens_created_token_entity_changes(&mut tables, &pools_created, tokens_store);
@@ -1298,7 +2781,15 @@ pub fn graph_out(
     db::total_value_locked_by_token_token_entity_change(&mut tables, &token_tvl_deltas);
     db::total_value_locked_usd_token_entity_change(&mut tables, &derived_tvl_deltas);
     db::derived_eth_prices_token_entity_change(&mut tables, &derived_eth_prices_deltas);
+    db::stale_price_token_entity_change(
+        &mut tables,
+        timestamp,
+        config.stale_price_days,
+        &derived_eth_prices_deltas,
+    );
+    db::price_provenance_token_entity_change(&mut tables, &token_prices_deltas);
     db::whitelist_token_entity_change(&mut tables, tokens_whitelist_pools_deltas);
+    db::total_supply_token_entity_change(&mut tables, &token_supply_deltas);
 
     // Tick:
     db::create_tick_entity_change(&mut tables, &events.ticks_created);
@@ -1317,6 +2808,9 @@ pub fn graph_out(
     db::decrease_liquidity_position_entity_change(&mut tables, &events.decrease_liquidity_positions);
     db::collect_position_entity_change(&mut tables, &events.collect_positions);
     db::transfer_position_entity_change(&mut tables, &events.transfer_positions);
+    db::amounts_position_entity_change(&mut tables, &position_amounts);
+    db::unclaimed_fees_position_entity_change(&mut tables, &position_fees);
+    db::pnl_position_entity_change(&mut tables, &position_pnls);
 
     // PositionSnapshot:
     // TODO: validate all the snapshot positions here
@@ -1340,16 +2834,22 @@ pub fn graph_out(
         &events.transfer_positions,
         &store_positions,
     );
+    db::pnl_snapshot_position_entity_change(&mut tables, clock.number, &position_pnls, &events);
 
     // Transaction:
     db::transaction_entity_change(&mut tables, &events.transactions);
 
     // Swap, Mint, Burn:
-    db::swaps_mints_burns_created_entity_change(&mut tables, &events.pool_events, tx_count_store, store_eth_prices);
+    db::swaps_mints_burns_created_entity_change(
+        &mut tables,
+        &events.pool_events,
+        &tx_count_store,
+        &store_eth_prices,
+        &whitelist,
+    );
 
     // Flashes:
-    // TODO: should we implement flashes entity change - UNISWAP has not done this part
-    // db::flashes_update_pool_fee_entity_change(&mut tables, events.flashes);
+    db::flashes_created_entity_change(&mut tables, &events.flashes, &store_eth_prices);
 
     // Uniswap day data:
     db::uniswap_day_data_create(&mut tables, &tx_count_deltas);
@@ -1375,6 +2875,7 @@ pub fn graph_out(
         &derived_tvl_deltas,
         &min_windows_deltas,
         &max_windows_deltas,
+        &pool_oracle_deltas,
     );
 
     // Token Day/Hour data:
@@ -0,0 +1,91 @@
+use crate::math::fixed_point::Q128;
+use crate::math::full_math::mul_div;
+use ethabi::ethereum_types::U256;
+
+// Port of `Tick.getFeeGrowthInside` and of the fees owed of `Position.update`. Fee growths are Q128.128
+// numbers of the fees earned per unit of liquidity, their differences wrap around like the uint256 of the
+// contracts: only the difference between two readings is meaningful.
+
+// Fee growth inside the range [tick_lower, tick_upper) from the fee growth global of the pool and the fee
+// growths outside of the ticks of the range
+pub fn get_fee_growth_inside(
+    tick_lower: i32,
+    tick_upper: i32,
+    tick_current: i32,
+    fee_growth_global_x128: U256,
+    fee_growth_outside_lower_x128: U256,
+    fee_growth_outside_upper_x128: U256,
+) -> U256 {
+    let fee_growth_below = match tick_current >= tick_lower {
+        true => fee_growth_outside_lower_x128,
+        false => fee_growth_global_x128.overflowing_sub(fee_growth_outside_lower_x128).0,
+    };
+    let fee_growth_above = match tick_current < tick_upper {
+        true => fee_growth_outside_upper_x128,
+        false => fee_growth_global_x128.overflowing_sub(fee_growth_outside_upper_x128).0,
+    };
+
+    fee_growth_global_x128
+        .overflowing_sub(fee_growth_below)
+        .0
+        .overflowing_sub(fee_growth_above)
+        .0
+}
+
+// Fees earned by `liquidity` since the fee growth inside was `fee_growth_inside_last_x128`
+pub fn get_fees_owed(fee_growth_inside_x128: U256, fee_growth_inside_last_x128: U256, liquidity: u128) -> U256 {
+    let fee_growth = fee_growth_inside_x128.overflowing_sub(fee_growth_inside_last_x128).0;
+    mul_div(fee_growth, U256::from(liquidity), Q128)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::math::fee_growth::{get_fee_growth_inside, get_fees_owed};
+    use crate::math::fixed_point::Q128;
+    use ethabi::ethereum_types::U256;
+
+    #[test]
+    fn test_get_fee_growth_inside() {
+        let global = U256::from(15);
+        let growth = |value: u64| U256::from(value);
+
+        // in range: the outsides of both ticks are away from the current tick
+        assert_eq!(
+            growth(10),
+            get_fee_growth_inside(-10, 10, 0, global, growth(2), growth(3))
+        );
+        // below the range: the outside of the lower tick is above it
+        assert_eq!(
+            growth(11),
+            get_fee_growth_inside(-10, 10, -20, global, growth(14), growth(3))
+        );
+        // above the range: the outside of the upper tick is below it
+        assert_eq!(
+            growth(12),
+            get_fee_growth_inside(-10, 10, 20, global, growth(2), growth(14))
+        );
+    }
+
+    #[test]
+    fn test_get_fee_growth_inside_wraps_around() {
+        // the outside of the ticks was set when the global was higher than the sum, it underflows
+        let inside = get_fee_growth_inside(-10, 10, 0, U256::from(5), U256::from(4), U256::from(3));
+        assert_eq!(U256::MAX - 1, inside);
+
+        // the difference with the previous reading is still right
+        let last = U256::MAX - 11;
+        assert_eq!(U256::from(10), inside.overflowing_sub(last).0);
+    }
+
+    #[test]
+    fn test_get_fees_owed() {
+        let liquidity = 1_000_000u128;
+        assert_eq!(U256::from(3_000_000), get_fees_owed(Q128 * 5, Q128 * 2, liquidity));
+        // across the wraparound of the fee growth
+        assert_eq!(
+            U256::from(2_000_000),
+            get_fees_owed(Q128, U256::MAX - Q128 + 1, liquidity)
+        );
+        assert_eq!(U256::zero(), get_fees_owed(Q128, Q128, liquidity));
+    }
+}
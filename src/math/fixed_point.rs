@@ -0,0 +1,62 @@
+use ethabi::ethereum_types::U256;
+use substreams::scalar::{BigDecimal, BigInt};
+
+// Q64.96 and Q128.128 fixed-point numbers of the contracts, e.g. the sqrt prices and the fee growths
+pub const RESOLUTION_96: u32 = 96;
+pub const Q96: U256 = U256([0, 1 << 32, 0, 0]);
+pub const Q128: U256 = U256([0, 0, 1, 0]);
+
+pub fn to_big_int(value: U256) -> BigInt {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    BigInt::from_unsigned_bytes_be(&bytes)
+}
+
+pub fn from_big_int(value: &BigInt) -> U256 {
+    let (_, bytes) = value.to_bytes_be();
+    assert!(value >= &BigInt::zero() && bytes.len() <= 32, "{value} isn't a uint256");
+    U256::from_big_endian(&bytes)
+}
+
+// Exact decimal value of a fixed-point number with `resolution` fractional bits,
+// value / 2^resolution being value * 5^resolution / 10^resolution
+pub fn to_decimal(value: BigInt, resolution: u32) -> BigDecimal {
+    BigDecimal::new(value * BigInt::from(5).pow(resolution), -(resolution as i64))
+}
+
+// Price of token0 in token1, in raw token units, of a Q64.96 sqrt price
+pub fn sqrt_price_x96_to_price(sqrt_price_x96: U256) -> BigDecimal {
+    let sqrt_price = to_big_int(sqrt_price_x96);
+    to_decimal(sqrt_price.clone() * sqrt_price, 2 * RESOLUTION_96)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::math::fixed_point::{from_big_int, sqrt_price_x96_to_price, to_big_int, to_decimal, Q128, Q96};
+    use ethabi::ethereum_types::U256;
+    use std::str::FromStr;
+    use substreams::scalar::{BigDecimal, BigInt};
+
+    #[test]
+    fn test_constants() {
+        assert_eq!(U256::one() << 96, Q96);
+        assert_eq!(U256::one() << 128, Q128);
+    }
+
+    #[test]
+    fn test_big_int_conversions() {
+        let value = BigInt::from_str("1461446703485210103287273052203988822378723970342").unwrap();
+        assert_eq!(value, to_big_int(from_big_int(&value)));
+        assert_eq!(BigInt::zero(), to_big_int(U256::zero()));
+    }
+
+    #[test]
+    fn test_to_decimal() {
+        assert_eq!(
+            BigDecimal::from_str("1.5").unwrap(),
+            to_decimal(BigInt::from(3) << 95, 96)
+        );
+        assert_eq!(BigDecimal::one(), sqrt_price_x96_to_price(Q96));
+        assert_eq!(BigDecimal::from(4), sqrt_price_x96_to_price(Q96 * 2));
+    }
+}
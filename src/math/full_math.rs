@@ -0,0 +1,29 @@
+use ethabi::ethereum_types::{U256, U512};
+
+// Port of `FullMath.mulDiv`: products are computed on 512 bits so `a * b / denominator` is exact
+// as long as the result fits in 256 bits, which is asserted like the contracts revert.
+
+pub fn mul_div(a: U256, b: U256, denominator: U256) -> U256 {
+    assert!(!denominator.is_zero(), "mul_div by zero");
+    U256::try_from(a.full_mul(b) / U512::from(denominator)).expect("mul_div overflow")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::math::full_math::mul_div;
+    use ethabi::ethereum_types::U256;
+
+    #[test]
+    fn test_mul_div() {
+        let q128 = U256::one() << 128;
+        // the product overflows 256 bits but the result doesn't
+        assert_eq!(q128 * 4, mul_div(q128, q128 * 1000, q128 * 250));
+        assert_eq!(U256::from(3), mul_div(U256::from(10), U256::from(2), U256::from(6)));
+    }
+
+    #[test]
+    #[should_panic(expected = "mul_div overflow")]
+    fn test_mul_div_overflow() {
+        mul_div(U256::MAX, U256::from(2), U256::one());
+    }
+}
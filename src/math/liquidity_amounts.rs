@@ -0,0 +1,68 @@
+use crate::math::sqrt_price_math::{get_amount0_delta, get_amount1_delta};
+use ethabi::ethereum_types::U256;
+
+// Port of the periphery `LiquidityAmounts.getAmountsForLiquidity`: token amounts held by `liquidity`
+// between the sqrt prices `sqrt_ratio_a_x96` and `sqrt_ratio_b_x96` at the sqrt price `sqrt_price_x96`,
+// rounded down. Below the range the position is all token0, above it all token1.
+pub fn get_amounts_for_liquidity(
+    sqrt_price_x96: U256,
+    sqrt_ratio_a_x96: U256,
+    sqrt_ratio_b_x96: U256,
+    liquidity: u128,
+) -> (U256, U256) {
+    let (sqrt_ratio_a_x96, sqrt_ratio_b_x96) = match sqrt_ratio_a_x96 > sqrt_ratio_b_x96 {
+        true => (sqrt_ratio_b_x96, sqrt_ratio_a_x96),
+        false => (sqrt_ratio_a_x96, sqrt_ratio_b_x96),
+    };
+
+    if sqrt_price_x96 <= sqrt_ratio_a_x96 {
+        (
+            get_amount0_delta(sqrt_ratio_a_x96, sqrt_ratio_b_x96, liquidity),
+            U256::zero(),
+        )
+    } else if sqrt_price_x96 < sqrt_ratio_b_x96 {
+        (
+            get_amount0_delta(sqrt_price_x96, sqrt_ratio_b_x96, liquidity),
+            get_amount1_delta(sqrt_ratio_a_x96, sqrt_price_x96, liquidity),
+        )
+    } else {
+        (
+            U256::zero(),
+            get_amount1_delta(sqrt_ratio_a_x96, sqrt_ratio_b_x96, liquidity),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::math::fixed_point::Q96;
+    use crate::math::liquidity_amounts::get_amounts_for_liquidity;
+    use ethabi::ethereum_types::U256;
+
+    #[test]
+    fn test_get_amounts_for_liquidity() {
+        // 1 ether of liquidity, sqrt prices of 99/110 and 121/100
+        let liquidity = 1_000_000_000_000_000_000u128;
+        let sqrt_price_99_110 = U256::from_dec_str("75162434512514376853788284022").unwrap();
+        let sqrt_price_121_100 = U256::from_dec_str("87150978765690771352898345369").unwrap();
+
+        // in range: token0 above the price and token1 below it
+        assert_eq!(
+            (
+                U256::from_dec_str("90909090909090909").unwrap(),
+                U256::from_dec_str("51316701949486231").unwrap()
+            ),
+            get_amounts_for_liquidity(Q96, sqrt_price_99_110, sqrt_price_121_100, liquidity)
+        );
+        // below the range: all token0
+        assert_eq!(
+            (U256::from_dec_str("90909090909090909").unwrap(), U256::zero()),
+            get_amounts_for_liquidity(Q96 / 10, Q96, sqrt_price_121_100, liquidity)
+        );
+        // above the range, with the bounds in any order: all token1
+        assert_eq!(
+            (U256::zero(), U256::from_dec_str("99999999999999999").unwrap()),
+            get_amounts_for_liquidity(Q96 * 10, sqrt_price_121_100, Q96, liquidity)
+        );
+    }
+}
@@ -1,28 +1,18 @@
-use crate::ticks_idx::ONE_POINT_0001;
 use std::ops::{Div, Mul};
 use substreams::scalar::BigDecimal;
 
-pub fn compute_price_from_tick_idx(desired_tick_idx: i32) -> BigDecimal {
-    if desired_tick_idx == 0 {
-        return BigDecimal::one();
-    }
-
-    let desired_tick_idx_abs = desired_tick_idx.abs();
-    let base_abs = desired_tick_idx_abs - (desired_tick_idx_abs % 1000);
-    let ratio = BigDecimal::try_from(1.0001).unwrap().with_prec(100);
-    let mut val = BigDecimal::try_from(*ONE_POINT_0001.get(&base_abs).unwrap()).unwrap();
-
-    let mut idx = base_abs;
-    while idx < desired_tick_idx_abs {
-        val = val.mul(ratio.clone()).with_prec(100);
-        idx += 1;
-    }
-
-    if desired_tick_idx.lt(&0) {
-        val = safe_div(&BigDecimal::one(), &val).with_prec(100);
-    }
+// Integer ports of the Uniswap v3 core libraries, the values are converted to decimals only at the edge
+pub mod fee_growth;
+pub mod fixed_point;
+pub mod full_math;
+pub mod liquidity_amounts;
+pub mod sqrt_price_math;
+pub mod tick_math;
 
-    return val.with_prec(100);
+// Price of token0 in token1, in raw token units, at a tick: the exact square of its Q64.96 sqrt price
+pub fn compute_price_from_tick_idx(desired_tick_idx: i32) -> BigDecimal {
+    let sqrt_price_x96 = tick_math::get_sqrt_ratio_at_tick(desired_tick_idx);
+    fixed_point::sqrt_price_x96_to_price(sqrt_price_x96).with_prec(100)
 }
 
 pub fn safe_div(amount0: &BigDecimal, amount1: &BigDecimal) -> BigDecimal {
@@ -58,7 +48,7 @@ mod test {
         let tick_idx = 257820;
         let actual_value = compute_price_from_tick_idx(tick_idx);
         let expected_value = BigDecimal::from_str(
-            "157188409912.8279800665572784382799429044388135818770675416117949775512036146406973508030483126972441",
+            "157188409912.8279800665572784382802824189851524136901567978785323024664618845607187925850704752188195",
         )
         .unwrap();
         assert_eq!(expected_value, actual_value);
@@ -69,7 +59,7 @@ mod test {
         let tick_idx = -16200;
         let actual_value = compute_price_from_tick_idx(tick_idx);
         let expected_value = BigDecimal::from_str(
-            "0.1979147284588052764428880652056914101428568621377186361720748341060154174725108347202390730358454528",
+            "0.1979147284588052764428880652141991309927214906085661138506442956779146604750589603787560218716630898",
         )
         .unwrap();
         assert_eq!(expected_value, actual_value);
@@ -0,0 +1,57 @@
+use crate::math::fixed_point::{Q96, RESOLUTION_96};
+use crate::math::full_math::mul_div;
+use ethabi::ethereum_types::U256;
+
+// Port of `SqrtPriceMath.getAmount0Delta` and `getAmount1Delta`: token amounts between two Q64.96 sqrt prices
+// for some liquidity, rounded down like the amounts held by a position.
+
+// Amount of token0 between two sqrt prices: liquidity * (sqrt_b - sqrt_a) / (sqrt_a * sqrt_b)
+pub fn get_amount0_delta(sqrt_ratio_a_x96: U256, sqrt_ratio_b_x96: U256, liquidity: u128) -> U256 {
+    let (sqrt_ratio_a_x96, sqrt_ratio_b_x96) = sorted(sqrt_ratio_a_x96, sqrt_ratio_b_x96);
+    assert!(!sqrt_ratio_a_x96.is_zero());
+
+    let numerator1 = U256::from(liquidity) << RESOLUTION_96;
+    let numerator2 = sqrt_ratio_b_x96 - sqrt_ratio_a_x96;
+    mul_div(numerator1, numerator2, sqrt_ratio_b_x96) / sqrt_ratio_a_x96
+}
+
+// Amount of token1 between two sqrt prices: liquidity * (sqrt_b - sqrt_a)
+pub fn get_amount1_delta(sqrt_ratio_a_x96: U256, sqrt_ratio_b_x96: U256, liquidity: u128) -> U256 {
+    let (sqrt_ratio_a_x96, sqrt_ratio_b_x96) = sorted(sqrt_ratio_a_x96, sqrt_ratio_b_x96);
+    mul_div(U256::from(liquidity), sqrt_ratio_b_x96 - sqrt_ratio_a_x96, Q96)
+}
+
+fn sorted(a: U256, b: U256) -> (U256, U256) {
+    match a > b {
+        true => (b, a),
+        false => (a, b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::math::fixed_point::Q96;
+    use crate::math::sqrt_price_math::{get_amount0_delta, get_amount1_delta};
+    use ethabi::ethereum_types::U256;
+
+    fn ether(amount: u64) -> U256 {
+        U256::from(amount) * U256::exp10(18)
+    }
+
+    #[test]
+    fn test_get_amount_deltas() {
+        // from the Uniswap v3 core tests: 1 ether of liquidity between prices 1 and 1.21
+        let sqrt_price_121_100 = U256::from_dec_str("87150978765690771352898345369").unwrap();
+        let liquidity = ether(1).as_u128();
+
+        assert_eq!(
+            U256::from_dec_str("90909090909090909").unwrap(),
+            get_amount0_delta(Q96, sqrt_price_121_100, liquidity)
+        );
+        assert_eq!(
+            U256::from_dec_str("99999999999999999").unwrap(),
+            get_amount1_delta(sqrt_price_121_100, Q96, liquidity)
+        );
+        assert_eq!(U256::zero(), get_amount0_delta(Q96, Q96, liquidity));
+    }
+}
@@ -0,0 +1,92 @@
+use ethabi::ethereum_types::U256;
+
+// Port of `TickMath`: sqrt prices are Q64.96 numbers of sqrt(1.0001^tick), computed exactly like the
+// contracts do so the results are bit-identical.
+
+pub const MIN_TICK: i32 = -887272;
+pub const MAX_TICK: i32 = -MIN_TICK;
+
+// 1 / sqrt(1.0001^(2^i)) as Q128.128 numbers, for i in 0..20
+const RATIOS: [u128; 20] = [
+    0xfffcb933bd6fad37aa2d162d1a594001,
+    0xfff97272373d413259a46990580e213a,
+    0xfff2e50f5f656932ef12357cf3c7fdcc,
+    0xffe5caca7e10e4e61c3624eaa0941cd0,
+    0xffcb9843d60f6159c9db58835c926644,
+    0xff973b41fa98c081472e6896dfb254c0,
+    0xff2ea16466c96a3843ec78b326b52861,
+    0xfe5dee046a99a2a811c461f1969c3053,
+    0xfcbe86c7900a88aedcffc83b479aa3a4,
+    0xf987a7253ac413176f2b074cf7815e54,
+    0xf3392b0822b70005940c7a398e4b70f3,
+    0xe7159475a2c29b7443b29c7fa6e889d9,
+    0xd097f3bdfd2022b8845ad8f792aa5825,
+    0xa9f746462d870fdf8a65dc1f90e061e5,
+    0x70d869a156d2a1b890bb3df62baf32f7,
+    0x31be135f97d08fd981231505542fcfa6,
+    0x9aa508b5b7a84e1c677de54f3e99bc9,
+    0x5d6af8dedb81196699c329225ee604,
+    0x2216e584f5fa1ea926041bedfe98,
+    0x48a170391f7dc42444e8fa2,
+];
+
+pub fn get_sqrt_ratio_at_tick(tick: i32) -> U256 {
+    let abs_tick = tick.unsigned_abs();
+    assert!(abs_tick <= MAX_TICK as u32, "tick {tick} out of bounds");
+
+    let mut ratio = match abs_tick & 1 != 0 {
+        true => U256::from(RATIOS[0]),
+        false => U256::one() << 128,
+    };
+    for (i, factor) in RATIOS.iter().enumerate().skip(1) {
+        if abs_tick & (1 << i) != 0 {
+            ratio = (ratio * U256::from(*factor)) >> 128;
+        }
+    }
+    if tick > 0 {
+        ratio = U256::MAX / ratio;
+    }
+
+    // from Q128.128 to Q64.96, rounded up
+    let remainder = ratio.low_u32();
+    (ratio >> 32) + if remainder == 0 { 0 } else { 1 }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::math::tick_math::{get_sqrt_ratio_at_tick, MAX_TICK, MIN_TICK};
+    use ethabi::ethereum_types::U256;
+
+    #[test]
+    fn test_get_sqrt_ratio_at_tick() {
+        // `TickMath.MIN_SQRT_RATIO` and `TickMath.MAX_SQRT_RATIO`
+        assert_eq!(U256::from(4295128739u64), get_sqrt_ratio_at_tick(MIN_TICK));
+        assert_eq!(
+            U256::from_dec_str("1461446703485210103287273052203988822378723970342").unwrap(),
+            get_sqrt_ratio_at_tick(MAX_TICK)
+        );
+        assert_eq!(U256::one() << 96, get_sqrt_ratio_at_tick(0));
+        assert_eq!(
+            U256::from_dec_str("79232123823359799118286999568").unwrap(),
+            get_sqrt_ratio_at_tick(1)
+        );
+        assert_eq!(
+            U256::from_dec_str("79224201403219477170569942574").unwrap(),
+            get_sqrt_ratio_at_tick(-1)
+        );
+        assert_eq!(
+            U256::from_dec_str("31411584497546210966172950056294283").unwrap(),
+            get_sqrt_ratio_at_tick(257820)
+        );
+        assert_eq!(
+            U256::from_dec_str("35246714534369728900268601106").unwrap(),
+            get_sqrt_ratio_at_tick(-16200)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    fn test_get_sqrt_ratio_at_tick_out_of_bounds() {
+        get_sqrt_ratio_at_tick(MAX_TICK + 1);
+    }
+}
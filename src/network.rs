@@ -0,0 +1,408 @@
+use crate::config::{ChainlinkFeed, Config};
+use crate::{guards, price, twap};
+use std::str::FromStr;
+use substreams::scalar::BigDecimal;
+
+// Built-in profiles of the Uniswap v3 deployments, selected with the `network` param. Addresses are
+// lowercase hex without the `0x` prefix, except the factory which is also used as the `Factory` entity id.
+//
+// Prices are derived against the wrapped native token of the network, the `ETH` fields of the entities
+// (`derivedETH`, `totalValueLockedETH`, `ethPriceUSD`, ...) hold amounts of that token. The minimum amount
+// of native token locked in a pool to use it for pricing is roughly the USD value of 52 ETH, and the
+// minimum amount of native token of the active liquidity of a pool roughly the USD value of 10 ETH. The USD price of
+// the native token is blended over its pools with the `usd_bundle_tokens`, on every fee tier.
+//
+// Tokens with a TVL worth at least the USD value of about 1000 ETH of native token for about a day of blocks join the
+// whitelist, on top of the `whitelist_tokens`.
+//
+// Chainlink feeds are `(name, aggregator, decimals)`, the aggregator being the contract emitting the
+// `AnswerUpdated` events behind the feed proxy.
+pub struct NetworkProfile {
+    pub name: &'static str,
+    pub factory: &'static str,
+    pub position_manager: &'static str,
+    pub error_pool: Option<&'static str>,
+    pub start_block: u64,
+    pub wrapped_native_token: &'static str,
+    pub minimum_native_locked: &'static str,
+    pub minimum_active_native_liquidity: &'static str,
+    pub whitelist_eth_tvl: &'static str,
+    pub whitelist_min_blocks: u64,
+    pub usd_reference_pool: &'static str,
+    pub usd_reference_token: &'static str,
+    pub stable_coins: &'static [&'static str],
+    pub usd_bundle_tokens: &'static [&'static str],
+    pub whitelist_tokens: &'static [&'static str],
+    pub chainlink_feeds: &'static [(&'static str, &'static str, u64)],
+    pub chainlink_native_usd_feed: Option<&'static str>,
+}
+
+pub const MAINNET: NetworkProfile = NetworkProfile {
+    name: "mainnet",
+    factory: "0x1F98431c8aD98523631AE4a59f267346ea31F984",
+    position_manager: "c36442b4a4522e871399cd717abdd847ab11fe88",
+    error_pool: Some("8fe8d9bb8eeba3ed688069c3d6b556c9ca258248"),
+    start_block: 12369621,
+    wrapped_native_token: "c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2", // WETH
+    minimum_native_locked: "52",
+    minimum_active_native_liquidity: "10",
+    whitelist_eth_tvl: "1000",
+    whitelist_min_blocks: 7200,                                      // ~1 day of 12s blocks
+    usd_reference_pool: "8ad599c3a0ff1de082011efddc58f1908eb6e6d8",  // USDC/WETH 0.3%
+    usd_reference_token: "a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48", // USDC
+    stable_coins: &[
+        "6b175474e89094c44da98b954eedeac495271d0f", // DAI
+        "a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48", // USDC
+        "dac17f958d2ee523a2206206994597c13d831ec7", // USDT
+        "0000000000085d4780b73119b644ae5ecd22b376", // TUSD
+        "956f47f50a910163d8bf957cf5846d573e7f87ca", // FEI
+        "4dd28568d05f09b02220b09c2cb307bfd837cb95", // PRINTS
+    ],
+    usd_bundle_tokens: &[
+        "6b175474e89094c44da98b954eedeac495271d0f", // DAI
+        "a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48", // USDC
+        "dac17f958d2ee523a2206206994597c13d831ec7", // USDT
+    ],
+    whitelist_tokens: &[
+        "c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2", // WETH
+        "6b175474e89094c44da98b954eedeac495271d0f", // DAI
+        "a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48", // USDC
+        "dac17f958d2ee523a2206206994597c13d831ec7", // USDT
+        "0000000000085d4780b73119b644ae5ecd22b376", // TUSD
+        "2260fac5e5542a773aa44fbcfedf7c193bc2c599", // WBTC
+        "5d3a536e4d6dbd6114cc1ead35777bab948e3643", // cDAI
+        "39aa39c021dfbae8fac545936693ac917d5e7563", // cUSDC
+        "86fadb80d8d2cff3c3680819e4da99c10232ba0f", // EBASE
+        "57ab1ec28d129707052df4df418d58a2d46d5f51", // sUSD
+        "9f8f72aa9304c8b593d555f12ef6589cc3a579a2", // MKR
+        "c00e94cb662c3520282e6f5717214004a7f26888", // COMP
+        "514910771af9ca656af840dff83e8264ecf986ca", // LINK
+        "c011a73ee8576fb46f5e1c5751ca3b9fe0af2a6f", // SNX
+        "0bc529c00c6401aef6d220be8c6ea1667f6ad93e", // YFI
+        "111111111117dc0aa78b770fa6a738034120c302", // 1INCH
+        "df5e0e81dff6faf3a7e52ba697820c5e32d806a8", // yCurv
+        "956f47f50a910163d8bf957cf5846d573e7f87ca", // FEI
+        "7d1afa7b718fb893db30a3abc0cfc608aacfebb0", // MATIC
+        "7fc66500c84a76ad7e9c93437bfc5ac33e2ddae9", // AAVE
+        "fe2e637202056d30016725477c5da089ab0a043a", // sETH2
+    ],
+    // ETH/USD aggregators behind the 0x5f4ec3df9cbd43714fe2740f5e3616155c5b8419 proxy since the start block, in
+    // the order of the upgrades
+    chainlink_feeds: &[
+        ("ETH/USD", "00c7a37b03690fb9f41b5c5af8131735c7275446", 8),
+        ("ETH/USD", "37bc7498f4ff12c19678ee8fe19d713b87f6a9e6", 8),
+        ("ETH/USD", "e62b71cf983019bff55bc83b48601ce8419650cc", 8),
+        ("ETH/USD", "7d4e742018fb52e48b08be73d041c18b21de6fb5", 8),
+    ],
+    chainlink_native_usd_feed: Some("ETH/USD"),
+};
+
+pub const ARBITRUM_ONE: NetworkProfile = NetworkProfile {
+    name: "arbitrum-one",
+    factory: "0x1F98431c8aD98523631AE4a59f267346ea31F984",
+    position_manager: "c36442b4a4522e871399cd717abdd847ab11fe88",
+    error_pool: None,
+    start_block: 165,
+    wrapped_native_token: "82af49447d8a07e3bd95bd0d56f35241523fbab1", // WETH
+    minimum_native_locked: "52",
+    minimum_active_native_liquidity: "10",
+    whitelist_eth_tvl: "1000",
+    whitelist_min_blocks: 345600,                                   // ~1 day of 0.25s blocks
+    usd_reference_pool: "c31e54c7a869b9fcbecc14363cf510d1c41fa443", // WETH/USDC.e 0.05%
+    usd_reference_token: "ff970a61a04b1ca14834a43f5de4533ebddb5cc8", // USDC.e
+    stable_coins: &[
+        "ff970a61a04b1ca14834a43f5de4533ebddb5cc8", // USDC.e
+        "af88d065e77c8cc2239327c5edb3a432268e5831", // USDC
+        "fd086bc7cd5c481dcc9c85ebe478a1c0b69fcbb9", // USDT
+        "da10009cbd5d07dd0cecc66161fc93d7c9000da1", // DAI
+    ],
+    usd_bundle_tokens: &[
+        "ff970a61a04b1ca14834a43f5de4533ebddb5cc8", // USDC.e
+        "af88d065e77c8cc2239327c5edb3a432268e5831", // USDC
+        "fd086bc7cd5c481dcc9c85ebe478a1c0b69fcbb9", // USDT
+        "da10009cbd5d07dd0cecc66161fc93d7c9000da1", // DAI
+    ],
+    whitelist_tokens: &[
+        "82af49447d8a07e3bd95bd0d56f35241523fbab1", // WETH
+        "ff970a61a04b1ca14834a43f5de4533ebddb5cc8", // USDC.e
+        "af88d065e77c8cc2239327c5edb3a432268e5831", // USDC
+        "fd086bc7cd5c481dcc9c85ebe478a1c0b69fcbb9", // USDT
+        "da10009cbd5d07dd0cecc66161fc93d7c9000da1", // DAI
+        "2f2a2543b76a4166549f7aab2e75bef0aefc5b0f", // WBTC
+        "912ce59144191c1204e64559fe8253a0e49e6548", // ARB
+    ],
+    chainlink_feeds: &[],
+    chainlink_native_usd_feed: None,
+};
+
+pub const OPTIMISM: NetworkProfile = NetworkProfile {
+    name: "optimism",
+    factory: "0x1F98431c8aD98523631AE4a59f267346ea31F984",
+    position_manager: "c36442b4a4522e871399cd717abdd847ab11fe88",
+    error_pool: None,
+    start_block: 0,
+    wrapped_native_token: "4200000000000000000000000000000000000006", // WETH
+    minimum_native_locked: "52",
+    minimum_active_native_liquidity: "10",
+    whitelist_eth_tvl: "1000",
+    whitelist_min_blocks: 43200,                                     // ~1 day of 2s blocks
+    usd_reference_pool: "85149247691df622eaf1a8bd0cafd40bc45154a9",  // WETH/USDC.e 0.05%
+    usd_reference_token: "7f5c764cbc14f9669b88837ca1490cca17c31607", // USDC.e
+    stable_coins: &[
+        "7f5c764cbc14f9669b88837ca1490cca17c31607", // USDC.e
+        "0b2c639c533813f4aa9d7837caf62653d097ff85", // USDC
+        "94b008aa00579c1307b0ef2c499ad98a8ce58e58", // USDT
+        "da10009cbd5d07dd0cecc66161fc93d7c9000da1", // DAI
+    ],
+    usd_bundle_tokens: &[
+        "7f5c764cbc14f9669b88837ca1490cca17c31607", // USDC.e
+        "0b2c639c533813f4aa9d7837caf62653d097ff85", // USDC
+        "94b008aa00579c1307b0ef2c499ad98a8ce58e58", // USDT
+        "da10009cbd5d07dd0cecc66161fc93d7c9000da1", // DAI
+    ],
+    whitelist_tokens: &[
+        "4200000000000000000000000000000000000006", // WETH
+        "7f5c764cbc14f9669b88837ca1490cca17c31607", // USDC.e
+        "0b2c639c533813f4aa9d7837caf62653d097ff85", // USDC
+        "94b008aa00579c1307b0ef2c499ad98a8ce58e58", // USDT
+        "da10009cbd5d07dd0cecc66161fc93d7c9000da1", // DAI
+        "68f180fcce6836688e9084f035309e29bf0a2095", // WBTC
+        "4200000000000000000000000000000000000042", // OP
+    ],
+    chainlink_feeds: &[],
+    chainlink_native_usd_feed: None,
+};
+
+pub const POLYGON: NetworkProfile = NetworkProfile {
+    name: "polygon",
+    factory: "0x1F98431c8aD98523631AE4a59f267346ea31F984",
+    position_manager: "c36442b4a4522e871399cd717abdd847ab11fe88",
+    error_pool: None,
+    start_block: 22757547,
+    wrapped_native_token: "0d500b1d8e8ef31e21c99d1db9a6444d3adf1270", // WMATIC
+    minimum_native_locked: "100000",
+    minimum_active_native_liquidity: "20000",
+    whitelist_eth_tvl: "2000000",
+    whitelist_min_blocks: 43200,                                     // ~1 day of 2s blocks
+    usd_reference_pool: "a374094527e1673a86de625aa59517c5de346d32",  // WMATIC/USDC.e 0.05%
+    usd_reference_token: "2791bca1f2de4661ed88a30c99a7a9449aa84174", // USDC.e
+    stable_coins: &[
+        "2791bca1f2de4661ed88a30c99a7a9449aa84174", // USDC.e
+        "3c499c542cef5e3811e1192ce70d8cc03d5c3359", // USDC
+        "c2132d05d31c914a87c6611c10748aeb04b58e8f", // USDT
+        "8f3cf7ad23cd3cadbd9735aff958023239c6a063", // DAI
+    ],
+    usd_bundle_tokens: &[
+        "2791bca1f2de4661ed88a30c99a7a9449aa84174", // USDC.e
+        "3c499c542cef5e3811e1192ce70d8cc03d5c3359", // USDC
+        "c2132d05d31c914a87c6611c10748aeb04b58e8f", // USDT
+        "8f3cf7ad23cd3cadbd9735aff958023239c6a063", // DAI
+    ],
+    whitelist_tokens: &[
+        "0d500b1d8e8ef31e21c99d1db9a6444d3adf1270", // WMATIC
+        "7ceb23fd6bc0add59e62ac25578270cff1b9f619", // WETH
+        "2791bca1f2de4661ed88a30c99a7a9449aa84174", // USDC.e
+        "3c499c542cef5e3811e1192ce70d8cc03d5c3359", // USDC
+        "c2132d05d31c914a87c6611c10748aeb04b58e8f", // USDT
+        "8f3cf7ad23cd3cadbd9735aff958023239c6a063", // DAI
+        "1bfd67037b42cf73acf2047067bd4f2c47d9bfd6", // WBTC
+    ],
+    chainlink_feeds: &[],
+    chainlink_native_usd_feed: None,
+};
+
+pub const BASE: NetworkProfile = NetworkProfile {
+    name: "base",
+    factory: "0x33128a8fC17869897dcE68Ed026d694621f6FDfD",
+    position_manager: "03a520b32c04bf3beef7beb72e919cf822ed34f1",
+    error_pool: None,
+    start_block: 1371680,
+    wrapped_native_token: "4200000000000000000000000000000000000006", // WETH
+    minimum_native_locked: "52",
+    minimum_active_native_liquidity: "10",
+    whitelist_eth_tvl: "1000",
+    whitelist_min_blocks: 43200,                                     // ~1 day of 2s blocks
+    usd_reference_pool: "4c36388be6f416a29c8d8eee81c771ce6be14b18",  // WETH/USDbC 0.05%
+    usd_reference_token: "d9aaec86b65d86f6a7b5b1b0c42ffa531710b6ca", // USDbC
+    stable_coins: &[
+        "d9aaec86b65d86f6a7b5b1b0c42ffa531710b6ca", // USDbC
+        "833589fcd6edb6e08f4c7c32d4f71b54bda02913", // USDC
+        "50c5725949a6f0c72e6c4a641f24049a917db0cb", // DAI
+    ],
+    usd_bundle_tokens: &[
+        "d9aaec86b65d86f6a7b5b1b0c42ffa531710b6ca", // USDbC
+        "833589fcd6edb6e08f4c7c32d4f71b54bda02913", // USDC
+        "50c5725949a6f0c72e6c4a641f24049a917db0cb", // DAI
+    ],
+    whitelist_tokens: &[
+        "4200000000000000000000000000000000000006", // WETH
+        "d9aaec86b65d86f6a7b5b1b0c42ffa531710b6ca", // USDbC
+        "833589fcd6edb6e08f4c7c32d4f71b54bda02913", // USDC
+        "50c5725949a6f0c72e6c4a641f24049a917db0cb", // DAI
+        "2ae3f1ec7f1f5012cfeab0185bfc7aa3cf0dec22", // cbETH
+    ],
+    chainlink_feeds: &[],
+    chainlink_native_usd_feed: None,
+};
+
+pub const BSC: NetworkProfile = NetworkProfile {
+    name: "bsc",
+    factory: "0xdB1d10011AD0Ff90774D0C6Bb92e5C5c8b4461F7",
+    position_manager: "7b8a01b39d58278b5de7e48c8449c9f4f5170613",
+    error_pool: None,
+    start_block: 26324014,
+    wrapped_native_token: "bb4cdb9cbd36b01bd1cbaebf2de08d9173bc095c", // WBNB
+    minimum_native_locked: "350",
+    minimum_active_native_liquidity: "70",
+    whitelist_eth_tvl: "7000",
+    whitelist_min_blocks: 28800,                                     // ~1 day of 3s blocks
+    usd_reference_pool: "6fe9e9de56356f7edbfcbb29fab7cd69471a4869",  // USDT/WBNB 0.05%
+    usd_reference_token: "55d398326f99059ff775485246999027b3197955", // USDT
+    stable_coins: &[
+        "55d398326f99059ff775485246999027b3197955", // USDT
+        "8ac76a51cc950d9822d68b83fe1ad97b32cd580d", // USDC
+        "e9e7cea3dedca5984780bafc599bd69add087d56", // BUSD
+    ],
+    usd_bundle_tokens: &[
+        "55d398326f99059ff775485246999027b3197955", // USDT
+        "8ac76a51cc950d9822d68b83fe1ad97b32cd580d", // USDC
+        "e9e7cea3dedca5984780bafc599bd69add087d56", // BUSD
+    ],
+    whitelist_tokens: &[
+        "bb4cdb9cbd36b01bd1cbaebf2de08d9173bc095c", // WBNB
+        "55d398326f99059ff775485246999027b3197955", // USDT
+        "8ac76a51cc950d9822d68b83fe1ad97b32cd580d", // USDC
+        "e9e7cea3dedca5984780bafc599bd69add087d56", // BUSD
+        "2170ed0880ac9a755fd29b2688956bd959f933f8", // ETH
+        "7130d2a12b9bcbfae4f2634d864a1ee1ce3ead9c", // BTCB
+    ],
+    chainlink_feeds: &[],
+    chainlink_native_usd_feed: None,
+};
+
+pub const CELO: NetworkProfile = NetworkProfile {
+    name: "celo",
+    factory: "0xAfE208a311B21f13EF87E33A90049fC17A7acDEc",
+    position_manager: "3d79edaabc0eab6f08ed885c05fc0b014290d95a",
+    error_pool: None,
+    start_block: 13916355,
+    // CELO is both the native currency and an ERC20
+    wrapped_native_token: "471ece3750da237f93b8e339c536989b8978a438", // CELO
+    minimum_native_locked: "100000",
+    minimum_active_native_liquidity: "20000",
+    whitelist_eth_tvl: "2000000",
+    whitelist_min_blocks: 17280,                                     // ~1 day of 5s blocks
+    usd_reference_pool: "2d70cbabf4d8e61d5317b62cbe912935fd94e0fe",  // CELO/cUSD 0.01%
+    usd_reference_token: "765de816845861e75a25fca122bb6898b8b1282a", // cUSD
+    stable_coins: &[
+        "765de816845861e75a25fca122bb6898b8b1282a", // cUSD
+        "ceba9300f2b948710d2653dd7b07f33a8b32118c", // USDC
+    ],
+    usd_bundle_tokens: &[
+        "765de816845861e75a25fca122bb6898b8b1282a", // cUSD
+        "ceba9300f2b948710d2653dd7b07f33a8b32118c", // USDC
+    ],
+    whitelist_tokens: &[
+        "471ece3750da237f93b8e339c536989b8978a438", // CELO
+        "765de816845861e75a25fca122bb6898b8b1282a", // cUSD
+        "d8763cba276a3738e6de85b4b3bf5fded6d6ca73", // cEUR
+        "ceba9300f2b948710d2653dd7b07f33a8b32118c", // USDC
+    ],
+    chainlink_feeds: &[],
+    chainlink_native_usd_feed: None,
+};
+
+pub const PROFILES: [&NetworkProfile; 7] = [&MAINNET, &ARBITRUM_ONE, &OPTIMISM, &POLYGON, &BASE, &BSC, &CELO];
+
+pub fn find_profile(name: &str) -> Option<&'static NetworkProfile> {
+    PROFILES.into_iter().find(|profile| profile.name == name)
+}
+
+impl NetworkProfile {
+    pub fn config(&self) -> Config {
+        Config {
+            network: self.name.to_string(),
+            factory: self.factory.to_string(),
+            position_manager: self.position_manager.to_string(),
+            error_pool: self.error_pool.map(|pool| pool.to_string()),
+            start_block: self.start_block,
+            wrapped_native_token: self.wrapped_native_token.to_string(),
+            minimum_native_locked: BigDecimal::from_str(self.minimum_native_locked).unwrap(),
+            minimum_active_native_liquidity: BigDecimal::from_str(self.minimum_active_native_liquidity).unwrap(),
+            whitelist_eth_tvl: BigDecimal::from_str(self.whitelist_eth_tvl).unwrap(),
+            whitelist_min_blocks: self.whitelist_min_blocks,
+            max_twap_tick_deviation: guards::DEFAULT_MAX_TWAP_TICK_DEVIATION,
+            reversal_tick_tolerance: guards::DEFAULT_REVERSAL_TICK_TOLERANCE,
+            twap_windows: twap::DEFAULT_TWAP_WINDOWS.to_vec(),
+            usd_reference_pool: self.usd_reference_pool.to_string(),
+            usd_reference_token: self.usd_reference_token.to_string(),
+            stable_coins: to_strings(self.stable_coins),
+            usd_bundle_tokens: to_strings(self.usd_bundle_tokens),
+            whitelist_tokens: to_strings(self.whitelist_tokens),
+            chainlink_feeds: self
+                .chainlink_feeds
+                .iter()
+                .map(|(name, aggregator, decimals)| ChainlinkFeed {
+                    name: name.to_string(),
+                    aggregator: aggregator.to_string(),
+                    decimals: *decimals,
+                })
+                .collect(),
+            chainlink_native_usd_feed: self.chainlink_native_usd_feed.map(|feed| feed.to_string()),
+            chainlink_as_bundle: false,
+            stale_price_days: price::DEFAULT_STALE_PRICE_DAYS,
+            token_overrides: vec![],
+        }
+    }
+}
+
+fn to_strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|value| value.to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::network::PROFILES;
+    use substreams::Hex;
+
+    #[test]
+    fn test_profiles_addresses() {
+        for profile in PROFILES {
+            let config = profile.config();
+            let mut addresses = vec![
+                config.factory.trim_start_matches("0x").to_lowercase(),
+                config.position_manager.clone(),
+                config.wrapped_native_token.clone(),
+                config.usd_reference_pool.clone(),
+                config.usd_reference_token.clone(),
+            ];
+            addresses.extend(config.stable_coins.clone());
+            addresses.extend(config.usd_bundle_tokens.clone());
+            addresses.extend(config.whitelist_tokens.clone());
+            addresses.extend(config.chainlink_feeds.iter().map(|feed| feed.aggregator.clone()));
+
+            for address in addresses {
+                assert_eq!(address, address.to_lowercase(), "{address}");
+                assert_eq!(20, Hex::decode(&address).unwrap().len(), "{address}");
+            }
+
+            assert!(config.is_stable_coin(&config.usd_reference_token), "{}", profile.name);
+            assert!(
+                config
+                    .usd_bundle_tokens
+                    .iter()
+                    .all(|token| config.is_stable_coin(token)),
+                "{}",
+                profile.name
+            );
+            assert!(config.is_whitelisted(&config.wrapped_native_token), "{}", profile.name);
+            if let Some(native_usd_feed) = &config.chainlink_native_usd_feed {
+                assert!(
+                    config.chainlink_feeds.iter().any(|feed| &feed.name == native_usd_feed),
+                    "{}",
+                    profile.name
+                );
+            }
+        }
+    }
+}
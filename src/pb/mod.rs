@@ -4,10 +4,9 @@ use crate::pb::uniswap::events::position_event::Type::{
 };
 use crate::pb::uniswap::events::PoolEvent;
 use crate::pb::uniswap::events::PositionEvent;
-use crate::utils::ERROR_POOL;
 use crate::{Erc20Token, Pool};
 use substreams::scalar::BigDecimal;
-use substreams::{log, Hex};
+use substreams::log;
 
 #[allow(unused_imports)]
 #[allow(dead_code)]
@@ -40,17 +39,11 @@ impl Erc20Token {
 
 impl Pool {
     pub fn should_handle_swap(&self) -> bool {
-        if self.ignore_pool {
-            return false;
-        }
-        return &self.address != &Hex(ERROR_POOL).to_string();
+        !self.ignore_pool
     }
 
     pub fn should_handle_mint_and_burn(&self) -> bool {
-        if self.ignore_pool {
-            return false;
-        }
-        return &self.address != &Hex(ERROR_POOL).to_string();
+        !self.ignore_pool
     }
 
     pub fn token0_ref(&self) -> &Erc20Token {
@@ -116,6 +109,8 @@ impl PoolEvent {
                 token0_addr: self.token0.clone(),
                 token1_addr: self.token1.clone(),
             }),
+            // The liquidity leaves the pool on the burn, a collect only transfers the tokens owed
+            Type::Collect(_) => None,
         };
     }
 }
@@ -20,6 +20,37 @@ pub struct Erc20Token {
     pub total_supply: ::prost::alloc::string::String,
     #[prost(string, repeated, tag="6")]
     pub whitelist_pools: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    #[prost(enumeration="erc20_token::MetadataStatus", tag="7")]
+    pub metadata_status: i32,
+    /// steps of the decoding past the plain `string` ABI, e.g. "symbol: bytes32; name: sanitized"
+    #[prost(string, tag="8")]
+    pub metadata_fallback_reason: ::prost::alloc::string::String,
+}
+/// Nested message and enum types in `ERC20Token`.
+pub mod erc20_token {
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+    #[repr(i32)]
+    pub enum MetadataStatus {
+        /// decimals, name and symbol read from the token contract
+        Resolved = 0,
+        /// taken from the hard-coded tokens or the `token_overrides`
+        Overridden = 1,
+        /// the token contract could not be read, amounts are in raw token units
+        Placeholder = 2,
+    }
+    impl MetadataStatus {
+        /// String value of the enum field names used in the ProtoBuf definition.
+        ///
+        /// The values are not transformed in any way and thus are considered stable
+        /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+        pub fn as_str_name(&self) -> &'static str {
+            match self {
+                MetadataStatus::Resolved => "RESOLVED",
+                MetadataStatus::Overridden => "OVERRIDDEN",
+                MetadataStatus::Placeholder => "PLACEHOLDER",
+            }
+        }
+    }
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -64,6 +95,50 @@ pub struct Pool {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+pub struct FactoryEvents {
+    #[prost(message, repeated, tag="1")]
+    pub fee_amounts_enabled: ::prost::alloc::vec::Vec<factory_events::FeeAmountEnabled>,
+    #[prost(message, repeated, tag="2")]
+    pub owners_changed: ::prost::alloc::vec::Vec<factory_events::OwnerChanged>,
+}
+/// Nested message and enum types in `FactoryEvents`.
+pub mod factory_events {
+    #[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct FeeAmountEnabled {
+        /// Integer
+        #[prost(string, tag="1")]
+        pub fee: ::prost::alloc::string::String,
+        #[prost(int32, tag="2")]
+        pub tick_spacing: i32,
+        #[prost(uint64, tag="3")]
+        pub log_ordinal: u64,
+        #[prost(string, tag="4")]
+        pub transaction_id: ::prost::alloc::string::String,
+        #[prost(uint64, tag="5")]
+        pub timestamp: u64,
+        #[prost(uint64, tag="6")]
+        pub block_number: u64,
+    }
+    #[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct OwnerChanged {
+        #[prost(string, tag="1")]
+        pub old_owner: ::prost::alloc::string::String,
+        #[prost(string, tag="2")]
+        pub new_owner: ::prost::alloc::string::String,
+        #[prost(uint64, tag="3")]
+        pub log_ordinal: u64,
+        #[prost(string, tag="4")]
+        pub transaction_id: ::prost::alloc::string::String,
+        #[prost(uint64, tag="5")]
+        pub timestamp: u64,
+        #[prost(uint64, tag="6")]
+        pub block_number: u64,
+    }
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Events {
     #[prost(message, repeated, tag="1")]
     pub pool_sqrt_prices: ::prost::alloc::vec::Vec<events::PoolSqrtPrice>,
@@ -85,6 +160,16 @@ pub struct Events {
     pub ticks_created: ::prost::alloc::vec::Vec<events::TickCreated>,
     #[prost(message, repeated, tag="9")]
     pub ticks_updated: ::prost::alloc::vec::Vec<events::TickUpdated>,
+    #[prost(message, repeated, tag="12")]
+    pub set_fee_protocols: ::prost::alloc::vec::Vec<events::SetFeeProtocol>,
+    #[prost(message, repeated, tag="13")]
+    pub collect_protocols: ::prost::alloc::vec::Vec<events::CollectProtocol>,
+    #[prost(message, repeated, tag="14")]
+    pub protocol_fees_updates: ::prost::alloc::vec::Vec<events::ProtocolFees>,
+    #[prost(message, repeated, tag="15")]
+    pub oracle_updates: ::prost::alloc::vec::Vec<events::OracleUpdate>,
+    #[prost(message, repeated, tag="16")]
+    pub observations: ::prost::alloc::vec::Vec<events::Observation>,
     #[prost(message, repeated, tag="20")]
     pub created_positions: ::prost::alloc::vec::Vec<events::CreatedPosition>,
     #[prost(message, repeated, tag="21")]
@@ -111,18 +196,23 @@ pub mod events {
         #[prost(string, tag="4")]
         pub new_value: ::prost::alloc::string::String,
     }
+    /// fee growth inside a tick range, computed from the fee growths global and outside of its ticks
     #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
     pub struct FeeGrowthInside {
         #[prost(string, tag="1")]
         pub pool_address: ::prost::alloc::string::String,
         #[prost(int32, tag="2")]
-        pub tick_idx: i32,
-        #[prost(uint64, tag="3")]
+        pub tick_lower: i32,
+        #[prost(int32, tag="3")]
+        pub tick_upper: i32,
+        #[prost(uint64, tag="4")]
         pub ordinal: u64,
         /// Integer
-        #[prost(string, tag="4")]
+        #[prost(string, tag="5")]
         pub new_value: ::prost::alloc::string::String,
+        #[prost(int32, tag="6")]
+        pub token_idx: i32,
     }
     #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -130,14 +220,14 @@ pub mod events {
         #[prost(string, tag="1")]
         pub pool_address: ::prost::alloc::string::String,
         #[prost(int32, tag="2")]
-        pub tick_lower: i32,
-        #[prost(int32, tag="3")]
-        pub tick_upper: i32,
-        #[prost(uint64, tag="4")]
+        pub tick_idx: i32,
+        #[prost(uint64, tag="3")]
         pub ordinal: u64,
         /// Integer
-        #[prost(string, tag="5")]
+        #[prost(string, tag="4")]
         pub new_value: ::prost::alloc::string::String,
+        #[prost(int32, tag="5")]
+        pub token_idx: i32,
     }
     #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -197,6 +287,8 @@ pub mod events {
         pub tick: ::prost::alloc::string::String,
         #[prost(bool, tag="5")]
         pub initialized: bool,
+        #[prost(string, tag="6")]
+        pub transaction_id: ::prost::alloc::string::String,
     }
     #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -219,7 +311,7 @@ pub mod events {
         pub timestamp: u64,
         #[prost(uint64, tag="108")]
         pub created_at_block_number: u64,
-        #[prost(oneof="pool_event::Type", tags="1, 2, 3")]
+        #[prost(oneof="pool_event::Type", tags="1, 2, 3, 4")]
         pub r#type: ::core::option::Option<pool_event::Type>,
     }
     /// Nested message and enum types in `PoolEvent`.
@@ -248,6 +340,9 @@ pub mod events {
             /// Integer
             #[prost(string, tag="8")]
             pub tick: ::prost::alloc::string::String,
+            /// number of initialized ticks crossed
+            #[prost(uint64, tag="9")]
+            pub ticks_crossed: u64,
         }
         #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -298,6 +393,26 @@ pub mod events {
             pub amount: ::prost::alloc::string::String,
         }
         #[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+        pub struct Collect {
+            #[prost(string, tag="1")]
+            pub owner: ::prost::alloc::string::String,
+            #[prost(string, tag="2")]
+            pub recipient: ::prost::alloc::string::String,
+            /// Decimal
+            #[prost(string, tag="3")]
+            pub amount_0: ::prost::alloc::string::String,
+            /// Decimal
+            #[prost(string, tag="4")]
+            pub amount_1: ::prost::alloc::string::String,
+            /// Integer
+            #[prost(string, tag="5")]
+            pub tick_lower: ::prost::alloc::string::String,
+            /// Integer
+            #[prost(string, tag="6")]
+            pub tick_upper: ::prost::alloc::string::String,
+        }
+        #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Oneof)]
         pub enum Type {
             #[prost(message, tag="1")]
@@ -306,6 +421,8 @@ pub mod events {
             Burn(Burn),
             #[prost(message, tag="3")]
             Mint(Mint),
+            #[prost(message, tag="4")]
+            Collect(Collect),
         }
     }
     #[allow(clippy::derive_partial_eq_without_eq)]
@@ -337,6 +454,125 @@ pub mod events {
         pub fee_growth_global_1x_128: ::prost::alloc::string::String,
         #[prost(uint64, tag="4")]
         pub log_ordinal: u64,
+        #[prost(uint64, tag="5")]
+        pub log_index: u64,
+        #[prost(string, tag="6")]
+        pub token0: ::prost::alloc::string::String,
+        #[prost(string, tag="7")]
+        pub token1: ::prost::alloc::string::String,
+        #[prost(string, tag="8")]
+        pub sender: ::prost::alloc::string::String,
+        #[prost(string, tag="9")]
+        pub recipient: ::prost::alloc::string::String,
+        /// Decimal
+        #[prost(string, tag="10")]
+        pub amount_0: ::prost::alloc::string::String,
+        /// Decimal
+        #[prost(string, tag="11")]
+        pub amount_1: ::prost::alloc::string::String,
+        /// Decimal
+        #[prost(string, tag="12")]
+        pub paid_0: ::prost::alloc::string::String,
+        /// Decimal
+        #[prost(string, tag="13")]
+        pub paid_1: ::prost::alloc::string::String,
+        #[prost(string, tag="14")]
+        pub transaction_id: ::prost::alloc::string::String,
+        #[prost(uint64, tag="15")]
+        pub timestamp: u64,
+    }
+    #[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct SetFeeProtocol {
+        #[prost(string, tag="1")]
+        pub pool_address: ::prost::alloc::string::String,
+        #[prost(uint64, tag="2")]
+        pub log_ordinal: u64,
+        #[prost(uint64, tag="3")]
+        pub fee_protocol_0_old: u64,
+        #[prost(uint64, tag="4")]
+        pub fee_protocol_1_old: u64,
+        #[prost(uint64, tag="5")]
+        pub fee_protocol_0_new: u64,
+        #[prost(uint64, tag="6")]
+        pub fee_protocol_1_new: u64,
+        #[prost(string, tag="7")]
+        pub transaction_id: ::prost::alloc::string::String,
+        #[prost(uint64, tag="8")]
+        pub timestamp: u64,
+    }
+    #[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct CollectProtocol {
+        #[prost(string, tag="1")]
+        pub pool_address: ::prost::alloc::string::String,
+        #[prost(uint64, tag="2")]
+        pub log_ordinal: u64,
+        #[prost(string, tag="3")]
+        pub token0: ::prost::alloc::string::String,
+        #[prost(string, tag="4")]
+        pub token1: ::prost::alloc::string::String,
+        #[prost(string, tag="5")]
+        pub sender: ::prost::alloc::string::String,
+        #[prost(string, tag="6")]
+        pub recipient: ::prost::alloc::string::String,
+        /// Decimal
+        #[prost(string, tag="7")]
+        pub amount_0: ::prost::alloc::string::String,
+        /// Decimal
+        #[prost(string, tag="8")]
+        pub amount_1: ::prost::alloc::string::String,
+        #[prost(string, tag="9")]
+        pub transaction_id: ::prost::alloc::string::String,
+        #[prost(uint64, tag="10")]
+        pub timestamp: u64,
+    }
+    #[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct ProtocolFees {
+        #[prost(string, tag="1")]
+        pub pool_address: ::prost::alloc::string::String,
+        #[prost(uint64, tag="2")]
+        pub ordinal: u64,
+        #[prost(int32, tag="3")]
+        pub token_idx: i32,
+        /// Decimal
+        #[prost(string, tag="4")]
+        pub new_value: ::prost::alloc::string::String,
+    }
+    #[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct OracleUpdate {
+        #[prost(string, tag="1")]
+        pub pool_address: ::prost::alloc::string::String,
+        #[prost(uint64, tag="2")]
+        pub ordinal: u64,
+        #[prost(uint64, optional, tag="3")]
+        pub observation_index: ::core::option::Option<u64>,
+        #[prost(uint64, optional, tag="4")]
+        pub observation_cardinality: ::core::option::Option<u64>,
+        #[prost(uint64, optional, tag="5")]
+        pub observation_cardinality_next: ::core::option::Option<u64>,
+    }
+    #[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct Observation {
+        #[prost(string, tag="1")]
+        pub pool_address: ::prost::alloc::string::String,
+        #[prost(uint64, tag="2")]
+        pub ordinal: u64,
+        #[prost(uint64, tag="3")]
+        pub index: u64,
+        #[prost(uint64, tag="4")]
+        pub block_timestamp: u64,
+        /// Integer
+        #[prost(string, tag="5")]
+        pub tick_cumulative: ::prost::alloc::string::String,
+        /// Integer
+        #[prost(string, tag="6")]
+        pub seconds_per_liquidity_cumulative_x128: ::prost::alloc::string::String,
+        #[prost(bool, tag="7")]
+        pub initialized: bool,
     }
     #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -428,6 +664,12 @@ pub mod events {
         /// BigInt
         #[prost(string, optional, tag="6")]
         pub fee_growth_inside1_last_x128: ::core::option::Option<::prost::alloc::string::String>,
+        /// BigInt
+        #[prost(string, optional, tag="7")]
+        pub tokens_owed0: ::core::option::Option<::prost::alloc::string::String>,
+        /// BigInt
+        #[prost(string, optional, tag="8")]
+        pub tokens_owed1: ::core::option::Option<::prost::alloc::string::String>,
         #[prost(uint64, tag="10")]
         pub log_ordinal: u64,
     }
@@ -451,6 +693,12 @@ pub mod events {
         /// BigInt
         #[prost(string, optional, tag="6")]
         pub fee_growth_inside1_last_x128: ::core::option::Option<::prost::alloc::string::String>,
+        /// BigInt
+        #[prost(string, optional, tag="7")]
+        pub tokens_owed0: ::core::option::Option<::prost::alloc::string::String>,
+        /// BigInt
+        #[prost(string, optional, tag="8")]
+        pub tokens_owed1: ::core::option::Option<::prost::alloc::string::String>,
         #[prost(uint64, tag="10")]
         pub log_ordinal: u64,
     }
@@ -471,6 +719,12 @@ pub mod events {
         /// BigInt
         #[prost(string, optional, tag="6")]
         pub fee_growth_inside1_last_x128: ::core::option::Option<::prost::alloc::string::String>,
+        /// BigInt
+        #[prost(string, optional, tag="7")]
+        pub tokens_owed0: ::core::option::Option<::prost::alloc::string::String>,
+        /// BigInt
+        #[prost(string, optional, tag="8")]
+        pub tokens_owed1: ::core::option::Option<::prost::alloc::string::String>,
         #[prost(uint64, tag="10")]
         pub log_ordinal: u64,
     }
@@ -487,6 +741,266 @@ pub mod events {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TwapCheckpoint {
+    #[prost(string, tag="1")]
+    pub pool_address: ::prost::alloc::string::String,
+    #[prost(uint64, tag="2")]
+    pub timestamp: u64,
+    #[prost(int64, tag="3")]
+    pub tick_cumulative: i64,
+    /// tick in effect before the checkpoint
+    #[prost(int32, tag="4")]
+    pub tick_before: i32,
+    /// tick in effect after the checkpoint
+    #[prost(int32, tag="5")]
+    pub tick_after: i32,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PoolTwaps {
+    #[prost(message, repeated, tag="1")]
+    pub pool_twaps: ::prost::alloc::vec::Vec<PoolTwap>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PoolTwap {
+    #[prost(string, tag="1")]
+    pub pool_address: ::prost::alloc::string::String,
+    /// window length in seconds
+    #[prost(uint64, tag="2")]
+    pub window: u64,
+    #[prost(int32, tag="3")]
+    pub tick: i32,
+    /// Decimal
+    #[prost(string, tag="4")]
+    pub token0_price: ::prost::alloc::string::String,
+    /// Decimal
+    #[prost(string, tag="5")]
+    pub token1_price: ::prost::alloc::string::String,
+    #[prost(uint64, tag="6")]
+    pub timestamp: u64,
+    #[prost(uint64, tag="7")]
+    pub block_number: u64,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TokenPrices {
+    #[prost(message, repeated, tag="1")]
+    pub token_prices: ::prost::alloc::vec::Vec<TokenPrice>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TokenPrice {
+    #[prost(string, tag="1")]
+    pub token_address: ::prost::alloc::string::String,
+    #[prost(uint64, tag="2")]
+    pub ordinal: u64,
+    /// first pool of the pricing path, empty for the wrapped native token
+    #[prost(string, tag="3")]
+    pub pool_address: ::prost::alloc::string::String,
+    /// Decimal
+    #[prost(string, tag="4")]
+    pub derived_eth_price: ::prost::alloc::string::String,
+    /// Decimal
+    #[prost(string, tag="5")]
+    pub eth_price_usd: ::prost::alloc::string::String,
+    /// tokens from the priced token to the wrapped native token
+    #[prost(string, repeated, tag="6")]
+    pub path: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// number of hops of the path
+    #[prost(uint32, tag="7")]
+    pub depth: u32,
+    /// Decimal, ETH locked in the shallowest pool of the path
+    #[prost(string, tag="8")]
+    pub eth_locked: ::prost::alloc::string::String,
+    #[prost(bool, tag="9")]
+    pub initialized: bool,
+    #[prost(uint64, tag="10")]
+    pub block_number: u64,
+    #[prost(int64, tag="11")]
+    pub timestamp: i64,
+    /// Decimal, between 0 and 1
+    #[prost(string, tag="12")]
+    pub confidence: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ChainlinkAnswers {
+    #[prost(message, repeated, tag="1")]
+    pub answers: ::prost::alloc::vec::Vec<ChainlinkAnswer>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ChainlinkAnswer {
+    #[prost(string, tag="1")]
+    pub feed: ::prost::alloc::string::String,
+    #[prost(string, tag="2")]
+    pub aggregator: ::prost::alloc::string::String,
+    /// Decimal
+    #[prost(string, tag="3")]
+    pub answer: ::prost::alloc::string::String,
+    /// Integer
+    #[prost(string, tag="4")]
+    pub round_id: ::prost::alloc::string::String,
+    #[prost(uint64, tag="5")]
+    pub updated_at: u64,
+    #[prost(uint64, tag="6")]
+    pub ordinal: u64,
+    #[prost(uint64, tag="7")]
+    pub block_number: u64,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PriceGuards {
+    #[prost(message, repeated, tag="1")]
+    pub price_guards: ::prost::alloc::vec::Vec<PriceGuard>,
+}
+/// Decision of the manipulation guards for a price update of a pool
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PriceGuard {
+    #[prost(string, tag="1")]
+    pub pool_address: ::prost::alloc::string::String,
+    #[prost(uint64, tag="2")]
+    pub ordinal: u64,
+    #[prost(string, tag="3")]
+    pub transaction_id: ::prost::alloc::string::String,
+    #[prost(int32, tag="4")]
+    pub tick: i32,
+    #[prost(enumeration="price_guard::Decision", tag="5")]
+    pub decision: i32,
+    #[prost(bool, tag="6")]
+    pub has_twap: bool,
+    #[prost(int32, tag="7")]
+    pub twap_tick: i32,
+    /// tick used for pricing
+    #[prost(int32, tag="8")]
+    pub guarded_tick: i32,
+    /// Decimal, empty when the wrapped native token is not in the pool
+    #[prost(string, tag="9")]
+    pub active_native_liquidity: ::prost::alloc::string::String,
+}
+/// Nested message and enum types in `PriceGuard`.
+pub mod price_guard {
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+    #[repr(i32)]
+    pub enum Decision {
+        Accepted = 0,
+        /// the transaction moved the price back, the update is ignored
+        Reversed = 1,
+        /// not enough active liquidity, the pool is not used for pricing
+        LowLiquidity = 2,
+        /// too far from the TWAP, the price is clamped for pricing
+        Clamped = 3,
+    }
+    impl Decision {
+        /// String value of the enum field names used in the ProtoBuf definition.
+        ///
+        /// The values are not transformed in any way and thus are considered stable
+        /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+        pub fn as_str_name(&self) -> &'static str {
+            match self {
+                Decision::Accepted => "ACCEPTED",
+                Decision::Reversed => "REVERSED",
+                Decision::LowLiquidity => "LOW_LIQUIDITY",
+                Decision::Clamped => "CLAMPED",
+            }
+        }
+    }
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PositionAmounts {
+    #[prost(message, repeated, tag="1")]
+    pub position_amounts: ::prost::alloc::vec::Vec<PositionAmount>,
+}
+/// Underlying token amounts of a position at the current price of its pool
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PositionAmount {
+    #[prost(string, tag="1")]
+    pub token_id: ::prost::alloc::string::String,
+    #[prost(string, tag="2")]
+    pub pool: ::prost::alloc::string::String,
+    /// Decimal
+    #[prost(string, tag="3")]
+    pub amount0: ::prost::alloc::string::String,
+    /// Decimal
+    #[prost(string, tag="4")]
+    pub amount1: ::prost::alloc::string::String,
+    /// Decimal
+    #[prost(string, tag="5")]
+    pub amount_usd: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PositionFees {
+    /// fee growths inside the ranges of the refreshed positions
+    #[prost(message, repeated, tag="1")]
+    pub fee_growth_inside_updates: ::prost::alloc::vec::Vec<events::FeeGrowthInside>,
+    #[prost(message, repeated, tag="2")]
+    pub position_fees: ::prost::alloc::vec::Vec<PositionFee>,
+}
+/// Fees earned by a position since it was last updated, not collected yet
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PositionFee {
+    #[prost(string, tag="1")]
+    pub token_id: ::prost::alloc::string::String,
+    #[prost(string, tag="2")]
+    pub pool: ::prost::alloc::string::String,
+    /// Decimal
+    #[prost(string, tag="3")]
+    pub unclaimed_fees_token0: ::prost::alloc::string::String,
+    /// Decimal
+    #[prost(string, tag="4")]
+    pub unclaimed_fees_token1: ::prost::alloc::string::String,
+    /// Decimal
+    #[prost(string, tag="5")]
+    pub unclaimed_fees_usd: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PositionPnls {
+    #[prost(message, repeated, tag="1")]
+    pub position_pnls: ::prost::alloc::vec::Vec<PositionPnl>,
+}
+/// Profit and loss of a position. Every value is a decimal.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PositionPnl {
+    #[prost(string, tag="1")]
+    pub token_id: ::prost::alloc::string::String,
+    #[prost(string, tag="2")]
+    pub pool: ::prost::alloc::string::String,
+    /// USD value of the deposits at the prices of their block
+    #[prost(string, tag="3")]
+    pub cost_basis_usd: ::prost::alloc::string::String,
+    /// current USD value of the liquidity of the position
+    #[prost(string, tag="4")]
+    pub value_usd: ::prost::alloc::string::String,
+    #[prost(string, tag="5")]
+    pub fees_earned_token0: ::prost::alloc::string::String,
+    #[prost(string, tag="6")]
+    pub fees_earned_token1: ::prost::alloc::string::String,
+    /// collected and unclaimed fees at the current prices
+    #[prost(string, tag="7")]
+    pub fees_earned_usd: ::prost::alloc::string::String,
+    /// current USD value of the tokens deposited, had they been held
+    #[prost(string, tag="8")]
+    pub hodl_value_usd: ::prost::alloc::string::String,
+    /// `value_usd` plus the current USD value of the withdrawn tokens, minus `hodl_value_usd`
+    #[prost(string, tag="9")]
+    pub impermanent_loss_usd: ::prost::alloc::string::String,
+    /// USD value of the collects at the prices of their block minus the cost basis, once closed
+    #[prost(string, tag="10")]
+    pub realised_pnl_usd: ::prost::alloc::string::String,
+    /// no liquidity left and every withdrawn token collected
+    #[prost(bool, tag="11")]
+    pub closed: bool,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
 pub struct SnapshotPositions {
     #[prost(message, repeated, tag="1")]
     pub snapshot_positions: ::prost::alloc::vec::Vec<SnapshotPosition>,
@@ -0,0 +1,409 @@
+use crate::math::fee_growth::{get_fee_growth_inside, get_fees_owed};
+use crate::math::fixed_point;
+use crate::math::liquidity_amounts::get_amounts_for_liquidity;
+use crate::math::tick_math::get_sqrt_ratio_at_tick;
+use crate::pb::uniswap::events::{CreatedPosition, PoolSqrtPrice};
+use crate::pb::uniswap::PositionPnl;
+use crate::Pool;
+use std::collections::BTreeMap;
+use substreams::scalar::{BigDecimal, BigInt};
+use substreams::store::{StoreGet, StoreGetBigDecimal, StoreGetBigInt, StoreGetProto};
+
+// Token ids tracked in each chunk bitset of `store_pool_positions`
+pub const POSITIONS_PER_CHUNK: u64 = 256;
+
+// Chunk of a position in the bitsets of `store_pool_positions`, and the bit of the position in its chunk
+pub fn bitset_position(token_id: u64) -> (u64, BigInt) {
+    let bit = (token_id % POSITIONS_PER_CHUNK) as u32;
+    (token_id / POSITIONS_PER_CHUNK, BigInt::one() << bit)
+}
+
+// Indices of the bits set in a bitset
+pub fn set_bits(bitset: &BigInt) -> Vec<u64> {
+    let (_, bytes) = bitset.to_bytes_be();
+    let mut bits = vec![];
+    for (index, byte) in bytes.iter().rev().enumerate() {
+        for bit in 0..8 {
+            if byte & (1 << bit) != 0 {
+                bits.push(index as u64 * 8 + bit);
+            }
+        }
+    }
+    bits
+}
+
+// Open positions of a pool, from the chunks listed under `pool:{pool}` in `store_pool_position_chunks` and their
+// bitsets under `pool:{pool}:{chunk}` in `store_pool_positions`
+pub fn pool_positions(
+    pool_positions_store: &StoreGetBigInt,
+    pool_position_chunks_store: &StoreGetBigInt,
+    pool_address: &str,
+) -> Vec<String> {
+    let chunks = match pool_position_chunks_store.get_last(format!("pool:{pool_address}")) {
+        None => return vec![],
+        Some(chunks) => set_bits(&chunks),
+    };
+    let mut token_ids = vec![];
+    for chunk in chunks {
+        let Some(bitset) = pool_positions_store.get_last(format!("pool:{pool_address}:{chunk}")) else {
+            continue;
+        };
+        for bit in set_bits(&bitset) {
+            token_ids.push((chunk * POSITIONS_PER_CHUNK + bit).to_string());
+        }
+    }
+    token_ids
+}
+
+// Lowest and highest ticks of the pools whose tick moved in the block, from their tick at the start of the block
+// and the ticks of their price updates
+pub fn swept_ticks(
+    pool_sqrt_prices: &[PoolSqrtPrice],
+    pool_sqrt_price_store: &StoreGetProto<PoolSqrtPrice>,
+) -> BTreeMap<String, (i32, i32)> {
+    let mut swept_ticks: BTreeMap<String, (i32, i32)> = BTreeMap::new();
+    for sqrt_price in pool_sqrt_prices {
+        let tick = sqrt_price.tick.parse::<i32>().unwrap();
+        let (tick_low, tick_high) = swept_ticks.entry(sqrt_price.pool_address.clone()).or_insert_with(|| {
+            match pool_sqrt_price_store.get_at(0, format!("pool:{}", sqrt_price.pool_address)) {
+                Some(start) => {
+                    let tick_start = start.tick.parse::<i32>().unwrap();
+                    (tick_start, tick_start)
+                }
+                None => (tick, tick),
+            }
+        });
+        *tick_low = (*tick_low).min(tick);
+        *tick_high = (*tick_high).max(tick);
+    }
+    swept_ticks.retain(|_, (tick_low, tick_high)| tick_low != tick_high);
+    swept_ticks
+}
+
+// Whether the tick of the pool of a position went through its range while moving between `swept_ticks`: its
+// amounts changed and it earned fees. A position is out of range below its lower tick and from its upper tick.
+pub fn range_swept(position: &CreatedPosition, swept_ticks: Option<&(i32, i32)>) -> bool {
+    let Some((tick_low, tick_high)) = swept_ticks else {
+        return false;
+    };
+    let tick_lower = position.tick_lower.parse::<i32>().unwrap();
+    let tick_upper = position.tick_upper.parse::<i32>().unwrap();
+    *tick_low < tick_upper && *tick_high >= tick_lower
+}
+
+// Token amounts of a position with `liquidity` at the sqrt price `sqrt_price` of its pool, in decimals
+pub fn position_amounts(
+    position: &CreatedPosition,
+    pool: &Pool,
+    liquidity: &BigInt,
+    sqrt_price: &BigInt,
+) -> (BigDecimal, BigDecimal) {
+    let sqrt_ratio_lower = get_sqrt_ratio_at_tick(position.tick_lower.parse::<i32>().unwrap());
+    let sqrt_ratio_upper = get_sqrt_ratio_at_tick(position.tick_upper.parse::<i32>().unwrap());
+    let (amount0, amount1) = get_amounts_for_liquidity(
+        fixed_point::from_big_int(sqrt_price),
+        sqrt_ratio_lower,
+        sqrt_ratio_upper,
+        fixed_point::from_big_int(liquidity).as_u128(),
+    );
+
+    (
+        fixed_point::to_big_int(amount0).to_decimal(pool.token0_ref().decimals),
+        fixed_point::to_big_int(amount1).to_decimal(pool.token1_ref().decimals),
+    )
+}
+
+// Fee growth inside the range of a position with the current tick of its pool at `tick_current`
+pub fn fee_growth_inside(
+    position: &CreatedPosition,
+    tick_current: i32,
+    fee_growth_global: &BigInt,
+    fee_growth_outside_lower: &BigInt,
+    fee_growth_outside_upper: &BigInt,
+) -> BigInt {
+    fixed_point::to_big_int(get_fee_growth_inside(
+        position.tick_lower.parse::<i32>().unwrap(),
+        position.tick_upper.parse::<i32>().unwrap(),
+        tick_current,
+        fixed_point::from_big_int(fee_growth_global),
+        fixed_point::from_big_int(fee_growth_outside_lower),
+        fixed_point::from_big_int(fee_growth_outside_upper),
+    ))
+}
+
+// Fees earned by a position since its last update, when the fee growth inside its range was
+// `fee_growth_inside_last`. Fees credited to the position by its updates are in its `tokensOwed`.
+pub fn fees_owed(fee_growth_inside: &BigInt, fee_growth_inside_last: &BigInt, liquidity: &BigInt) -> BigInt {
+    fixed_point::to_big_int(get_fees_owed(
+        fixed_point::from_big_int(fee_growth_inside),
+        fixed_point::from_big_int(fee_growth_inside_last),
+        fixed_point::from_big_int(liquidity).as_u128(),
+    ))
+}
+
+// Fee growth inside the range of a position and the tokens owed to it, for token0 and token1: its `tokensOwed`
+// and the fees it earned since its last update, from the fee growths tracked in `store_fee_growth`
+pub fn earned_fees(
+    position: &CreatedPosition,
+    liquidity: &BigInt,
+    tick_current: i32,
+    fee_growth_store: &StoreGetBigInt,
+) -> [(BigInt, BigInt); 2] {
+    let fee_growth = |key: String| fee_growth_store.get_last(key).unwrap_or(BigInt::zero());
+    [0, 1].map(|token_idx| {
+        let fee_growth_inside = fee_growth_inside(
+            position,
+            tick_current,
+            &fee_growth(format!("pool:{}:token{token_idx}", position.pool)),
+            &fee_growth(format!(
+                "tick:{}:{}:token{token_idx}",
+                position.pool, position.tick_lower
+            )),
+            &fee_growth(format!(
+                "tick:{}:{}:token{token_idx}",
+                position.pool, position.tick_upper
+            )),
+        );
+        let fee_growth_inside_last = fee_growth(format!("position:{}:token{token_idx}", position.token_id));
+        let tokens_owed = fee_growth(format!("position:{}:owed{token_idx}", position.token_id));
+        let fees = fees_owed(&fee_growth_inside, &fee_growth_inside_last, liquidity) + tokens_owed;
+        (fee_growth_inside, fees)
+    })
+}
+
+// Fees of a position not collected yet, from the tokens owed to it by `earned_fees`. The tokens owed hold the
+// withdrawn tokens not collected yet as well: like in `position_pnl`, the collects take them first.
+pub fn unclaimed_fees(tokens_owed: &[(BigInt, BigInt); 2], pool: &Pool, flows: &PositionFlows) -> [BigDecimal; 2] {
+    let decimals = [pool.token0_ref().decimals, pool.token1_ref().decimals];
+    [0, 1].map(|i| {
+        let owed = tokens_owed[i].1.to_decimal(decimals[i]);
+        let uncollected = flows.withdrawn[i].clone() - flows.collected[i].clone();
+        match uncollected > BigDecimal::zero() {
+            true if owed > uncollected => owed - uncollected,
+            true => BigDecimal::zero(),
+            false => owed,
+        }
+    })
+}
+
+// Cumulated deposits, withdrawals and collects of a position, tracked under `position:{token_id}:{flow}` in
+// `store_position_flows`. The collects hold the withdrawn tokens as well as the fees.
+#[derive(Default)]
+pub struct PositionFlows {
+    pub deposited: [BigDecimal; 2],
+    pub withdrawn: [BigDecimal; 2],
+    pub collected: [BigDecimal; 2],
+    pub deposited_usd: BigDecimal,
+    pub collected_usd: BigDecimal,
+}
+
+impl PositionFlows {
+    pub fn load(position_flows_store: &StoreGetBigDecimal, token_id: &str) -> Self {
+        let flow = |name: &str| {
+            position_flows_store
+                .get_last(format!("position:{token_id}:{name}"))
+                .unwrap_or_default()
+        };
+        PositionFlows {
+            deposited: [flow("deposited0"), flow("deposited1")],
+            withdrawn: [flow("withdrawn0"), flow("withdrawn1")],
+            collected: [flow("collected0"), flow("collected1")],
+            deposited_usd: flow("depositedUSD"),
+            collected_usd: flow("collectedUSD"),
+        }
+    }
+}
+
+// Profit and loss of a position holding the token `amounts` with `liquidity`, and the `unclaimed_fees` not
+// collected yet, at the USD `prices` of token0 and token1. The collects take the withdrawn tokens
+// first: only what they hold beyond them counts as fees. The HODL benchmark holds the tokens deposited: the
+// impermanent loss compares it to the tokens of the position and the ones withdrawn from it.
+pub fn position_pnl(
+    flows: &PositionFlows,
+    liquidity: &BigInt,
+    amounts: &[BigDecimal; 2],
+    unclaimed_fees: &[BigDecimal; 2],
+    prices: &[BigDecimal; 2],
+) -> PositionPnl {
+    let value =
+        |tokens: &[BigDecimal; 2]| tokens[0].clone() * prices[0].clone() + tokens[1].clone() * prices[1].clone();
+
+    let fees_earned = [0, 1].map(|i| {
+        let collected_fees = flows.collected[i].clone() - flows.withdrawn[i].clone();
+        match collected_fees > BigDecimal::zero() {
+            true => collected_fees + unclaimed_fees[i].clone(),
+            false => unclaimed_fees[i].clone(),
+        }
+    });
+    let value_usd = value(amounts);
+    let hodl_value_usd = value(&flows.deposited);
+    let withdrawn_value_usd = value(&flows.withdrawn);
+
+    let closed = liquidity.is_zero() && (0..2).all(|i| flows.collected[i] >= flows.withdrawn[i]);
+    let realised_pnl_usd = match closed {
+        true => flows.collected_usd.clone() - flows.deposited_usd.clone(),
+        false => BigDecimal::zero(),
+    };
+
+    PositionPnl {
+        cost_basis_usd: flows.deposited_usd.to_string(),
+        fees_earned_usd: value(&fees_earned).to_string(),
+        fees_earned_token0: fees_earned[0].to_string(),
+        fees_earned_token1: fees_earned[1].to_string(),
+        impermanent_loss_usd: (value_usd.clone() + withdrawn_value_usd - hodl_value_usd.clone()).to_string(),
+        value_usd: value_usd.to_string(),
+        hodl_value_usd: hodl_value_usd.to_string(),
+        realised_pnl_usd: realised_pnl_usd.to_string(),
+        closed,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pb::uniswap::events::CreatedPosition;
+    use crate::positions::{
+        bitset_position, position_amounts, position_pnl, range_swept, set_bits, unclaimed_fees, PositionFlows,
+    };
+    use crate::{Erc20Token, Pool};
+    use std::str::FromStr;
+    use substreams::scalar::{BigDecimal, BigInt};
+
+    #[test]
+    fn test_position_amounts() {
+        let token = |decimals: u64| Erc20Token {
+            decimals,
+            ..Default::default()
+        };
+        let pool = Pool {
+            token0: Some(token(18)),
+            token1: Some(token(6)),
+            ..Default::default()
+        };
+        let position = |tick_lower: i32, tick_upper: i32| CreatedPosition {
+            tick_lower: tick_lower.to_string(),
+            tick_upper: tick_upper.to_string(),
+            ..Default::default()
+        };
+        // 1 ether of liquidity at tick 0
+        let liquidity = BigInt::from_str("1000000000000000000").unwrap();
+        let sqrt_price = BigInt::from_str("79228162514264337593543950336").unwrap();
+
+        let (amount0, amount1) = position_amounts(&position(-60, 60), &pool, &liquidity, &sqrt_price);
+        assert_eq!(BigDecimal::from_str("0.00299535495591078").unwrap(), amount0);
+        assert_eq!(BigDecimal::from_str("2995354955.910780").unwrap(), amount1);
+
+        let (amount0, amount1) = position_amounts(&position(60, 120), &pool, &liquidity, &sqrt_price);
+        assert!(amount0 > BigDecimal::zero());
+        assert_eq!(BigDecimal::zero(), amount1);
+    }
+
+    #[test]
+    fn test_range_swept() {
+        let position = CreatedPosition {
+            tick_lower: "-60".to_string(),
+            tick_upper: "60".to_string(),
+            ..Default::default()
+        };
+
+        assert!(range_swept(&position, Some(&(-10, 10))));
+        assert!(range_swept(&position, Some(&(-120, -60))));
+        assert!(range_swept(&position, Some(&(59, 120))));
+        assert!(!range_swept(&position, Some(&(-120, -61))));
+        assert!(!range_swept(&position, Some(&(60, 120))));
+        assert!(!range_swept(&position, None));
+    }
+
+    #[test]
+    fn test_set_bits() {
+        assert_eq!(Vec::<u64>::new(), set_bits(&BigInt::zero()));
+
+        let (chunk, bit) = bitset_position(0);
+        assert_eq!((0, vec![0]), (chunk, set_bits(&bit)));
+
+        let (chunk, bit) = bitset_position(512 + 255);
+        assert_eq!((2, vec![255]), (chunk, set_bits(&bit)));
+
+        let bitset = bitset_position(3).1 + bitset_position(9).1 + bitset_position(200).1;
+        assert_eq!(vec![3, 9, 200], set_bits(&bitset));
+        assert_eq!(vec![3, 200], set_bits(&(bitset - bitset_position(9).1)));
+    }
+
+    #[test]
+    fn test_unclaimed_fees() {
+        let token = |decimals: u64| Erc20Token {
+            decimals,
+            ..Default::default()
+        };
+        let pool = Pool {
+            token0: Some(token(18)),
+            token1: Some(token(6)),
+            ..Default::default()
+        };
+        let decimal = |value: &str| BigDecimal::from_str(value).unwrap();
+        let tokens_owed = [
+            (BigInt::zero(), BigInt::from_str("10000000000000000").unwrap()),
+            (BigInt::zero(), BigInt::from(121_000_000)),
+        ];
+
+        // 120 token1 withdrawn and not collected yet
+        let mut flows = PositionFlows {
+            withdrawn: [decimal("0"), decimal("120")],
+            ..Default::default()
+        };
+        let fees = unclaimed_fees(&tokens_owed, &pool, &flows);
+        assert_eq!([decimal("0.01"), decimal("1")], fees);
+
+        // and collected
+        flows.collected = [decimal("0"), decimal("120")];
+        let fees = unclaimed_fees(&tokens_owed, &pool, &flows);
+        assert_eq!([decimal("0.01"), decimal("121")], fees);
+    }
+
+    #[test]
+    fn test_position_pnl() {
+        let decimal = |value: &str| BigDecimal::from_str(value).unwrap();
+        let tokens = |amount0: &str, amount1: &str| [decimal(amount0), decimal(amount1)];
+        let prices = tokens("120", "1");
+
+        // 1 token0 and 100 token1 deposited when token0 was worth 100
+        let mut flows = PositionFlows {
+            deposited: tokens("1", "100"),
+            deposited_usd: decimal("200"),
+            ..Default::default()
+        };
+        let pnl = position_pnl(
+            &flows,
+            &BigInt::from(1000),
+            &tokens("0.8", "120"),
+            &tokens("0.01", "1"),
+            &prices,
+        );
+        assert_eq!(decimal("200"), decimal(&pnl.cost_basis_usd));
+        assert_eq!(decimal("216"), decimal(&pnl.value_usd));
+        assert_eq!(decimal("220"), decimal(&pnl.hodl_value_usd));
+        assert_eq!(decimal("-4"), decimal(&pnl.impermanent_loss_usd));
+        assert_eq!(decimal("2.2"), decimal(&pnl.fees_earned_usd));
+        assert_eq!(decimal("0"), decimal(&pnl.realised_pnl_usd));
+        assert!(!pnl.closed);
+
+        // everything withdrawn, the token1 not collected yet
+        flows.withdrawn = tokens("0.8", "120");
+        flows.collected = tokens("0.81", "0");
+        flows.collected_usd = decimal("97.2");
+        let pnl = position_pnl(&flows, &BigInt::zero(), &tokens("0", "0"), &tokens("0", "1"), &prices);
+        assert_eq!(decimal("0.01"), decimal(&pnl.fees_earned_token0));
+        assert_eq!(decimal("1"), decimal(&pnl.fees_earned_token1));
+        assert!(!pnl.closed);
+
+        // and collected
+        flows.collected = tokens("0.81", "121");
+        flows.collected_usd = decimal("218.2");
+        let pnl = position_pnl(&flows, &BigInt::zero(), &tokens("0", "0"), &tokens("0", "0"), &prices);
+        assert_eq!(decimal("2.2"), decimal(&pnl.fees_earned_usd));
+        assert_eq!(decimal("220"), decimal(&pnl.hodl_value_usd));
+        assert_eq!(decimal("-4"), decimal(&pnl.impermanent_loss_usd));
+        assert_eq!(decimal("18.2"), decimal(&pnl.realised_pnl_usd));
+        assert!(pnl.closed);
+    }
+}
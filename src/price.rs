@@ -1,48 +1,15 @@
+use crate::config::Config;
+use crate::pb::uniswap::events::PoolSqrtPrice;
+use crate::whitelist::{self, Whitelist};
 use crate::{math, Erc20Token, Pool};
-use std::ops::{Div, Mul};
+use std::collections::HashSet;
+use std::ops::{Div, Mul, Sub};
 use std::str;
 use std::str::FromStr;
 use substreams::log;
 use substreams::scalar::{BigDecimal, BigInt};
 use substreams::store::{StoreGet, StoreGetBigDecimal, StoreGetBigInt, StoreGetProto, StoreGetRaw};
 
-const USDC_WETH_03_POOL: &str = "8ad599c3a0ff1de082011efddc58f1908eb6e6d8";
-const USDC_ADDRESS: &str = "a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48";
-const WETH_ADDRESS: &str = "c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2";
-
-pub const STABLE_COINS: [&str; 6] = [
-    "6b175474e89094c44da98b954eedeac495271d0f", // DAI
-    "a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48", // USDC
-    "dac17f958d2ee523a2206206994597c13d831ec7", // USDT
-    "0000000000085d4780b73119b644ae5ecd22b376", // TUSD
-    "956f47f50a910163d8bf957cf5846d573e7f87ca", // FEI
-    "4dd28568d05f09b02220b09c2cb307bfd837cb95", // PRINTS
-];
-
-pub const WHITELIST_TOKENS: [&str; 21] = [
-    "c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2", // WETH
-    "6b175474e89094c44da98b954eedeac495271d0f", // DAI
-    "a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48", // USDC
-    "dac17f958d2ee523a2206206994597c13d831ec7", // USDT
-    "0000000000085d4780b73119b644ae5ecd22b376", // TUSD
-    "2260fac5e5542a773aa44fbcfedf7c193bc2c599", // WBTC
-    "5d3a536e4d6dbd6114cc1ead35777bab948e3643", // cDAI
-    "39aa39c021dfbae8fac545936693ac917d5e7563", // cUSDC
-    "86fadb80d8d2cff3c3680819e4da99c10232ba0f", // EBASE
-    "57ab1ec28d129707052df4df418d58a2d46d5f51", // sUSD
-    "9f8f72aa9304c8b593d555f12ef6589cc3a579a2", // MKR
-    "c00e94cb662c3520282e6f5717214004a7f26888", // COMP
-    "514910771af9ca656af840dff83e8264ecf986ca", // LINK
-    "c011a73ee8576fb46f5e1c5751ca3b9fe0af2a6f", // SNX
-    "0bc529c00c6401aef6d220be8c6ea1667f6ad93e", // YFI
-    "111111111117dc0aa78b770fa6a738034120c302", // 1INCH
-    "df5e0e81dff6faf3a7e52ba697820c5e32d806a8", // yCurv
-    "956f47f50a910163d8bf957cf5846d573e7f87ca", // FEI
-    "7d1afa7b718fb893db30a3abc0cfc608aacfebb0", // MATIC
-    "7fc66500c84a76ad7e9c93437bfc5ac33e2ddae9", // AAVE
-    "fe2e637202056d30016725477c5da089ab0a043a", // sETH2
-];
-
 pub fn sqrt_price_x96_to_token_prices(
     sqrt_price: BigDecimal,
     token_0: &Erc20Token,
@@ -70,49 +37,107 @@ pub fn sqrt_price_x96_to_token_prices(
     return (price0, price1);
 }
 
+// Same as `sqrt_price_x96_to_token_prices`, for a tick (e.g. a time-weighted average tick)
+pub fn tick_to_token_prices(tick: i32, token_0: &Erc20Token, token_1: &Erc20Token) -> (BigDecimal, BigDecimal) {
+    let price1 = math::compute_price_from_tick_idx(tick)
+        .mul(math::exponent_to_big_decimal(token_0.decimals))
+        .div(math::exponent_to_big_decimal(token_1.decimals));
+
+    let price0 = math::safe_div(&BigDecimal::one(), &price1);
+
+    (price0, price1)
+}
+
+// Maximum number of hops of a pricing path, the last hop reaching the wrapped native token
+pub const MAX_HOPS: usize = 3;
+
+// Maximum number of pools of a token followed when looking for a multi-hop path
+pub const MAX_POOLS_PER_TOKEN: usize = 32;
+
+// Number of full days without an update after which a derived price is flagged as stale
+pub const DEFAULT_STALE_PRICE_DAYS: i64 = 1;
+
+// Derived price of a token in the wrapped native token with the path it was found through
+#[derive(Clone, Debug, PartialEq)]
+pub struct DerivedEthPrice {
+    pub price: BigDecimal,
+    // first pool of the path, empty for the wrapped native token
+    pub pool_address: String,
+    // tokens from the priced token to the wrapped native token, empty when no price was found
+    pub path: Vec<String>,
+    pub eth_locked: BigDecimal,
+}
+
+impl DerivedEthPrice {
+    fn not_found() -> Self {
+        DerivedEthPrice {
+            price: BigDecimal::zero(),
+            pool_address: "".to_string(),
+            path: vec![],
+            eth_locked: BigDecimal::zero(),
+        }
+    }
+
+    pub fn depth(&self) -> u32 {
+        self.path.len().saturating_sub(1) as u32
+    }
+}
+
+// Stores read to price a token in the wrapped native token
+pub struct PricingStores<'a> {
+    pub pools_store: &'a StoreGetProto<Pool>,
+    pub pool_liquidities_store: &'a StoreGetBigInt,
+    pub tokens_whitelist_pools_store: &'a StoreGetRaw,
+    pub token_pools_store: &'a StoreGetRaw,
+    pub total_native_amounts_store: &'a StoreGetBigDecimal,
+    pub token_tvl_store: &'a StoreGetBigDecimal,
+    pub prices_store: &'a StoreGetBigDecimal,
+}
+
+// Price of the token in the wrapped native token of the network (WETH on Ethereum Mainnet), the `eth`
+// naming is kept for the store keys and the entity fields. Tokens without a usable pool against a
+// whitelisted token are priced through `find_eth_per_token_multi_hop`.
 pub fn find_eth_per_token(
     ord: u64,
     pool_address: &String,
     token_address: &String,
-    pools_store: &StoreGetProto<Pool>,
-    pool_liquidities_store: &StoreGetBigInt,
-    tokens_whitelist_pools_store: &StoreGetRaw,
-    total_native_amounts_store: &StoreGetBigDecimal,
-    prices_store: &StoreGetBigDecimal,
-) -> BigDecimal {
+    stores: &PricingStores,
+    whitelist: &Whitelist,
+    eth_price_usd: &BigDecimal,
+    config: &Config,
+) -> DerivedEthPrice {
+    let PricingStores {
+        pools_store,
+        pool_liquidities_store,
+        tokens_whitelist_pools_store,
+        token_pools_store,
+        total_native_amounts_store,
+        prices_store,
+        ..
+    } = stores;
     log::debug!("finding ETH per token for {} in pool {}", token_address, pool_address);
-    if token_address.eq(WETH_ADDRESS) {
-        log::debug!("is ETH return 1");
-        return BigDecimal::one();
+    if token_address.eq(&config.wrapped_native_token) {
+        log::debug!("is the wrapped native token return 1");
+        return DerivedEthPrice {
+            price: BigDecimal::one(),
+            path: vec![token_address.clone()],
+            ..DerivedEthPrice::not_found()
+        };
     }
 
     let mut price_so_far = BigDecimal::zero();
+    // pool and complementary token of the largest pool found so far
+    let mut price_source: Option<(String, String)> = None;
+    let mut largest_eth_locked = BigDecimal::zero();
 
-    if STABLE_COINS.contains(&token_address.as_str()) {
+    if config.is_stable_coin(token_address) {
         log::debug!("token addr: {} is a stable coin", token_address);
-        let eth_price_usd = get_eth_price_in_usd(prices_store, ord);
-        log::info!("eth_price_usd {}", eth_price_usd);
-        price_so_far = math::safe_div(&BigDecimal::one(), &eth_price_usd);
+        price_so_far = math::safe_div(&BigDecimal::one(), eth_price_usd);
+        price_source = Some((config.usd_reference_pool.clone(), config.wrapped_native_token.clone()));
     } else {
-        // TODO: @eduard change this once the changes for store of list has been merged
-        let wl = match tokens_whitelist_pools_store.get_last(&format!("token:{token_address}")) {
-            None => {
-                log::debug!("failed to get whitelisted pools for token {}", token_address);
-                return BigDecimal::zero();
-            }
-            Some(bytes) => String::from_utf8(bytes.to_vec()).unwrap(),
-        };
-
-        let mut whitelisted_pools: Vec<&str> = vec![];
-        for p in wl.split(";") {
-            if !p.is_empty() {
-                whitelisted_pools.push(p);
-            }
-        }
+        let whitelisted_pools = whitelist::whitelist_pools(tokens_whitelist_pools_store, token_address);
         log::debug!("found whitelisted pools {}", whitelisted_pools.len());
 
-        let mut largest_eth_locked = BigDecimal::zero();
-        let minimum_eth_locked = BigDecimal::from_str("52").unwrap();
         let mut eth_locked: BigDecimal;
 
         for pool_address in whitelisted_pools.iter() {
@@ -152,32 +177,23 @@ pub fn find_eth_per_token(
                     log::debug!("native amount value of token1 in pool {}", native_amount);
 
                     let token1_eth_price;
-                    // If the counter token is WETH we know the derived price is 1
-                    if token1.address.eq(WETH_ADDRESS) {
-                        log::debug!("token 1 is WETH");
+                    // If the counter token is the wrapped native token we know the derived price is 1
+                    if token1.address.eq(&config.wrapped_native_token) {
+                        log::debug!("token 1 is the wrapped native token");
                         eth_locked = native_amount;
                         token1_eth_price = BigDecimal::one();
                     } else {
-                        log::debug!("token 1 is NOT WETH");
-
-                        match pool_liquidities_store.get_at(ord, format!("pair:{WETH_ADDRESS}:{token1_addr}")) {
-                            None => {
-                                log::debug!("unable to find liquidity for {:?}", token1_addr);
-                                continue;
-                            }
-                            Some(l) => {
-                                // There is no liquidity in the pool. We can't compute the eth_price
-                                // of the token.
-                                if l.eq(&BigInt::zero()) {
-                                    continue;
-                                }
-
-                                // Else we have enough liquidity to compute the price
-                            }
-                        }
+                        log::debug!("token 1 is NOT the wrapped native token");
 
-                        token1_eth_price = match prices_store.get_at(ord, format!("pair:{WETH_ADDRESS}:{token1_addr}"))
-                        {
+                        token1_eth_price = match native_pair_eth_price(
+                            ord,
+                            token1_addr,
+                            pools_store,
+                            pool_liquidities_store,
+                            token_pools_store,
+                            prices_store,
+                            config,
+                        ) {
                             None => {
                                 log::debug!("unable to find token 1 price in eth {token1_addr}");
                                 continue;
@@ -195,11 +211,11 @@ pub fn find_eth_per_token(
                     );
                     // should the check below make more sens if we EITHER have eth.gt > largest && (eth_locked > min BUT !Whitelist || whitelist)???
                     if eth_locked.gt(&largest_eth_locked)
-                        && (eth_locked.gt(&minimum_eth_locked) || WHITELIST_TOKENS.contains(&token0_addr.as_str()))
+                        && (eth_locked.gt(&config.minimum_native_locked) || whitelist.contains(token0_addr))
                     {
                         log::debug!("eth locked passed test");
                         let token1_price =
-                            match prices_store.get_at(ord, format!("pool:{pool_address}:{token1_addr}:token1")) {
+                            match prices_store.get_at(ord, format!("guarded:{pool_address}:{token1_addr}:token1")) {
                                 None => {
                                     log::debug!("unable to find pool {pool_address} for token {token1_addr} price",);
                                     continue;
@@ -209,6 +225,7 @@ pub fn find_eth_per_token(
                         log::debug!("found token 1 price {}", token1_price);
                         largest_eth_locked = eth_locked.clone();
                         price_so_far = token1_price.mul(token1_eth_price.clone());
+                        price_source = Some((pool_address.to_string(), token1_addr.clone()));
                         log::debug!("price_so_far {}", price_so_far);
                     }
                 }
@@ -228,33 +245,24 @@ pub fn find_eth_per_token(
 
                     let mut token0_eth_price = BigDecimal::zero();
 
-                    // If the counter token is WETH we know the derived price is 1
-                    if token0.address.eq(WETH_ADDRESS) {
-                        log::debug!("token 0 is WETH");
+                    // If the counter token is the wrapped native token we know the derived price is 1
+                    if token0.address.eq(&config.wrapped_native_token) {
+                        log::debug!("token 0 is the wrapped native token");
                         eth_locked = native_amount
                     } else {
-                        log::debug!("token 0 is NOT WETH");
-
-                        match pool_liquidities_store.get_at(ord, format!("pair:{WETH_ADDRESS}:{token0_addr}")) {
-                            None => {
-                                log::debug!("unable to find liquidity for {:?}", token0_addr);
-                                continue;
-                            }
-                            Some(l) => {
-                                // There is no liquidity in the pool. We can't compute the eth_price
-                                // of the token.
-                                if l.eq(&BigInt::zero()) {
-                                    continue;
-                                }
-
-                                // Else we have enough liquidity to compute the price
-                            }
-                        }
+                        log::debug!("token 0 is NOT the wrapped native token");
 
-                        token0_eth_price = match prices_store.get_at(ord, format!("pair:{WETH_ADDRESS}:{token0_addr}"))
-                        {
+                        token0_eth_price = match native_pair_eth_price(
+                            ord,
+                            token0_addr,
+                            pools_store,
+                            pool_liquidities_store,
+                            token_pools_store,
+                            prices_store,
+                            config,
+                        ) {
                             None => {
-                                log::debug!("unable to find token 0 price in eth {:?}", token0.address);
+                                log::debug!("unable to find token 0 price in eth {token0_addr}");
                                 continue;
                             }
                             Some(price) => price,
@@ -265,11 +273,11 @@ pub fn find_eth_per_token(
                     }
                     log::debug!("eth locked in pool {pool_address} {eth_locked} (largest {largest_eth_locked})",);
                     if eth_locked.gt(&largest_eth_locked)
-                        && (eth_locked.gt(&minimum_eth_locked) || WHITELIST_TOKENS.contains(&token1_addr.as_str()))
+                        && (eth_locked.gt(&config.minimum_native_locked) || whitelist.contains(token1_addr))
                     {
                         log::debug!("eth locked passed test");
                         let token0_price =
-                            match prices_store.get_at(ord, format!("pool:{pool_address}:{token0_addr}:token0")) {
+                            match prices_store.get_at(ord, format!("guarded:{pool_address}:{token0_addr}:token0")) {
                                 None => {
                                     log::debug!("unable to find pool {pool_address} for token {token0_addr} price",);
                                     continue;
@@ -279,22 +287,577 @@ pub fn find_eth_per_token(
                         log::debug!("found token 0 price {}", token0_price);
                         largest_eth_locked = eth_locked.clone();
                         price_so_far = token0_price.mul(token0_eth_price.clone());
+                        price_source = Some((pool_address.to_string(), token0_addr.clone()));
                         log::debug!("price_so_far {}", price_so_far);
                     }
                 }
             }
         }
     }
-    return price_so_far;
+
+    if price_so_far.is_zero() {
+        return find_eth_per_token_multi_hop(ord, token_address, stores, whitelist, config);
+    }
+
+    let (pool_address, counter_token) = price_source.unwrap();
+    let mut path = vec![token_address.clone(), counter_token.clone()];
+    if counter_token != config.wrapped_native_token {
+        path.push(config.wrapped_native_token.clone());
+    }
+
+    DerivedEthPrice {
+        price: price_so_far,
+        pool_address,
+        path,
+        eth_locked: largest_eth_locked,
+    }
+}
+
+// A path from the priced token through pools with active liquidity
+#[derive(Clone, Debug)]
+struct PricePath {
+    tokens: Vec<String>,
+    pools: Vec<String>,
+    // amount of the last token of the path for one priced token
+    rate: BigDecimal,
+    // for each pool of the path, the amount locked of the token received and the rate after the hop
+    hops: Vec<(BigDecimal, BigDecimal)>,
+}
+
+impl PricePath {
+    fn new(token_address: &str) -> Self {
+        PricePath {
+            tokens: vec![token_address.to_string()],
+            pools: vec![],
+            rate: BigDecimal::one(),
+            hops: vec![],
+        }
+    }
+
+    fn last_token(&self) -> &String {
+        self.tokens.last().unwrap()
+    }
+
+    fn extend(&self, pool_address: &str, token_address: &str, rate: BigDecimal, amount_locked: BigDecimal) -> Self {
+        let mut path = self.clone();
+        path.tokens.push(token_address.to_string());
+        path.pools.push(pool_address.to_string());
+        path.rate = self.rate.clone().mul(rate);
+        path.hops.push((amount_locked, path.rate.clone()));
+        path
+    }
+
+    // Price of the priced token in the wrapped native token and ETH locked in the shallowest pool of
+    // the path, given the price in the wrapped native token of the last token of the path
+    fn priced(&self, last_token_eth_price: &BigDecimal) -> (BigDecimal, BigDecimal) {
+        let price = self.rate.clone().mul(last_token_eth_price.clone());
+        let eth_locked = self
+            .hops
+            .iter()
+            .map(|(amount_locked, rate)| math::safe_div(&amount_locked.clone().mul(price.clone()), rate))
+            .min()
+            .unwrap_or_else(BigDecimal::zero);
+        (price, eth_locked)
+    }
 }
 
-pub fn get_eth_price_in_usd(prices_store: &StoreGetBigDecimal, ordinal: u64) -> BigDecimal {
-    let key = format!("pool:{}:{}:{}", USDC_WETH_03_POOL, USDC_ADDRESS, "token0");
-    return match prices_store.get_at(ordinal, &key) {
+// Breadth-first search of paths of at most `MAX_HOPS` hops to the wrapped native token, or to a
+// whitelisted token paired with it. Only the pools with active liquidity are followed, and only the
+// paths whose shallowest pool holds at least `minimum_native_locked` are kept. The price is the
+// average of the prices of the shortest paths found, weighted by their ETH locked, the heaviest path
+// is the one returned.
+fn find_eth_per_token_multi_hop(
+    ord: u64,
+    token_address: &String,
+    stores: &PricingStores,
+    whitelist: &Whitelist,
+    config: &Config,
+) -> DerivedEthPrice {
+    let PricingStores {
+        pools_store,
+        pool_liquidities_store,
+        token_pools_store,
+        token_tvl_store,
+        prices_store,
+        ..
+    } = stores;
+    let native = &config.wrapped_native_token;
+    let mut visited: HashSet<String> = HashSet::from([token_address.clone()]);
+    let mut frontier = vec![PricePath::new(token_address)];
+    let mut candidates: Vec<(PricePath, BigDecimal, BigDecimal)> = vec![];
+
+    for hop in 1..=MAX_HOPS {
+        let mut next_frontier = vec![];
+
+        for path in frontier.iter() {
+            let current_token = path.last_token();
+            for pool_address in token_pools(token_pools_store, current_token)
+                .iter()
+                .take(MAX_POOLS_PER_TOKEN)
+            {
+                if path.pools.contains(pool_address) {
+                    continue;
+                }
+                let pool = match pools_store.get_last(format!("pool:{pool_address}")) {
+                    None => continue,
+                    Some(p) => p,
+                };
+                let (counter_token, counter_key) = if &pool.token0_ref().address == current_token {
+                    let token1_addr = &pool.token1_ref().address;
+                    (token1_addr.clone(), format!("{pool_address}:{token1_addr}:token1"))
+                } else {
+                    let token0_addr = &pool.token0_ref().address;
+                    (token0_addr.clone(), format!("{pool_address}:{token0_addr}:token0"))
+                };
+                if path.tokens.contains(&counter_token) {
+                    continue;
+                }
+
+                match pool_liquidities_store.get_at(ord, format!("pool:{pool_address}")) {
+                    Some(liquidity) if liquidity.gt(&BigInt::zero()) => {}
+                    _ => continue,
+                }
+                let rate = match prices_store.get_at(ord, format!("guarded:{counter_key}")) {
+                    Some(price) if !price.is_zero() => price,
+                    _ => continue,
+                };
+                let amount_locked = match token_tvl_store.get_at(ord, format!("pool:{counter_key}")) {
+                    Some(amount) if amount.gt(&BigDecimal::zero()) => amount,
+                    _ => continue,
+                };
+                let next_path = path.extend(pool_address, &counter_token, rate, amount_locked);
+
+                if &counter_token == native {
+                    let (price, eth_locked) = next_path.priced(&BigDecimal::one());
+                    candidates.push((next_path, price, eth_locked));
+                    continue;
+                }
+
+                if hop < MAX_HOPS && whitelist.contains(&counter_token) {
+                    if let Some(counter_eth_price) = native_pair_eth_price(
+                        ord,
+                        &counter_token,
+                        pools_store,
+                        pool_liquidities_store,
+                        token_pools_store,
+                        prices_store,
+                        config,
+                    ) {
+                        let (price, eth_locked) = next_path.priced(&counter_eth_price);
+                        let mut next_path = next_path;
+                        next_path.tokens.push(native.clone());
+                        candidates.push((next_path, price, eth_locked));
+                        continue;
+                    }
+                }
+
+                if hop < MAX_HOPS && visited.insert(counter_token) {
+                    next_frontier.push(next_path);
+                }
+            }
+        }
+
+        candidates.retain(|(_, _, eth_locked)| eth_locked.ge(&config.minimum_native_locked));
+        if !candidates.is_empty() {
+            break;
+        }
+        frontier = next_frontier;
+    }
+
+    let price = match weighted_eth_price(&candidates) {
+        None => {
+            log::debug!("no multi-hop path found for token {}", token_address);
+            return DerivedEthPrice::not_found();
+        }
+        Some(price) => price,
+    };
+    let (path, _, eth_locked) = candidates
+        .into_iter()
+        .max_by(|(_, _, left), (_, _, right)| left.cmp(right))
+        .unwrap();
+    log::debug!("multi-hop path {:?} with {} eth locked", path.tokens, eth_locked);
+
+    DerivedEthPrice {
+        price,
+        pool_address: path.pools[0].clone(),
+        path: path.tokens,
+        eth_locked,
+    }
+}
+
+// Price in the wrapped native token of a token, from its pool against the wrapped native token with the
+// most active liquidity. Only the guarded prices are read: a pool left out by the price guards doesn't
+// price the token.
+pub fn native_pair_eth_price(
+    ord: u64,
+    token_address: &str,
+    pools_store: &StoreGetProto<Pool>,
+    pool_liquidities_store: &StoreGetBigInt,
+    token_pools_store: &StoreGetRaw,
+    prices_store: &StoreGetBigDecimal,
+    config: &Config,
+) -> Option<BigDecimal> {
+    let native = &config.wrapped_native_token;
+    let mut deepest: Option<(BigInt, BigDecimal)> = None;
+    for pool_address in token_pools(token_pools_store, token_address)
+        .iter()
+        .take(MAX_POOLS_PER_TOKEN)
+    {
+        let Some(pool) = pools_store.get_last(format!("pool:{pool_address}")) else {
+            continue;
+        };
+        let native_key = match (pool.token0_ref().address(), pool.token1_ref().address()) {
+            (token0, _) if token0 == native => "token0",
+            (_, token1) if token1 == native => "token1",
+            _ => continue,
+        };
+        let liquidity = match pool_liquidities_store.get_at(ord, format!("pool:{pool_address}")) {
+            Some(liquidity) if liquidity.gt(&BigInt::zero()) => liquidity,
+            _ => continue,
+        };
+        let price = match prices_store.get_at(ord, format!("guarded:{pool_address}:{native}:{native_key}")) {
+            Some(price) if !price.is_zero() => price,
+            _ => continue,
+        };
+        if deepest
+            .as_ref()
+            .map_or(true, |(deepest_liquidity, _)| liquidity.gt(deepest_liquidity))
+        {
+            deepest = Some((liquidity, price));
+        }
+    }
+    deepest.map(|(_, price)| price)
+}
+
+pub fn token_pools(token_pools_store: &StoreGetRaw, token_address: &str) -> Vec<String> {
+    match token_pools_store.get_last(format!("token:{token_address}")) {
+        None => vec![],
+        Some(bytes) => String::from_utf8(bytes.to_vec())
+            .unwrap()
+            .split(';')
+            .filter(|pool| !pool.is_empty())
+            .map(|pool| pool.to_string())
+            .collect(),
+    }
+}
+
+// Average of the prices of the paths weighted by their ETH locked
+fn weighted_eth_price(candidates: &[(PricePath, BigDecimal, BigDecimal)]) -> Option<BigDecimal> {
+    let mut total_eth_locked = BigDecimal::zero();
+    let mut weighted_sum = BigDecimal::zero();
+    for (_, price, eth_locked) in candidates {
+        total_eth_locked = total_eth_locked + eth_locked.clone();
+        weighted_sum = weighted_sum + price.clone().mul(eth_locked.clone());
+    }
+
+    if total_eth_locked.is_zero() {
+        return None;
+    }
+    Some(weighted_sum.div(total_eth_locked))
+}
+
+// Relative deviation of a price from a reference price
+pub fn price_deviation(price: &BigDecimal, reference: &BigDecimal) -> BigDecimal {
+    math::safe_div(&price.clone().sub(reference.clone()), reference)
+}
+
+// Confidence in a derived price, between 0 and 1. The wrapped native token and the stable coins are
+// trusted, other tokens scale with the ETH locked along their path up to ten times the
+// `minimum_native_locked`, and lose a fifth for every hop after the first one.
+pub fn price_confidence(token_address: &str, derived_eth_price: &DerivedEthPrice, config: &Config) -> BigDecimal {
+    if derived_eth_price.price.is_zero() {
+        return BigDecimal::zero();
+    }
+    if token_address == config.wrapped_native_token || config.is_stable_coin(token_address) {
+        return BigDecimal::one();
+    }
+
+    let full_confidence_locked = config.minimum_native_locked.clone().mul(BigDecimal::from(10));
+    let liquidity_factor = if full_confidence_locked.is_zero() {
+        BigDecimal::one()
+    } else {
+        derived_eth_price
+            .eth_locked
+            .clone()
+            .div(full_confidence_locked)
+            .min(BigDecimal::one())
+    };
+
+    // the depth is at most MAX_HOPS
+    let extra_hops = derived_eth_price.depth().saturating_sub(1);
+    let depth_factor = BigDecimal::one().sub(BigDecimal::from(extra_hops).mul(BigDecimal::from_str("0.2").unwrap()));
+
+    liquidity_factor.mul(depth_factor)
+}
+
+// Pool of the wrapped native token with one of the `usd_bundle_tokens`
+#[derive(Clone, Debug, PartialEq)]
+pub struct UsdBundlePool {
+    pub address: String,
+    // key of the price of the wrapped native token in the stable coin in `store_prices`
+    pub price_key: String,
+    pub native_is_token0: bool,
+    pub native_decimals: u64,
+}
+
+// Pools of the wrapped native token with the `usd_bundle_tokens`, on every fee tier. The wrapped native
+// token is whitelisted so these are found in the whitelisted pools of the stable coins.
+pub fn usd_bundle_pools(
+    pools_store: &StoreGetProto<Pool>,
+    tokens_whitelist_pools_store: &StoreGetRaw,
+    config: &Config,
+) -> Vec<UsdBundlePool> {
+    let mut bundle_pools = vec![];
+    for stable_coin in config.usd_bundle_tokens.iter() {
+        for pool_address in whitelist::whitelist_pools(tokens_whitelist_pools_store, stable_coin) {
+            let pool = match pools_store.get_last(format!("pool:{pool_address}")) {
+                None => continue,
+                Some(p) => p,
+            };
+            let token0 = pool.token0_ref();
+            let token1 = pool.token1_ref();
+
+            let (native, price_key) = if token0.address == config.wrapped_native_token {
+                (token0, format!("guarded:{pool_address}:{stable_coin}:token1"))
+            } else if token1.address == config.wrapped_native_token {
+                (token1, format!("guarded:{pool_address}:{stable_coin}:token0"))
+            } else {
+                continue;
+            };
+
+            bundle_pools.push(UsdBundlePool {
+                native_is_token0: native.address == token0.address,
+                native_decimals: native.decimals,
+                address: pool_address,
+                price_key,
+            });
+        }
+    }
+    bundle_pools
+}
+
+// Price of the wrapped native token in USD, blended over the `usd_bundle_tokens` pools weighted by the
+// amount of wrapped native token of their active liquidity. The pools holding less than
+// `minimum_native_locked` are left out, unless they are all below it, in which case the deepest pool is
+// used. Without any active liquidity the price is read from the reference pool of the network.
+pub fn get_eth_price_in_usd(
+    ordinal: u64,
+    bundle_pools: &[UsdBundlePool],
+    pool_liquidities_store: &StoreGetBigInt,
+    pool_sqrt_price_store: &StoreGetProto<PoolSqrtPrice>,
+    prices_store: &StoreGetBigDecimal,
+    config: &Config,
+) -> BigDecimal {
+    let mut quotes = vec![];
+    for bundle_pool in bundle_pools {
+        let pool_address = &bundle_pool.address;
+        let price = match prices_store.get_at(ordinal, &bundle_pool.price_key) {
+            Some(price) if !price.is_zero() => price,
+            _ => continue,
+        };
+        let liquidity = match pool_liquidities_store.get_at(ordinal, format!("pool:{pool_address}")) {
+            Some(liquidity) if liquidity.gt(&BigInt::zero()) => liquidity,
+            _ => continue,
+        };
+        let sqrt_price = match pool_sqrt_price_store.get_at(ordinal, format!("pool:{pool_address}")) {
+            None => continue,
+            Some(sqrt_price) => BigInt::try_from(sqrt_price.sqrt_price).unwrap(),
+        };
+
+        let native_liquidity = active_native_liquidity(
+            &liquidity,
+            &sqrt_price,
+            bundle_pool.native_is_token0,
+            bundle_pool.native_decimals,
+        );
+        log::debug!("bundle pool {pool_address} price {price} native liquidity {native_liquidity}");
+        quotes.push((price, native_liquidity));
+    }
+
+    if let Some(price) = blend_eth_price_usd(&quotes, &config.minimum_native_locked) {
+        return price;
+    }
+
+    // the stable coin is either the token0 or the token1 of the reference pool depending on the network
+    log::debug!("no bundle pool with active liquidity, falling back to the reference pool");
+    let key_prefix = format!("guarded:{}:{}", config.usd_reference_pool, config.usd_reference_token);
+    match prices_store
+        .get_at(ordinal, format!("{key_prefix}:token0"))
+        .or_else(|| prices_store.get_at(ordinal, format!("{key_prefix}:token1")))
+    {
         None => {
             log::debug!("price not found");
             BigDecimal::zero()
         }
         Some(price) => price,
+    }
+}
+
+// Amount of the wrapped native token backing the active liquidity of a pool: `L * sqrtP` when it is the
+// token1 and `L / sqrtP` when it is the token0
+pub fn active_native_liquidity(
+    liquidity: &BigInt,
+    sqrt_price_x96: &BigInt,
+    native_is_token0: bool,
+    native_decimals: u64,
+) -> BigDecimal {
+    if sqrt_price_x96.is_zero() {
+        return BigDecimal::zero();
+    }
+
+    let q96 = BigInt::from(2).pow(96);
+    let amount = if native_is_token0 {
+        liquidity.clone().mul(q96).div(sqrt_price_x96.clone())
+    } else {
+        liquidity.clone().mul(sqrt_price_x96.clone()).div(q96)
+    };
+    amount.to_decimal(native_decimals)
+}
+
+// Liquidity weighted average of the `(price, native liquidity)` quotes above the floor, or the price of
+// the deepest quote when none is above it
+fn blend_eth_price_usd(
+    quotes: &[(BigDecimal, BigDecimal)],
+    minimum_native_liquidity: &BigDecimal,
+) -> Option<BigDecimal> {
+    let mut total_liquidity = BigDecimal::zero();
+    let mut weighted_sum = BigDecimal::zero();
+    for (price, native_liquidity) in quotes {
+        if native_liquidity.lt(minimum_native_liquidity) {
+            continue;
+        }
+        total_liquidity = total_liquidity + native_liquidity.clone();
+        weighted_sum = weighted_sum + price.clone().mul(native_liquidity.clone());
+    }
+
+    if !total_liquidity.is_zero() {
+        return Some(weighted_sum.div(total_liquidity));
+    }
+
+    quotes
+        .iter()
+        .filter(|(_, native_liquidity)| !native_liquidity.is_zero())
+        .max_by(|(_, left), (_, right)| left.cmp(right))
+        .map(|(price, _)| price.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::price::{
+        active_native_liquidity, blend_eth_price_usd, price_confidence, price_deviation, weighted_eth_price,
+        DerivedEthPrice, PricePath,
     };
+    use std::str::FromStr;
+    use substreams::scalar::{BigDecimal, BigInt};
+
+    fn dec(value: &str) -> BigDecimal {
+        BigDecimal::from_str(value).unwrap()
+    }
+
+    #[test]
+    fn test_price_path_eth_locked_is_the_shallowest_pool() {
+        let token = "token".to_string();
+        let path = PricePath::new(&token)
+            // 2 A per token, 100 A locked in the first pool
+            .extend("pool_a", "a", dec("2"), dec("100"))
+            // 0.5 ETH per A, 10 ETH locked in the second pool
+            .extend("pool_eth", "eth", dec("0.5"), dec("10"));
+
+        let (price, eth_locked) = path.priced(&BigDecimal::one());
+        assert_eq!(dec("1"), price);
+        assert_eq!(dec("10"), eth_locked);
+        assert_eq!(vec!["token", "a", "eth"], path.tokens);
+    }
+
+    #[test]
+    fn test_weighted_eth_price() {
+        let path = PricePath::new("token");
+        let candidates = vec![(path.clone(), dec("2"), dec("30")), (path.clone(), dec("4"), dec("10"))];
+        assert_eq!(Some(dec("2.5")), weighted_eth_price(&candidates));
+        assert_eq!(None, weighted_eth_price(&[]));
+    }
+
+    #[test]
+    fn test_depth() {
+        let mut derived = DerivedEthPrice::not_found();
+        assert_eq!(0, derived.depth());
+        derived.path = vec!["token".to_string(), "usdc".to_string(), "weth".to_string()];
+        assert_eq!(2, derived.depth());
+    }
+
+    #[test]
+    fn test_active_native_liquidity() {
+        // USDC/WETH 0.3% at ~3000 USDC per WETH, WETH is the token1
+        let liquidity = BigInt::from_str("10000000000000000000").unwrap();
+        let sqrt_price = BigInt::from_str("1446501726624926496477173928747177").unwrap();
+        let amount = active_native_liquidity(&liquidity, &sqrt_price, false, 18);
+        assert_eq!(dec("182574"), amount.with_prec(6));
+
+        // same pool seen from a pool where WETH is the token0
+        let sqrt_price = BigInt::from_str("4339505179874779489431521").unwrap();
+        let amount = active_native_liquidity(&liquidity, &sqrt_price, true, 18);
+        assert_eq!(dec("182574"), amount.with_prec(6));
+    }
+
+    #[test]
+    fn test_blend_eth_price_usd() {
+        let floor = dec("52");
+        let quotes = vec![
+            (dec("3000"), dec("300")),
+            (dec("3010"), dec("100")),
+            // thin pool, left out
+            (dec("2000"), dec("1")),
+        ];
+        assert_eq!(Some(dec("3002.5")), blend_eth_price_usd(&quotes, &floor));
+
+        // every pool is thin, the deepest one is used
+        let quotes = vec![(dec("3000"), dec("3")), (dec("2000"), dec("1"))];
+        assert_eq!(Some(dec("3000")), blend_eth_price_usd(&quotes, &floor));
+
+        assert_eq!(None, blend_eth_price_usd(&[], &floor));
+    }
+
+    #[test]
+    fn test_price_deviation() {
+        assert_eq!(dec("0.01"), price_deviation(&dec("3030"), &dec("3000")));
+        assert_eq!(dec("-0.02"), price_deviation(&dec("2940"), &dec("3000")));
+        assert_eq!(dec("0"), price_deviation(&dec("3000"), &dec("0")));
+    }
+
+    #[test]
+    fn test_price_confidence() {
+        let config = Config::from_params("network=mainnet&minimum_native_locked=60").unwrap();
+        let path = |tokens: &[&str]| tokens.iter().map(|token| token.to_string()).collect::<Vec<_>>();
+        let derived = |eth_locked: &str, tokens: &[&str]| DerivedEthPrice {
+            price: dec("0.01"),
+            pool_address: "pool".to_string(),
+            path: path(tokens),
+            eth_locked: dec(eth_locked),
+        };
+
+        // deep pool against the wrapped native token
+        assert_eq!(
+            dec("1"),
+            price_confidence("token", &derived("6000", &["token", "weth"]), &config)
+        );
+        // half of the fully trusted liquidity
+        assert_eq!(
+            dec("0.5"),
+            price_confidence("token", &derived("300", &["token", "weth"]), &config)
+        );
+        // two hops
+        assert_eq!(
+            dec("0.8"),
+            price_confidence("token", &derived("600", &["token", "usdc", "weth"]), &config)
+        );
+        // stable coins are trusted
+        let usdc = "a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48";
+        assert_eq!(dec("1"), price_confidence(usdc, &derived("0", &[usdc]), &config));
+        // no price
+        assert_eq!(
+            dec("0"),
+            price_confidence("token", &DerivedEthPrice::not_found(), &config)
+        );
+    }
 }
@@ -1,20 +1,43 @@
+use crate::uniswap::erc20_token::MetadataStatus;
 use crate::{abi, eth, utils, Erc20Token};
-use prost::Message;
 use substreams::log;
 use substreams::scalar::BigInt;
 use substreams::Hex;
+use substreams_ethereum::pb::eth::rpc::RpcResponse;
 use substreams_ethereum::rpc::RpcBatch;
 
-pub fn create_uniswap_token(token_address: &String) -> Option<Erc20Token> {
+// Metadata and total supply of a token, read with a single batch of `eth_call`s. The metadata is the reason it
+// can't be used when the decimals of the token can't be read, the total supply zero when it can't be read.
+pub struct TokenCalls {
+    pub token: Result<Erc20Token, String>,
+    pub total_supply: BigInt,
+}
+
+pub fn create_uniswap_token(token_address: &String) -> TokenCalls {
     let batch = RpcBatch::new();
     let responses = batch
         .add(abi::erc20::functions::Decimals {}, hex::decode(token_address).unwrap())
         .add(abi::erc20::functions::Name {}, hex::decode(token_address).unwrap())
         .add(abi::erc20::functions::Symbol {}, hex::decode(token_address).unwrap())
+        .add(
+            abi::erc20::functions::TotalSupply {},
+            hex::decode(token_address).unwrap(),
+        )
         .execute()
         .unwrap()
         .responses;
 
+    TokenCalls {
+        token: decode_token(token_address, &responses),
+        total_supply: RpcBatch::decode::<_, abi::erc20::functions::TotalSupply>(&responses[3])
+            .unwrap_or(BigInt::zero()),
+    }
+}
+
+fn decode_token(token_address: &String, responses: &[RpcResponse]) -> Result<Erc20Token, String> {
+    let static_token = utils::get_static_uniswap_tokens(hex::decode(token_address).unwrap().as_slice());
+    let mut fallback_reasons: Vec<String> = vec![];
+
     let decimals: u64;
     match RpcBatch::decode::<_, abi::erc20::functions::Decimals>(&responses[0]) {
         Some(decoded_decimals) => {
@@ -24,75 +47,149 @@ pub fn create_uniswap_token(token_address: &String) -> Option<Erc20Token> {
                     "ignoring token address {} because the decimals are bigger than 255",
                     token_address,
                 );
-                return None;
+                return Err(format!("decimals: {decoded_decimals} above 254"));
             }
             decimals = decoded_decimals.to_u64();
         }
-        None => match utils::get_static_uniswap_tokens(token_address.encode_to_vec().as_slice()) {
-            Some(token) => decimals = token.decimals,
+        None => match &static_token {
+            Some(token) => {
+                decimals = token.decimals;
+                fallback_reasons.push("decimals: static override".to_string());
+            }
             None => {
                 log::debug!(
                     "{} is not an ERC20 token contract decimal `eth_call` failed",
                     Hex(&token_address),
                 );
 
-                return None;
+                return Err("decimals: call failed".to_string());
             }
         },
     };
     log::debug!("decoded_decimals ok");
 
-    let name: String;
-    match RpcBatch::decode::<_, abi::erc20::functions::Name>(&responses[1]) {
-        Some(decoded_name) => {
-            name = decoded_name;
-        }
-        None => match utils::get_static_uniswap_tokens(token_address.encode_to_vec().as_slice()) {
-            Some(token) => name = token.name,
-            None => {
-                log::debug!(
-                    "{} is not an ERC20 token contract name `eth_call` failed",
-                    &token_address,
-                );
-                name = eth::read_string_from_bytes(responses[1].raw.as_ref());
-            }
-        },
-    };
+    // `name()` and `symbol()` have the same selector whether they return a `string` or a `bytes32`, the
+    // same response is decoded with both ABIs
+    let name = decode_token_string(
+        "name",
+        RpcBatch::decode::<_, abi::erc20::functions::Name>(&responses[1]),
+        RpcBatch::decode::<_, abi::erc20_name_bytes::functions::Name>(&responses[1]),
+        static_token.as_ref().map(|token| token.name.clone()),
+        eth::MAX_NAME_LENGTH,
+        &mut fallback_reasons,
+    );
     log::debug!("decoded_name ok");
 
-    let symbol: String;
-    match RpcBatch::decode::<_, abi::erc20::functions::Symbol>(&responses[2]) {
-        Some(decoded_symbol) => {
-            symbol = decoded_symbol;
-        }
-        None => match utils::get_static_uniswap_tokens(token_address.encode_to_vec().as_slice()) {
-            Some(token) => symbol = token.symbol,
-            None => {
-                log::debug!(
-                    "{} is not a an ERC20 token contract symbol `eth_call` failed",
-                    &token_address,
-                );
-                symbol = eth::read_string_from_bytes(responses[2].raw.as_ref());
-            }
-        },
-    };
+    let symbol = decode_token_string(
+        "symbol",
+        RpcBatch::decode::<_, abi::erc20::functions::Symbol>(&responses[2]),
+        RpcBatch::decode::<_, abi::erc20_symbol_bytes::functions::Symbol>(&responses[2]),
+        static_token.as_ref().map(|token| token.symbol.clone()),
+        eth::MAX_SYMBOL_LENGTH,
+        &mut fallback_reasons,
+    );
     log::debug!("decoded_symbol ok");
 
-    return Some(Erc20Token {
+    let metadata_status = match fallback_reasons
+        .iter()
+        .any(|reason| reason.ends_with("static override"))
+    {
+        true => MetadataStatus::Overridden,
+        false => MetadataStatus::Resolved,
+    };
+
+    Ok(Erc20Token {
         address: token_address.clone(),
         name,
         symbol,
         decimals,
         total_supply: "".to_string(),
         whitelist_pools: vec![],
-    });
+        metadata_status: metadata_status as i32,
+        metadata_fallback_reason: fallback_reasons.join("; "),
+    })
 }
 
-pub fn token_total_supply_call(token_address: &String) -> Option<BigInt> {
-    let token_supply = abi::erc20::functions::TotalSupply {};
-    if let Some(token_supply_result) = token_supply.call(hex::decode(token_address).unwrap()) {
-        return Some(token_supply_result);
-    };
+// Decodes a name or symbol as a `string`, then as a `bytes32`, then takes the static value. Every step
+// past the `string` is recorded in `fallback_reasons`, as well as the sanitizing of the value.
+fn decode_token_string(
+    field: &str,
+    string_value: Option<String>,
+    bytes32_value: Option<[u8; 32]>,
+    static_value: Option<String>,
+    max_length: usize,
+    fallback_reasons: &mut Vec<String>,
+) -> String {
+    if let Some(value) = string_value {
+        let (value, sanitized) = eth::sanitize_string(&value, max_length);
+        if sanitized {
+            fallback_reasons.push(format!("{field}: sanitized"));
+        }
+        return value;
+    }
+
+    match bytes32_value.map(|bytes| eth::decode_bytes32_string(&bytes)) {
+        Some(Ok(value)) => {
+            let (value, sanitized) = eth::sanitize_string(&value, max_length);
+            fallback_reasons.push(match sanitized {
+                true => format!("{field}: bytes32, sanitized"),
+                false => format!("{field}: bytes32"),
+            });
+            return value;
+        }
+        Some(Err(err)) => fallback_reasons.push(format!("{field}: {}", err.msg)),
+        None => fallback_reasons.push(format!("{field}: call failed")),
+    }
+
+    match static_value {
+        Some(value) => {
+            fallback_reasons.push(format!("{field}: static override"));
+            value
+        }
+        None => "".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eth;
+    use crate::rpc::decode_token_string;
+
+    fn bytes32(value: &[u8]) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        bytes[0..value.len()].copy_from_slice(value);
+        bytes
+    }
+
+    #[test]
+    fn test_decode_token_string() {
+        let mut reasons = vec![];
+        let decode = |string_value: Option<&str>, bytes32_value: Option<[u8; 32]>, reasons: &mut Vec<String>| {
+            decode_token_string(
+                "symbol",
+                string_value.map(str::to_string),
+                bytes32_value,
+                Some("STATIC".to_string()),
+                eth::MAX_SYMBOL_LENGTH,
+                reasons,
+            )
+        };
+
+        assert_eq!("MKR", decode(Some("MKR"), None, &mut reasons));
+        assert!(reasons.is_empty());
+
+        assert_eq!("MKR", decode(None, Some(bytes32(b"MKR")), &mut reasons));
+        assert_eq!(vec!["symbol: bytes32"], reasons);
+
+        reasons.clear();
+        assert_eq!("STATIC", decode(None, Some(bytes32(&[0xff, 0xfe])), &mut reasons));
+        assert_eq!(
+            vec!["symbol: invalid utf-8 in bytes32", "symbol: static override"],
+            reasons
+        );
 
-    return None;
+        reasons.clear();
+        assert_eq!("", decode_token_string("name", None, None, None, 64, &mut reasons));
+        assert_eq!(vec!["name: call failed"], reasons);
+    }
 }
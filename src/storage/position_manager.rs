@@ -262,6 +262,44 @@ impl<'a> PositionStruct<'a> {
             None
         }
     }
+
+    pub fn tokens_owed0(&self) -> Option<(BigInt, BigInt)> {
+        let slot = BigInt::from(4);
+        let offset = 0;
+        let number_of_bytes = 16;
+
+        let slot_key = utils::calc_struct_slot(&self.struct_slot, slot);
+
+        if let Some((old_data, new_data)) =
+            utils::get_storage_change(&self.storage_changes, slot_key, offset, number_of_bytes)
+        {
+            Some((
+                BigInt::from_signed_bytes_be(old_data),
+                BigInt::from_signed_bytes_be(new_data),
+            ))
+        } else {
+            None
+        }
+    }
+
+    pub fn tokens_owed1(&self) -> Option<(BigInt, BigInt)> {
+        let slot = BigInt::from(4);
+        let offset = 16;
+        let number_of_bytes = 16;
+
+        let slot_key = utils::calc_struct_slot(&self.struct_slot, slot);
+
+        if let Some((old_data, new_data)) =
+            utils::get_storage_change(&self.storage_changes, slot_key, offset, number_of_bytes)
+        {
+            Some((
+                BigInt::from_signed_bytes_be(old_data),
+                BigInt::from_signed_bytes_be(new_data),
+            ))
+        } else {
+            None
+        }
+    }
 }
 
 pub struct PoolKeyStruct<'a> {
@@ -448,6 +486,22 @@ mod tests {
         assert_eq!(None, v_opt);
     }
 
+    #[test]
+    fn position_tokens_owed0() {
+        let changes = get_store_changes();
+        let storage = get_position_manager(&changes);
+        let v_opt = storage.positions(&BigInt::from_str("1").unwrap()).tokens_owed0();
+        assert_eq!(None, v_opt);
+    }
+
+    #[test]
+    fn position_tokens_owed1() {
+        let changes = get_store_changes();
+        let storage = get_position_manager(&changes);
+        let v_opt = storage.positions(&BigInt::from_str("1").unwrap()).tokens_owed1();
+        assert_eq!(None, v_opt);
+    }
+
     #[test]
     fn pool_ids() {
         let changes = get_store_changes();
@@ -3,6 +3,10 @@ use hex::encode;
 use substreams::scalar::BigInt;
 use substreams_ethereum::pb::eth::v2::StorageChange;
 
+// Multiples of the tick spacing hashed looking for the ticks crossed by a swap, which covers the whole tick range
+// of the pools with a tick spacing of 60 and more
+pub const MAX_WALKED_TICKS: i32 = 65536;
+
 pub struct UniswapPoolStorage<'a> {
     pub storage_changes: &'a Vec<StorageChange>,
     pub contract_addr: [u8; 20],
@@ -58,6 +62,50 @@ impl<'a> UniswapPoolStorage<'a> {
         }
     }
 
+    // accumulated protocol fees in token0 that are not yet collected
+    pub fn protocol_fees_token0(&self) -> Option<(BigInt, BigInt)> {
+        let protocol_fees_slot = BigInt::from(3);
+        let offset = 0;
+        let number_of_bytes = 16;
+
+        // ----
+        let slot_key = utils::left_pad_from_bigint(&protocol_fees_slot);
+        // ----
+
+        if let Some((old_data, new_data)) =
+            utils::get_storage_change(&self.filtered_changes(), slot_key, offset, number_of_bytes)
+        {
+            Some((
+                BigInt::from_signed_bytes_be(old_data),
+                BigInt::from_signed_bytes_be(new_data),
+            ))
+        } else {
+            None
+        }
+    }
+
+    // accumulated protocol fees in token1 that are not yet collected
+    pub fn protocol_fees_token1(&self) -> Option<(BigInt, BigInt)> {
+        let protocol_fees_slot = BigInt::from(3);
+        let offset = 16;
+        let number_of_bytes = 16;
+
+        // ----
+        let slot_key = utils::left_pad_from_bigint(&protocol_fees_slot);
+        // ----
+
+        if let Some((old_data, new_data)) =
+            utils::get_storage_change(&self.filtered_changes(), slot_key, offset, number_of_bytes)
+        {
+            Some((
+                BigInt::from_signed_bytes_be(old_data),
+                BigInt::from_signed_bytes_be(new_data),
+            ))
+        } else {
+            None
+        }
+    }
+
     pub fn liquidity(&self) -> Option<(BigInt, BigInt)> {
         let liquidity_slot = BigInt::from(4);
         let offset = 0;
@@ -90,6 +138,86 @@ impl<'a> UniswapPoolStorage<'a> {
         return TickStruct::new(self.filtered_changes(), ticker_struct_slot);
     }
 
+    // Initialized ticks crossed by a swap moving the current tick from `tick_from` to `tick_to`: crossing a
+    // tick flips its outside values and keeps it initialized. The slots of the ticks are hashes, so the multiples
+    // of `tick_spacing` in between are hashed in the direction of the swap until every written slot holding an
+    // initialized tick is matched, for at most `MAX_WALKED_TICKS` multiples.
+    pub fn crossed_ticks(&self, tick_from: i32, tick_to: i32, tick_spacing: i32) -> Vec<i32> {
+        let mut crossed_ticks = vec![];
+        if tick_spacing <= 0 || tick_from == tick_to {
+            return crossed_ticks;
+        }
+
+        // the variables and the `observations` array of the pool are below, the mappings entries are hashes. The
+        // last slot of a tick, from `tickCumulativeOutside` to `initialized`, is written on every crossing
+        let hashed_slots_start = BigInt::from(8 + 65535);
+        let mut tick_slots: Vec<&Vec<u8>> = self
+            .filtered_changes()
+            .into_iter()
+            .filter(|change| BigInt::from_unsigned_bytes_be(&change.key) >= hashed_slots_start)
+            .filter(|change| {
+                utils::read_bytes(&change.old_value, 31, 1) == [1u8]
+                    && utils::read_bytes(&change.new_value, 31, 1) == [1u8]
+            })
+            .map(|change| &change.key)
+            .collect();
+
+        // a swap going up crosses the ticks in (tick_from, tick_to], going down in (tick_to, tick_from]
+        let (mut tick, step) = match tick_to > tick_from {
+            true => (
+                tick_from.div_euclid(tick_spacing) * tick_spacing + tick_spacing,
+                tick_spacing,
+            ),
+            false => (tick_from.div_euclid(tick_spacing) * tick_spacing, -tick_spacing),
+        };
+        let ticks_slot = utils::left_pad_from_bigint(&BigInt::from(5));
+        let mut walked_ticks = 0;
+        while !tick_slots.is_empty()
+            && walked_ticks < MAX_WALKED_TICKS
+            && ((step > 0 && tick <= tick_to) || (step < 0 && tick > tick_to))
+        {
+            let tick_struct_slot = utils::calc_map_slot(&utils::left_pad_from_bigint(&BigInt::from(tick)), &ticks_slot);
+            let outside_slot = utils::calc_struct_slot(&tick_struct_slot, BigInt::from(3));
+
+            let tick_slots_count = tick_slots.len();
+            tick_slots.retain(|key| key.as_slice() != outside_slot.as_slice());
+            if tick_slots.len() < tick_slots_count {
+                crossed_ticks.push(tick);
+            }
+            tick += step;
+            walked_ticks += 1;
+        }
+
+        crossed_ticks
+    }
+
+    // observations written in the `observations` fixed size array, with their index in the array
+    pub fn observations(&self) -> Vec<(u64, ObservationStruct)> {
+        let observations_slot = BigInt::from(8);
+        let observations_end = observations_slot.clone() + BigInt::from(65535);
+
+        let mut observations: Vec<(u64, ObservationStruct)> = vec![];
+        for change in self.filtered_changes() {
+            let slot = BigInt::from_unsigned_bytes_be(&change.key);
+            if slot < observations_slot || slot >= observations_end {
+                continue;
+            }
+
+            let index = (slot - observations_slot.clone()).to_u64();
+            match observations.iter_mut().find(|(idx, _)| *idx == index) {
+                // keep the change with the highest ordinal
+                Some((_, observation)) => {
+                    if change.ordinal > observation.storage_change.ordinal {
+                        observation.storage_change = change;
+                    }
+                }
+                None => observations.push((index, ObservationStruct::new(change))),
+            }
+        }
+
+        observations
+    }
+
     fn filtered_changes(&self) -> Vec<&StorageChange> {
         return self
             .storage_changes
@@ -326,9 +454,61 @@ impl<'a> TickStruct<'a> {
     }
 }
 
+// An observation fits in a single slot, so unlike the other structs the values are read
+// straight from the latest write instead of only being returned when they changed.
+pub struct ObservationStruct<'a> {
+    pub storage_change: &'a StorageChange,
+}
+
+impl<'a> ObservationStruct<'a> {
+    pub fn new(storage_change: &'a StorageChange) -> ObservationStruct<'a> {
+        Self { storage_change }
+    }
+
+    // the block timestamp of the observation
+    pub fn block_timestamp(&self) -> BigInt {
+        let offset = 0;
+        let number_of_bytes = 4;
+        BigInt::from_unsigned_bytes_be(utils::read_bytes(
+            &self.storage_change.new_value,
+            offset,
+            number_of_bytes,
+        ))
+    }
+
+    // the tick accumulator, i.e. tick * time elapsed since the pool was first initialized
+    pub fn tick_cumulative(&self) -> BigInt {
+        let offset = 4;
+        let number_of_bytes = 7;
+        BigInt::from_signed_bytes_be(utils::read_bytes(
+            &self.storage_change.new_value,
+            offset,
+            number_of_bytes,
+        ))
+    }
+
+    // the seconds per liquidity, i.e. seconds elapsed / max(1, liquidity) since the pool was first initialized
+    pub fn seconds_per_liquidity_cumulative_x128(&self) -> BigInt {
+        let offset = 11;
+        let number_of_bytes = 20;
+        BigInt::from_unsigned_bytes_be(utils::read_bytes(
+            &self.storage_change.new_value,
+            offset,
+            number_of_bytes,
+        ))
+    }
+
+    // whether or not the observation is initialized
+    pub fn initialized(&self) -> bool {
+        let offset = 31;
+        let number_of_bytes = 1;
+        utils::read_bytes(&self.storage_change.new_value, offset, number_of_bytes) == [1u8]
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::storage::uniswap_v3_pool::UniswapPoolStorage;
+    use crate::storage::uniswap_v3_pool::{UniswapPoolStorage, MAX_WALKED_TICKS};
     use crate::storage::utils;
     use std::ops::Add;
     use std::str::FromStr;
@@ -469,6 +649,54 @@ mod tests {
         assert_eq!(None, v_opt);
     }
 
+    #[test]
+    fn protocol_fees() {
+        let storage_changes = vec![StorageChange {
+            address: hex!("7858e59e0c01ea06df3af3d20ac7b0003275d4bf").to_vec(),
+            key: hex!("0000000000000000000000000000000000000000000000000000000000000003").to_vec(),
+            old_value: hex!("00000000000000000000000000000001000000000000000000000000000003e8").to_vec(),
+            new_value: hex!("00000000000000000000000000000001000000000000000000000000000007d0").to_vec(),
+            ordinal: 0,
+        }];
+
+        let storage = UniswapPoolStorage::new(
+            &storage_changes,
+            &hex!("7858e59e0c01ea06df3af3d20ac7b0003275d4bf").to_vec(),
+        );
+        assert_eq!(
+            Some((BigInt::from(1000), BigInt::from(2000))),
+            storage.protocol_fees_token0()
+        );
+        // token1 side of the packed slot is unchanged
+        assert_eq!(None, storage.protocol_fees_token1());
+    }
+
+    #[test]
+    fn observations() {
+        let storage_changes = vec![StorageChange {
+            address: hex!("7858e59e0c01ea06df3af3d20ac7b0003275d4bf").to_vec(),
+            // slot 8 + index 2
+            key: hex!("000000000000000000000000000000000000000000000000000000000000000a").to_vec(),
+            old_value: hex!("0000000000000000000000000000000000000000000000000000000000000001").to_vec(),
+            new_value: hex!("010000000000000000000000000000000000000000fffffffffffffe605a63b4").to_vec(),
+            ordinal: 0,
+        }];
+
+        let storage = UniswapPoolStorage::new(
+            &storage_changes,
+            &hex!("7858e59e0c01ea06df3af3d20ac7b0003275d4bf").to_vec(),
+        );
+        let observations = storage.observations();
+        assert_eq!(1, observations.len());
+
+        let (index, observation) = &observations[0];
+        assert_eq!(2, *index);
+        assert_eq!(BigInt::from(1616536500), observation.block_timestamp());
+        assert_eq!(BigInt::from(-2), observation.tick_cumulative());
+        assert_eq!(BigInt::zero(), observation.seconds_per_liquidity_cumulative_x128());
+        assert!(observation.initialized());
+    }
+
     #[test]
     fn slot0_unlocked() {
         // derived from: https://etherscan.io/tx/0x37d8f4b1b371fde9e4b1942588d16a1cbf424b7c66e731ec915aca785ca2efcf#statechange
@@ -600,4 +828,41 @@ mod tests {
         }
         s
     }
+
+    #[test]
+    fn crossed_ticks() {
+        let pool_address = hex!("7858e59e0c01ea06df3af3d20ac7b0003275d4bf").to_vec();
+        let ticks_slot = utils::left_pad_from_bigint(&BigInt::from(5));
+        let tick_change = |tick: i32| {
+            let tick_struct_slot = utils::calc_map_slot(&utils::left_pad_from_bigint(&BigInt::from(tick)), &ticks_slot);
+            StorageChange {
+                address: pool_address.clone(),
+                key: utils::calc_struct_slot(&tick_struct_slot, BigInt::from(3)).to_vec(),
+                old_value: [1u8; 32].to_vec(),
+                new_value: [1u8; 32].to_vec(),
+                ordinal: 0,
+            }
+        };
+        let storage_changes = vec![
+            StorageChange {
+                address: pool_address.clone(),
+                key: [0u8; 32].to_vec(),
+                old_value: [0u8; 32].to_vec(),
+                new_value: [1u8; 32].to_vec(),
+                ordinal: 0,
+            },
+            tick_change(-60),
+            tick_change(60),
+            tick_change(120),
+            // a tick further than the walk goes
+            tick_change(MAX_WALKED_TICKS + 60),
+        ];
+
+        let storage = UniswapPoolStorage::new(&storage_changes, &pool_address);
+        assert_eq!(vec![60, 120], storage.crossed_ticks(0, 150, 60));
+        assert_eq!(vec![120, 60, -60], storage.crossed_ticks(150, -61, 60));
+        assert_eq!(vec![120, 60], storage.crossed_ticks(150, -60, 60));
+        assert_eq!(Vec::<i32>::new(), storage.crossed_ticks(0, 150, 0));
+        assert_eq!(vec![60, 120], storage.crossed_ticks(0, MAX_WALKED_TICKS + 60, 1));
+    }
 }
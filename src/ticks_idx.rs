@@ -1,892 +0,0 @@
-use phf::phf_map;
-
-pub static ONE_POINT_0001: phf::Map<i32, &'static str> = phf_map! {
-  0i32 => "1.000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000",
-  1000i32 => "1.105165392603232697240184240109058537464789419664949673403655468578346746949907378191279017285076620",
-  2000i32 => "1.221390545007857464989220754764367385549895750931220041142828307680269907897184603529662401160408599",
-  3000i32 => "1.349838561195485151223686935939829766876792197031156365163116813787604440236495787171011181518605129",
-  4000i32 => "1.491794863434591091891067253329980885149809983932183738783723693824320043783482075104701190349734638",
-  5000i32 => "1.648680055931175769628200045451048976844011249465030400193594869887965262948202327990257576290138060",
-  6000i32 => "1.822064141290297511487512369795985740159755030167687708132207296972292435599657887713041032731847612",
-  7000i32 => "2.013682232057363701574887430263204486789636330099603478642266500597134886935841942569039195455915081",
-  8000i32 => "2.225451914569830285966179585361440318061459440642128053353732887291068209852152745542394269595139874",
-  9000i32 => "2.459492438885182360367575755434237558023891554282588594402845527655085574844378209907539860340856485",
-  10000i32 => "2.718145926825224864037664674913146536113822649220720818370865873787419773771513968473052781477839575",
-  11000i32 => "3.004000810372677451283533239519888195160583958061221367960568389746638042295077774074625947649453879",
-  12000i32 => "3.319917734975949252890136361774236582089507290649671184387751839546757164964011605825904603336261464",
-  13000i32 => "3.669058186985129996527499701549515152220409541118014171723276576933525363753300644771027263976983293",
-  14000i32 => "4.054916131703526357249493065700680659508987983765265729070991544014706767981743836064620305183576524",
-  15000i32 => "4.481352978667309329602220404449746305940395021850256815356699966359066155959234832376865802127694691",
-  16000i32 => "4.952636224062523197441909949824985478162173083692895430036823646659669475153636558057640343027706856",
-  17000i32 => "5.473482156987050389900659139958031357082817101871266786196137110290875892544791420433225970712816322",
-  18000i32 => "6.049103056933382487933634752871202109993929390987687272474093908710437433177201969067257621833058135",
-  19000i32 => "6.685259354813196728080878810241429190246682568931917078090446282972961579761518512897515620479628131",
-  20000i32 => "7.388317279516560681116760369564549470424627564421172512950637460869645899834041965337621032549730221",
-  21000i32 => "8.165312566894167916227226598899402285858185137749020053733296825927685039503999267896832687156010855",
-  22000i32 => "9.024020868719702831187827265054526589780144470845346257262977469423554082404695907717933156539994954",
-  23000i32 => "9.973035566238375366142701815989970650166778350118846977446524109555868934059798162270892202975938869",
-  24000i32 => "11.02185376700783722126050586648187080407648197197552855641945477891254186924205693264939911847342065",
-  25000i32 => "12.18097134563063566616558053878863881051554227772870061741386232244698251659645857562705424735153072",
-  26000i32 => "13.46198797948260915303722981822388248120386545277559742065493551630982879374177997713947726797889446",
-  27000i32 => "14.87772323056489702087741041984433050380785466126840126860594060909717173796339539809883059753838626",
-  28000i32 => "16.44234483514948991068422578270940699212599765315203985071837459584567242773032512067858706305624071",
-  29000i32 => "18.17151048505572141960181667220236683559969676629173789929602387047951226438855477072109410910282527",
-  30000i32 => "20.08252451941036578736548967116063494831058370214029035150289380052164694323980878937764704196094784",
-  31000i32 => "22.19451109495820394749321973453532178412648816611090089359156142034387074464345265850846966387563860",
-  32000i32 => "24.52860556789628748098612385671685113373349849061130187064989175110436503351900498244882114263522340",
-  33000i32 => "27.10816600245394006553140072137161838660920019422955026707149731028429292547631384446049194292738784",
-  34000i32 => "29.95900692285561372936246930260022567447767471836355169428406748357402533990222090584087005370007041",
-  35000i32 => "33.10965764790069065932299942063779516703445193273457646068427382270817133315853229684311837105141480",
-  36000i32 => "36.59164779340079285722366908634316874095663272492410925288676924692129255204424185936168849591228186",
-  37000i32 => "40.43982279959300064604039247428437780289647394321682768275078799029708600862784449366026855891821345",
-  38000i32 => "44.69269264111735938275106798797525773656282801989234953800426642704906916537218722935561683635276017",
-  39000i32 => "49.39281720921607532907072030575899443370271916432494781313692570417774452445907228256680313879667832",
-  40000i32 => "54.58723222280299225316077424718818450942293428240758913973342084740780973016516483015307397057023480",
-  41000i32 => "60.32791993063790360087513711755216652938522658883699797415050337122158464266422826431330771616341833",
-  42000i32 => "66.67232931507982540176795358105560303406085119686331799830227826610497358871545604627085060929865388",
-  43000i32 => "73.68395100327221579545784651268606563573497112385276036935782751040554085419069419621291533857426335",
-  44000i32 => "81.43295263908870015931324110008260439790653786748339912442573194835936573957265224619058916078025390",
-  45000i32 => "89.99688107421891749904753661994663127501838646230519901037796269776281860157576249342601646274354499",
-  46000i32 => "99.46143840545559236525569769958519673807016025516048540960358273356890424906390656427183205245360826",
-  47000i32 => "109.9213396242475764356514468281896581210058701064595402320859020330469228013633489820267393152847636",
-  48000i32 => "121.4812604613048517023458995676546996433980766922935961814737754263707048711613769090102662562209356",
-  49000i32 => "134.2568849116535456753352474782600186848554869627064300147920424341351172778932468972779296529209057",
-  50000i32 => "148.3760629230746189827935464799882462676280513781300373466249603422259898890879435541438194844058525",
-  51000i32 => "163.9800898333017197763352689154169102199197853403111290999911461746116204370057988392803408108843752",
-  52000i32 => "181.2251203597342616747180298993758344964303901838126245078876216244653189327728779606446963205530710",
-  53000i32 => "200.2837312919338144818498546988307553979180660615238949473207580218600938544335545720175994767096968",
-  54000i32 => "221.3466485252903959604503966153202713043433168766770654632413589328597611268941722857706220645711482",
-  55000i32 => "244.6246557188623181804523539120800352205510588414817649899236624449009237765938309359457067024821816",
-  56000i32 => "270.3507036779671065466691294620080785276135488995900006463973099123907092655108133980896133922276730",
-  57000i32 => "298.7822415708207432501972502414542691217526106740166451453524779082461627723793054002021420548154966",
-  58000i32 => "330.2037933084900199460700972445486757091004528803988603065226313702555309480566435671995478001568608",
-  59000i32 => "364.9298048708540746982071127873297201168914741815678993802716357832223545131933546751614071182995054",
-  60000i32 => "403.3077910727185433342207735504894953563371826785598105962324122387093000808195982519676033403694306",
-  61000i32 => "445.7218132608235360764097935617471704568390277283776809519630277736398839184555730061464484522196424",
-  62000i32 => "492.5963227442228131228238725808394575472937701994814685425664738929864944954810836263591065313451216",
-  63000i32 => "544.4004084205277294198222314756072329818322172501997887991778688791618200520830570892043583652317489",
-  64000i32 => "601.6524911054327556813006198118053814571932005555533500474128862182985637314546437434286163645814118",
-  65000i32 => "664.9255115432485597729709855118103730191200402556230684500592801777665700341761801717446957784951961",
-  66000i32 => "734.8526640165996293069739997801434130924785709370700095969883880082365056564549567301397108397149845",
-  67000i32 => "812.1337329334367784189648846558205618819522495992601513253056750936160854497554629390178148044071604",
-  68000i32 => "897.5420958037105893657889798210989000253005522103263521960796124348787567104142597627665737842344233",
-  69000i32 => "991.9324626868361078892733969503553069157988556671531954217416663420080559807056241070379093603976600",
-  70000i32 => "1096.249429561188695361881682884785188142009843978577500778068226805937065684098488408645173113531775",
-  71000i32 => "1211.536931212060992737791771361185591626883774441486119135451867300360937289904156956662469262132154",
-  72000i32 => "1338.948688236293112988117904975704186194630861539789508230998961696517511167920432719244258299790894",
-  73000i32 => "1479.759752710246295513399256854095747624047609110430406160235903891194995641175678687639113299517137",
-  74000i32 => "1635.379268062481876492483635079151128208894690812848752611719019310013131941379057359254772962580694",
-  75000i32 => "1807.364570843460110410720495275712364245638628977004441619255061568564742367314757964473072515344848",
-  76000i32 => "1997.436775513385768524675690426864667540301263438651490119863722370756062414353197798674753026354778",
-  77000i32 => "2207.497998210386157778470561924605069475451283752485303917276297413259796294607808539460880689757736",
-  78000i32 => "2439.650391863031688145375912340470224210726465455116375549990746420245191585944861549687929206787125",
-  79000i32 => "2696.217183137937912144215158486900606259249593531272329235768248782542929862847153050211357108881317",
-  80000i32 => "2979.765921746221306476153191223793533659879608519817366164824205216193897228927544129444108588671234",
-  81000i32 => "3293.134174772396228809826822112852746862216732929419411377394286969837418254088348542029340165717940",
-  82000i32 => "3639.457923157457999613714820914925666201882048960837089406482289773174711183446713941852354807446387",
-  83000i32 => "4022.202944509257967345059569086326180381579456869413505257236026799971253049681399503160364299868159",
-  84000i32 => "4445.199496298452660173756740834312836832340517226603638122380186542321410523055274570395395826579188",
-  85000i32 => "4912.680646526371665097196634871849141929198096106274755968138274555003007076935654912657685605740729",
-  86000i32 => "5429.324635452620576678591366224605704578762569681259622092807259183763001990729810839258497470818427",
-  87000i32 => "6000.301692310398661088925823320532828936730908091868846219175456740109032845192981113751251744773401",
-  88000i32 => "6631.325775520063296065861702255492160196855861655709176573096979235866704444992089364955766155682809",
-  89000i32 => "7328.711754182567290306764407996397219705729083303009967614375175667619277288136585573494709167590280",
-  90000i32 => "8099.438603087103177729645247787383050366418947017545521606866802567385276606777617066518392743888225",
-  91000i32 => "8951.219243646536988231532342303455020071491792642910384945370336807393532351166297358233607114233789",
-  92000i32 => "9892.577729682236687974543242048465759891167865489595907127647163626848305262181354388631946324768213",
-  93000i32 => "10932.93455048226549123656162312219825112989469443910429682452015023945016026856353375438950335580395",
-  94000i32 => "12082.70090478918032830834543106831584979912676203242954594877648422201320288852626261848208699525436",
-  95000i32 => "13353.38288914876941163597307634463625359251908511666399158774655362509815332872438162920942918269889",
-  96000i32 => "14757.69664326738947063041587474763890952869199270602452003004746687922767849953332809857621134597031",
-  97000i32 => "16309.69560467601379629653644387946718393050424679532684219601093990456427162461781667134849163688329",
-  98000i32 => "18024.91114618098549095655930601066601091476075798336893186094285193222092559655455679502637637898707",
-  99000i32 => "19920.50800350749390128473503490489938827065769615004105537352858724560598897197167017403294737742688",
-  100000i32 => "22015.45604855219864570145658165871552059764182711541172046938857013283360232194591555605588689798352",
-  101000i32 => "24330.72012723740458187939069404195782944245301004353598378593597370627336072728963753851130565576019",
-  102000i32 => "26889.46986173770203994479116337598663771971548375856830001345434348724006655212149839455295388251743",
-  103000i32 => "29717.31151664014070820946277824575772058257185513182640396363802439169033435884090011341329178094473",
-  104000i32 => "32842.54424940016960963309089201045225713163430437493954154506010764408125313435479813412127259215667",
-  105000i32 => "36296.44330947738076329240835150707777210680161078046416950645833085005488924329990790739754442615820",
-  106000i32 => "40113.57302021954822419879339970253800461708980712789122784964711038607207766138864849930859307369429",
-  107000i32 => "44332.13267560937978818768505483493133336062133937593091206818446988301838895930152004272002250447074",
-  108000i32 => "48994.33881337844102080687181043013735373542315216594077383679474512716009178745896572030445267582238",
-  109000i32 => "54146.84769002318676700263133684158845950263787979726515124866037786782530760020053752066396794081949",
-  110000i32 => "59841.22218557191867154980213913487677258180003070889048281887677171156821398693386939686039930832131",
-  111000i32 => "66134.44781057486810798715779416200536658074258450515086345504621099870107664243013006622828816037695",
-  112000i32 => "73089.50297917197719117520047616640322388010071738161281349663602965269618322035766784319223731575715",
-  113000i32 => "80775.98925515174402997059273577732192879967411822677516047506384394143299887671973251749812434947943",
-  114000i32 => "89270.82787808428308099543716306660487579506835915288111826919334017140903079919586765210469784207423",
-  115000i32 => "98659.02953989862720600830529659645141864643496111972780499862072183581986750299753950404367353985616",
-  116000i32 => "109034.5451153159984728420955143843690281978344504112201746120025943538883754236619039518033354664551",
-  117000i32 => "120501.2058596830933983785609734354948444503053723145774380273883078951810838249271790446717346088976",
-  118000i32 => "133173.7624830796303428229810602427507120044625616696256498912261829375357443609499106858235328138533",
-  119000i32 => "147179.0334990623609793328354719786263028006296387043688794830727337892885554076335201377940897052113",
-  120000i32 => "162657.1743399555911590126963185692351792908055454306306638977195196341070885268023821477284105066917",
-  121000i32 => "179763.0799391494881684002967920979991715026190092493992415080582304588610303282973499566782781187213",
-  122000i32 => "198667.9348165164478141254764059975936167329786017433435365695364775720912362174035283290007222174452",
-  123000i32 => "219560.9261791688422974498491871367244072679856083156787651789669384335687112416378446404596497807519",
-  124000i32 => "242651.1371811305255394603433466859538469616301777919425229721936792195350696714835593733758739733298",
-  125000i32 => "268169.6392884049922311550252488395726783765849052139035870916435693561833107380200489831087840211261",
-  126000i32 => "296371.8046884373991197732234165732597146705945219127478526362156167141598947547628618356549300050437",
-  127000i32 => "327539.8618850255191938118030938096425195253810103849845652580318487973531015085167114644386022829351",
-  128000i32 => "361985.7200533728411884139060297861460459539783026882807301825791324722882283959589915734319097401811",
-  129000i32 => "400054.0904195496792243735483536491239673378652087301556616483247551547937103699088196359681843952316",
-  130000i32 => "442125.9359010507737092325507546354472833638807654997230524288774995213785671876563241621927162449027",
-  131000i32 => "488622.2835301564723716989512793638285353061369773723125730853219999896781366001050727844901063384622",
-  132000i32 => "540008.4378122934596355601827274765529383466734213150079793540080988061750707644421179441497548269445",
-  133000i32 => "596798.6371838816702111381261834483576374172634069604926869464944302608652111302920870419533125791192",
-  134000i32 => "659561.2001683988137582062420101379251191267908588285783984553628638329917163769822381757983192863848",
-  135000i32 => "728924.2127299678227919792709308099261966597141500484886277314290679049245358766352815491163311993856",
-  136000i32 => "805581.8137397171979520606360117875378390582221627595312762865110197048887719972347204394881992577722",
-  137000i32 => "890301.1414556788333936800071223920124652747336464935560026361675658162163616260113814211587954128803",
-  138000i32 => "983930.0105319715074499530071566240685584105682587029682531201341244882842782154860347274598079469901",
-  139000i32 => "1087405.396383669173712522294602139529290194292832062778024192311382544427522684570797340941792448401",
-  140000i32 => "1201762.811813231615018368238257980514432662947332801533929270513508655342699519401920644200251384213",
-  141000i32 => "1328146.669733534971011611676394612961297919129687366146380865416063755539271569477202349211318580083",
-  142000i32 => "1467821.735690738209697684925424906742795025241962352353609080775882344514939546712342381828713704842",
-  143000i32 => "1622185.784796213148978412978459033182873477302488510376282253013731892268660176407682025985519143932",
-  144000i32 => "1792783.589729690051293586892158671038518453256634309939338405895710082402195712780167307424265190357",
-  145000i32 => "1981322.379796245759976957859553713930936323026383144962229157264577263957898998345442028530667818828",
-  146000i32 => "2189688.925741089268720102594289308912713159076175694390909821957029594949444444468722656176336328577",
-  147000i32 => "2419968.421295601768963804295890090506521431543468553903724570088219240792761887211030984948053883720",
-  148000i32 => "2674465.350408578954886992574165464567303902373877996852094185680400821091040703171237959546403417645",
-  149000i32 => "2955726.548988039467843015026708478087494675133860237836992859376653025452873356250665343122763689625",
-  150000i32 => "3266566.691940164740240673700522414625714709057462325657582132070108748912897476028802526655151654328",
-  151000i32 => "3610096.460562695241986673902130840569641329524279751426749884408049715023522745137458279502304956999",
-  152000i32 => "3989753.672173311852889214385337941758623029986822042483850444527181922399153646798203572956325842251",
-  153000i32 => "4409337.683497607554825591663157746248021576235452883914254081653994312704380755723391115402494156442",
-  154000i32 => "4873047.412102862048250034121285788944943332022462809250833313420567151704824138233462436292948115357",
-  155000i32 => "5385523.356370826614058280978140336843337108407983355356005864829633320885384692134062563328084968624",
-  156000i32 => "5951894.034517444072603336513509877197303719228200712858320958730526017374632226837912037887128697913",
-  157000i32 => "6577827.307390309701466462885814442154743780889470401977769627406152441874703273801352590498026252351",
-  158000i32 => "7269587.098648276626839781436329604609720274523424836103073947183303660250030665062661608157465995913",
-  159000i32 => "8034096.079941017941765869989481606348312914775023529506033191572173310688287409428948803877648876507",
-  160000i32 => "8879004.948400107878680321995007089460528647084297009215633415669293300002534797417482370421214925946",
-  161000i32 => "9812768.989724651100416366467501561924874747045927945605855507010838713566550813672835332374667954974",
-  162000i32 => "10844732.69305387111052132404412295088076568277101403651434159160202248479510306007504163799052150262",
-  163000i32 => "11985223.26439599449625646812893933768392494819883561602784508559449762662909836160423097372392025103",
-  164000i32 => "13245653.97443359745749252371052505726632144685188240476036217477442760308390038790298941915951269775",
-  165000i32 => "14638638.37494147628578767367990414206080324526822553855373235899562629654291122708129950548140076153",
-  166000i32 => "16178116.52681894492728085260257320686416279101203819003590581219620720302907352428914138197638903523",
-  167000i32 => "17879494.50294270665232374856418494559694988603556709361896090052269012689185577545668810560758413743",
-  168000i32 => "19759798.56189201724525755946393137200660695458318703418076320967959287440831132167395706494147030659",
-  169000i32 => "21837845.53541418408393866603384235451572267049343373172917738247839628654509107111007129017762605749",
-  170000i32 => "24134431.13475476909972865036379971927364378895284528609419340507741338616253295396985944932672915932",
-  171000i32 => "26672538.06029693720574670077314671265236627241843008867875930204275603193343000131067781287329942556",
-  172000i32 => "29477565.99713273131974283054991597799662091803584596157124471876632952107990950557854038323438144918",
-  173000i32 => "32577585.79820889752954018123415825189556447150334802473838325020277075946707404263559776835007992894",
-  174000i32 => "36003620.39874303408674917968907117021760316578741491553432624821784258920916843221095114346301639951",
-  175000i32 => "39789955.27311460261729699724551051235735647101485413908325756864840740454202154547638354294996240016",
-  176000i32 => "43974481.54107676890493647543941932971836234023640694171462730401125168591061601727011875126296263335",
-  177000i32 => "48599075.15686771651867996363780814763729635890404519036723715472902537541786589158290278643784528138",
-  178000i32 => "53710015.97589372260876755998067405018078741185022862775336665174874439073254668254533089217759702319",
-  179000i32 => "59358450.89272448630320961971099730802062796359603253852843294439631412297383070251042170021358557804",
-  180000i32 => "65600905.68517756528930250419841986339683418258471348365097771137474360059047279896203828792694684810",
-  181000i32 => "72499850.68668690381042619477484826058246789513005469083136628900490605260931612896728576664976730014",
-  182000i32 => "80124325.94782808171015672376075859963258630594691709849412891255843183721610173888602176494863473584",
-  183000i32 => "88550632.14320080672787340136087831400663879446249499196131561188465464050382309153329577612186982106",
-  184000i32 => "97863094.13780495637219282408889069170537336065743019851588900999890773795722467618714712296762183232",
-  185000i32 => "108154904.8541743348656355267263610818558445045844249089969042353024128529025833114620273423526737211",
-  186000i32 => "119529057.8851288566030584335893111623591647336720851613770414738401151367468079885120050144420950641",
-  187000i32 => "132099378.1851129597648552544569175810640883806757881559420873741458573472947409732218301424349773394",
-  188000i32 => "145991661.1545932769491633298520156870892015409824045114099573967836828390172140497173129335996522849",
-  189000i32 => "161344931.5167141950529469880967150919529381599104029342765445347104769394632159809221294106885494779",
-  190000i32 => "178312834.5842111361525149330868888264326665791499532629573741144527100375815900083911688290405324915",
-  191000i32 => "197065173.8394549894555360277658420510309397156649334699607951873899092503099384022164976559609888860",
-  192000i32 => "217789610.2147055748352635680259182924483096563445516976434248895669825333835852482753253951719890537",
-  193000i32 => "240693540.0778401047781046215825611403603376962506602767570509207112823794932166706420112242197607573",
-  194000i32 => "266006170.7171880832998589384911167323414737421284034077146448416377948089528653457175251403395285099",
-  195000i32 => "293980814.0955437090620795390255017978609363011513679429792413438061146015993367063988678236098364197",
-  196000i32 => "324897421.8277195281024470918213801439603814640573347839130905672591055363901044799579262498278584384",
-  197000i32 => "359065386.7500097568369131170639250957212659385103832623285421907851847737099667260942677009231146638",
-  198000i32 => "396826639.1178061206532900625489562656042224730816951439737940915296779914588413005465156046111189015",
-  199000i32 => "438559068.4160515393635839997229552032677667355981488641973334445894982420394339371385991077286332190",
-  200000i32 => "484680305.0257335883327160063504900717330342351363928277603867011742047195419412013295159663862729956",
-  201000i32 => "535651899.5908194389371612092348783869250829443795688721102600391990361703998097531705264215412320180",
-  202000i32 => "591983941.9099553450062061822275436530068834376620404945403276102445360520697425469365203420958096804",
-  203000i32 => "654240165.5757250975674980049763936273972529542052796078070248907738593829252890706389041941700828586",
-  204000i32 => "723043589.4453001928605315644828141456694019558070006417277694126362832520974554008658739580717060123",
-  205000i32 => "799082752.3985657848836978122005466978407233928381610864200186485882767533521271348250961673049071698",
-  206000i32 => "883118603.7770327399362767080465789329424043929847838358986496416057010839298974556971013087766027536",
-  207000i32 => "975992118.4584630859679143202121861288500357934530483621937625132154001303597163326744587826081006661",
-  208000i32 => "1078632712.773808150224119824267458218001530622803086229603594102612836541070129374710500361987866298",
-  209000i32 => "1192067545.487355612333275493336143038969483768574554577082587547131463147970125645842598238351060419",
-  210000i32 => "1317431796.918105317104527988234271228548042636935534458838880011828355591088065709731341031768522039",
-  211000i32 => "1455980029.068980190960001292697191052197370923153949052346963131155515865048745244813950669569864600",
-  212000i32 => "1609098740.448485647845667461492472452706800205746422427265557993607712322029661013732320042760315989",
-  213000i32 => "1778320241.225117870134254180732770185554626028201451215319773111497965093380026167816370605427822737",
-  214000i32 => "1965337987.567832866864171047030365320092002821016944038491028657559990532524601836012349504604510227",
-  215000i32 => "2172023528.628451272127564907771526230375057336257511884470134843173483826874514462619424916652672232",
-  216000i32 => "2400445235.760121184157442137843975060732629003910527390350676483511703485095439997307715007340309471",
-  217000i32 => "2652889001.401393800601791591828236801866942308959218756065632842691092143062555533558221682913325525",
-  218000i32 => "2931881114.766569316782630531276529186262090628640188415547950138783956584250426334431905830978838039",
-  219000i32 => "3240213543.266999120325443821929935111827101785580588624281686582014610533511262350559160390879405215",
-  220000i32 => "3580971872.662984798817250587168384179720082657469025867896246207844381703081994215420607090671075833",
-  221000i32 => "3957566185.552721000562633781878181288980009068191213417955759288980824153256655259984964723293436925",
-  222000i32 => "4373765187.209650965870950735918064467717002973075395850171712167146116211854085207681780861030325296",
-  223000i32 => "4833733920.276905466855645015949968936162197406001209907422676774263425987780742534885647358294882620",
-  224000i32 => "5342075445.742389329294469311581657605315536266800648275732209796743132547741183671917405610712427485",
-  225000i32 => "5903876907.309977014122080274041332549377392428149819693891268185895231744170247429453328697316253433",
-  226000i32 => "6524760440.148390003293853019832891736533228720054131113660145353224389316762374545477995216728418959",
-  227000i32 => "7210939433.478636815315897373959478782250126890296547671671364792251911369722662885803819636594126337",
-  228000i32 => "7969280710.038550023717149511412709088454964558336125614520061798546866755588350004105018812226367926",
-  229000i32 => "8807373244.675123169853697973235732785477662729883587190901518706150890808634080991318175489090376098",
-  230000i32 => "9733604109.754589928646052391927857206516585174325964603387459297975687196463303691680916480075422374",
-  231000i32 => "10757242407.40137066328677011838749908514047467563288099862484790080675806857937477852165203728865306",
-  232000i32 => "11888532028.50387986271184498630340176908804712917986238912681857102306774030043458773557653738340347",
-  233000i32 => "13138794166.75759680377555670315729826951010236335678403632196565492358813055290023920091932567511425",
-  234000i32 => "14520540613.63772308431934436193517636082481065095420758084378714465709017081073851239014879513460494",
-  235000i32 => "16047598968.08211965821956095529349464698108949718926779655114472230796212403131425091355657618265423",
-  236000i32 => "17735251013.89970767001210142785304930077737347155766653222352254026297884739553549252628237274442699",
-  237000i32 => "19600385649.69335118116076365246687217594115088863998203316031183124561046271021222541019566292078276",
-  238000i32 => "21661667901.71812064033351956609373878319666058208436797888916553465978050040800513024308153186096436",
-  239000i32 => "23939725711.04315062605202925411036403907030802623750320394374386948312236879221925671951833633254648",
-  240000i32 => "26457356364.25870760241858358687114236746014633849566551230109487621243063988684591914605679276944749",
-  241000i32 => "29239754633.54961181829684870425461944719003638608711122280210029174284118202645845555354565057895487",
-  242000i32 => "32314764909.20904915085317683869096745266993639448211230989561932894033020387814148242552341240145158",
-  243000i32 => "35713159847.76718601161455042547319099804785113493648232719185960105225205776044615094767222089602898",
-  244000i32 => "39468948324.25962819520135837968806674283111797121104506955869382009620141562432095076958583067580683",
-  245000i32 => "43619715770.41709525900054715819500313327001435738986085313079930472051829962829995091816926081991138",
-  246000i32 => "48207000304.65442988245358670238462715127738929752999205984473980839104251525641584267825593691505879",
-  247000i32 => "53276708417.91757124521340719684734153695880837787003741301252218708558992709565659834966914233827001",
-  248000i32 => "58879574375.29582496792344864116855947093795682060560973451363025308441705231620237449541846616060289",
-  249000i32 => "65071667930.78504997940224204102454154214032772586336376839478915912583351215082130056138409497469700",
-  250000i32 => "71914955436.07324638803073489188697721688441939314101891945346129358545938899940702090341199625508326",
-  251000i32 => "79477919958.55187282475068566692769865306368589796356254047150391345243697351547165103000539177878534",
-  252000i32 => "87836246614.28128431033645709652765477144370059785858272908195971707818610932226917215267566403331781",
-  253000i32 => "97073579974.26654433368334025720622942653857865481356516297356784125629026622249572394652220806939323",
-  254000i32 => "107282361123.6615928596580697152877048230224502987955516465505556965367695562147603251202751326175602",
-  255000i32 => "118564752750.6332528150102771624990076837852371223502224052692754014548960559211838451230272502003035",
-  256000i32 => "131033661522.5588126949159870409326109778728423047540354691846998301691090245392364958378093938540942",
-  257000i32 => "144813867980.8178161445419025697345888748004357232060883830774450971094654192680585983088957937960210",
-  258000i32 => "160043275261.4132304398192647506549383621735048563053421316487152769476186257598622259535567970777495",
-  259000i32 => "176874289137.7869919041047456202314420900792267868507367664816932923328559018315788444802699656826200",
-  260000i32 => "195475343196.3800574292282160716629111200637487230282569654751911092919302400317895773603123552053041",
-  261000i32 => "216032584407.8790176700585531180282345534948920182904765305222673786639891024301993043760586818751673",
-  262000i32 => "238751735962.2246210360378175232610428239785993711493005665244707579136040301331495627219195124183939",
-  263000i32 => "263860156009.3953241546132332740967908234902559219027979795865430717240235667551986109308876235179185",
-  264000i32 => "291609112908.4736127060635523549054604774534018807433713779122648391176322843007264057649532910702316",
-  265000i32 => "322276299754.1736520275488713540321888850186133317377239505952784397493177150353812878820361300421970",
-  266000i32 => "356168613344.5384293363939404896697461269440550535968393933772780458663453789548761316817165954578414",
-  267000i32 => "393625225399.8657976167423038032824227681385140409391250607207587757005423688073917221225041630158204",
-  268000i32 => "435020976767.5786473902361981305649960164354447978195260924143912622706573676114472804430096859492387",
-  269000i32 => "480770128579.9828259054261572408010098257375257926448231649871632609083513262998008098869962079024185",
-  270000i32 => "531330507904.0033845608512822788127155176103129655487916784031318460259923367974741967577251019905550",
-  271000i32 => "587208089369.8029342764489482868203616451709277407968500186826500454026613983274859632036117863722604",
-  272000i32 => "648962058628.1724124141833214346658482021621771877137765572363187457731661503698271056505039336140860",
-  273000i32 => "717210408308.4062794421216002260264570076010418794877438528558421300190417900903301102012121321878306",
-  274000i32 => "792636122477.2846518075276242629326522911511453131709200845554877790789306552270422543365419209216703",
-  275000i32 => "875994011489.1123294024232219261307570422712665552083496115353263779163139509352976412806737545706152",
-  276000i32 => "968118265625.4455615728883584008697904808750822380495535806545994835431509061828445843401553565010324",
-  277000i32 => "1069930803116.306261851087967083574090665630474354981062897428212371054548098717109840279897615308013",
-  278000i32 => "1182450496084.324675694918226698586003125933142599832729747339454938065942990600872183762935451280922",
-  279000i32 => "1306803366738.919947375588261828976356948732131422843058742519711040327476587749896848487293150710538",
-  280000i32 => "1444233855857.244744978433720949440301542468140562337581703609404618428288725026926724049872157460417",
-  281000i32 => "1596117276319.352468938387940470372493002419809071304068527462558193021711400361937012882281196517988",
-  282000i32 => "1763973576324.279618226240770347028250599016854723788391569063687909068132950118372482641452364823797",
-  283000i32 => "1949482550020.150941701122919333606500687751989679015055187840231346321488157778817392701503082390263",
-  284000i32 => "2154500647766.171340255618699777427408000546950237626135335791425435179490785884627482774595706067850",
-  285000i32 => "2381079554252.419910550477884386744662466236494862093375020082843031425965997009939742831487306355673",
-  286000i32 => "2631486720394.905959247266691680804171486392257048437340047799894260115020451565083723619225188700376",
-  287000i32 => "2908228054475.429471350187183393188938134432299136414741732261470287242737615216731540803633605838142",
-  288000i32 => "3214072999604.073619563869173371993913521517347940085847843437081460674421935353010031615077817266558",
-  289000i32 => "3552082248462.885791240177286807387204327856314294647190439424069990011996165130881549961733609695266",
-  290000i32 => "3925638372681.458728486425573228707914593565832050171535993373735437388163103150468095700224646655606",
-  291000i32 => "4338479673362.819850741726025480644220846999490231240347283460781555544520901528710890529678047104966",
-  292000i32 => "4794737591513.165553854728455370232377635669852729433664488749321513984090981701883478931119520159289",
-  293000i32 => "5298978052754.125972374149657432011150901096295258981756696899409753910135185965155460937691246996959",
-  294000i32 => "5856247160067.927133255668981970848557898648285406073027420438135358584561102569375175184302360519958",
-  295000i32 => "6472121691838.037206924882308507096546781939932414883592812160350156220358354094782393656224815446536",
-  296000i32 => "7152764910536.083015335886373002227416917401335729754641689074569018787646741188173411445408981483854",
-  297000i32 => "7904988240551.236785495853352959271149350237674894383864934946440768158630052038394837509959012980515",
-  298000i32 => "8736319432392.745276127005611625863804803301446727449785184076848045069069113101091437513720300572046",
-  299000i32 => "9655077895407.579366201161538930053964591383538714498375737203846665631001684501734501890124231873694",
-  300000i32 => "10670457952892.91113092961380584427128471271280336256239335701874499888095300344558944310256068409861",
-  301000i32 => "11792620852765.18079574655553486773121838364453233326585325760931396233020902116783110599613922026477",
-  302000i32 => "13032796454567.29980262649557786830949484246725547153930602238011385106328907649016702627067868193542",
-  303000i32 => "14403395610429.88903462217615578332455069836857952314101356115414490916289887009882915615725923882708",
-  304000i32 => "15918134364620.42678696177080454644487346336599458083338593374780226862615803972366367776384518510517",
-  305000i32 => "17592171214586.74402902155235853096911838550664874798497856293991394002474674062711359858601489906638",
-  306000i32 => "19442258807112.04797492355290488369082240183823161632455128377248765055618515191225676930418385307883",
-  307000i32 => "21486911587655.64510878512215485490421799781716458807343594000180773523337526351090699148387362296620",
-  308000i32 => "23746591080602.40102004627640825700820870181954759065433995740889685447741935764301117852240812998956",
-  309000i32 => "26243910654582.37630727213061074480982047746322756737957592793400215771618976987275171928801181980043",
-  310000i32 => "29003861822015.69351821205268366454830257079555467796542197928964900766995029598176189695820992522070",
-  311000i32 => "32054064337537.88595448281363253223104647120325588366744087775604772885059378088931780229818797554616",
-  312000i32 => "35425042598124.33773358067125477992397044697740759763685816380391865837947134192555732046632696102849",
-  313000i32 => "39150531110942.32617238804244054024515604319809215616270921737996566013311718083166497793067368950372",
-  314000i32 => "43267812085849.65187413857669308336046108837478800833679643525446584052016918254691682051796633897891",
-  315000i32 => "47818088530940.92715477442237690259161800470364861461114332958774019219061718407453713039912862282189",
-  316000i32 => "52846896584833.46840923913387026996952147527879042540112005601318830369604484452552639499323831206867",
-  317000i32 => "58404561212039.91733685855794819723784206830393027185318472353120394794078426887119151663569867524413",
-  318000i32 => "64546699821723.63135430516760376116389394249628546786266352781152169280046346769277586178745517952487",
-  319000i32 => "71334778849718.20699275502682115587075148900018662607202439974325672902382239738390367596690656501829",
-  320000i32 => "78836728873713.60237323281172238442449424588064934482559750001646014928171939771103450957649064713166",
-  321000i32 => "87127624417272.30446262933925683878320821728201060001141794495168380448960391639373996587946488364683",
-  322000i32 => "96290435245701.74981354601093805225716847773427941914157987754468425462222239031547463684978003892808",
-  323000i32 => "106416856672252.1296194694733052927999072192024991927513852842024192192057260800845863138955781561805",
-  324000i32 => "117608227183791.4678359605403664430805666704530783908674962802196137245806090551265341690153215915941",
-  325000i32 => "129976542568945.0816988996067955995964021343755121722156657728395887610586013182448412122356187992322",
-  326000i32 => "143645576697418.9785940547770999420172592429039479600249246429480206266937384615825853165529439982719",
-  327000i32 => "158752120166520.8195439439636835040378804098760480305271918042356735574534422134241705323872206674854",
-  328000i32 => "175447349210428.5564480922922783548817184908152464740005944271279396653712086035429500021827855624071",
-  329000i32 => "193898338571339.7437627973727292415141572594873036844773378359905006318726333584598265774257585549796",
-  330000i32 => "214289733472309.2256475712390903696866311609463674088829096721004544614767084968043794908564613544867",
-  331000i32 => "236825597423766.7204213958080998193855401196604290455533276936937671094789313343185335741050556204227",
-  332000i32 => "261731454355332.2816005231302600384571451604008517061496118697775859078555225118554609706898122284274",
-  333000i32 => "289256545509225.8794486597916969237315277641643395785558390851476529173533007734126035097738896425904",
-  334000i32 => "319676323680758.4648193312717870857067778391981627142044162989248324046371909825503889500621103305096",
-  335000i32 => "353295209766603.5226071445647850983433042334000303900383335762817343661440457078401424902033337370624",
-  336000i32 => "390449639206549.8328802507099053629969497613828611709561656919052801247099459791301015680129347639646",
-  337000i32 => "431511428805497.2040176606105177729344366456329850314136915857436745156824856385465382213099162243696",
-  338000i32 => "476891497628609.2123213876749773889528609443933330164734750919093633353989436558574469055073665850938",
-  339000i32 => "527043979205865.5149553945012804293663633852309266527854480584516019974229834911307689221603349219994",
-  340000i32 => "582470766198220.3716755142569776819401152502554764566385111643616020313499296728154041806760402347810",
-  341000i32 => "643726533005361.9781224646203855521298983633528685997385848400725980682970827719905539715673772744636",
-  342000i32 => "711424286577988.7014435060258865308968749025101462910250340427130595428781362627843385619465257171642",
-  343000i32 => "786241500983437.6130769972298274073084920691197223555296156864568674349503034231681074685535077207725",
-  344000i32 => "868926897115315.7964841940108087127619193053922079753061504041663339115913116439166070456395729039126",
-  345000i32 => "960307935393956.7672766625101732909610551034664158337268970326208532552025675451031431536261393610274",
-  346000i32 => "1061299096439662.051187214625466367949766242866279315340815977064112411383237905939287773898018561139",
-  347000i32 => "1172911032586195.231671709870854677911349222412547181276529314567316004394730711270372777620961634674",
-  348000i32 => "1296260681816785.512808736234380491350928053669335985923329332852519023370786664118176674938845848811",
-  349000i32 => "1432582445336181.860861927773101276346597005876995217971032751286132400201432348709000674482750014969",
-  350000i32 => "1583240540636460.570561845646872026295513298348194502838596460897357843703256704425622241181346191421",
-  351000i32 => "1749742653677848.337665665437245620765822722406740104030348634365363080404915756395287949885546523093",
-  352000i32 => "1933755026806501.480267109539441455502520526185877299506943284738782227623190007323301009356951129233",
-  353000i32 => "2137119133399081.977210231045610368079500883798589632254368858286049866344509201089608114538136195944",
-  354000i32 => "2361870106102876.864947613393447160735953012498634154734902995303601393890065384776930509709785873492",
-  355000i32 => "2610257103089024.777412958762805356158153384370416165504829690337774722960935107396932875742381965301",
-  356000i32 => "2884765816130758.911814047383348970146036125088884292550783038028192891914062664339984043528742379839",
-  357000i32 => "3188143345752535.160203304195995766240476627772648213039244246287573431457013124437295395629842905825",
-  358000i32 => "3523425692383984.364966514547597996222001242076918994925653510188721286459649902684839726610747188659",
-  359000i32 => "3893968138631863.079172243687460405764088421139152311434630742436644189024274595879150910115385709673",
-  360000i32 => "4303478826715562.206817958164141681051464529660212577938187057482832328308837212489453378799966154738",
-  361000i32 => "4756055867086803.518954005840823410085838705302636952062219820804532503311074032081442856750191362924",
-  362000i32 => "5256228349591895.517979558979244082586800955939353391264842123913610287305960139534491222102912884885",
-  363000i32 => "5809001667588969.054785563541922848061175010162262793115309120185654481460824770461744759862029574164",
-  364000i32 => "6419907608593796.424520378749440019454148877027098001357738470833823004300857680669564323053139075042",
-  365000i32 => "7095059712728043.777038079617786198082408717612552582667572211690311005606418079542458871597820268205",
-  366000i32 => "7841214452960467.897832554460504621572242866166741845762363462535540175315204554617070508049756095871",
-  367000i32 => "8665838849392198.008909589820012868011581973561547141934577736909451773207461003995766985797125198966",
-  368000i32 => "9577185194224874.817225816660615115868874089020028999643710941744065413079869200874116043326962260182",
-  369000i32 => "10584373635209401.17021132703098997468994216494304315770536649021717441652640626129311670464452790392",
-  370000i32 => "11697483444015503.10292712092730888996069222827080759012148646359862328843277475479582298668164901033",
-  371000i32 => "12927653882875208.02967953956584048522733608472184744505627867722120863725219631997264541655003392077",
-  372000i32 => "14287195678906484.88995668855709255624452711210415007837835659471988031469646896856970919864188997392",
-  373000i32 => "15789714221677895.09013612470060700952332936614937302396858337373127818838611831079777150987890520606",
-  374000i32 => "17450245716893497.72361961862048600081510076961874534894071211594574358670592758795238605278611524575",
-  375000i32 => "19285407658733482.22528082057241075165185527007140759666389368344263247613511183218393611770150564248",
-  376000i32 => "21313565126677579.58638181544865402285511200354034908724025104837112340310918843755293023639685682014",
-  377000i32 => "23555014570999196.28036490235927461046476527458333081415439695086109295685480941010238769365314985899",
-  378000i32 => "26032186926133193.56207010565145958134255175063016238322731951000976490090563028284698846142829058065",
-  379000i32 => "28769872084540732.24174907181267759978945479636886767667388596278132166907623225097059853156507078997",
-  380000i32 => "31795466977456243.02473223949669937042285719661699507009101263211070936964256513241608559933959976988",
-  381000i32 => "35139249745143549.29009760891259730094469606391485903998583108899420733912906640321101513606632120777",
-  382000i32 => "38834682740374615.15022144556061103811624426648067374398781363249276546251242023824484156500669198163",
-  383000i32 => "42918747397388096.19528663142107774840975488957136716410571646248544884572792750390850895090597933730",
-  384000i32 => "47432314317473386.86249421732048739393461750621484409475395519569442864815388278079191989689220944125",
-  385000i32 => "52420552274750410.94352975153647275818177969966783004973069821284462380140096036939120225595884034374",
-  386000i32 => "57933380235202820.75208491363000039221871432104418753820080643955959435016957584164926455115686205183",
-  387000i32 => "64025966912470286.81550623520221461771562486016589791418932193793427761897096012408017386865661237417",
-  388000i32 => "70759282859621810.93083162236390048560977435118571911425513899825250431040918166807411293695832032167",
-  389000i32 => "78200710621877132.70331321680792588208595101810878398972506950438645892081405483353216180790551476929",
-  390000i32 => "86424719056278630.73442723917226287041411026910247961174097780316284955104173474158112775514921861889",
-  391000i32 => "95513608566456259.38138063634985843855667566821975473140716285612495973443431662287227417849759984056",
-  392000i32 => "105558334710299121.6687637850455709038518996004544408214953529928192384799221293848779581559707006982",
-  393000i32 => "116659418422651174.1997909239058424768396504357392571853547847779055328350375467033652429951788312862",
-  394000i32 => "128927951961934082.2473393302046084444855379656417878745317441266264472670793106872235069902850236511",
-  395000i32 => "142486710647541605.2966914117636701973438431656439881474324557953663678587216074652260074383725180136",
-  396000i32 => "157471381513533534.8388254416901817111091174176288191130376964380571506522223812043541001250493562554",
-  397000i32 => "174031921174177728.5438430964136226451523257138827039258996594309707036268950819712702557428685609831",
-  398000i32 => "192334056489954974.8596163075232308707149401647604194250190833090774443024062310067282347951429421161",
-  399000i32 => "212560943051693425.5206595035717701100889388445049320377732702459048453104902087471192954649215916212",
-  400000i32 => "234914998079838151.8843526884411526271016355544126532324534040884927147689505200445792558054923566172",
-  401000i32 => "259619926081291986.3376996475760408883626448884033592153408579378971558635443297228463420814271890711",
-  402000i32 => "286922957535253310.2146774962176198527703285489962825000702710399711137608865720371000845221438035149",
-  403000i32 => "317097323011328887.9768233586853504045577277176595691355084794829920355512484843896706234604415315730",
-  404000i32 => "350444987479249384.3695959403326146232931359383563244066030275932717232353304983626487565731012398493",
-  405000i32 => "387299672173339612.7739933709658957013754177646019565791148032805048236879244873842342255690022321670",
-  406000i32 => "428030194252552190.9858613747949582730333899799511166644382148744428010079165723177344965646935251983",
-  407000i32 => "473044157677159797.7305554169848711884545069884272064458010976593794262665659111527509869511701871266",
-  408000i32 => "522792032237943820.4548340994245043439225760082256673095539029987739070308982709061830036577216595581",
-  409000i32 => "577771661558089067.3095165493310436282530624117658333850953762727718815134033293648166651176687054500",
-  410000i32 => "638533245180867592.6346651802991421418886685558348204557105637277764724310521959183669117560062340844",
-  411000i32 => "705684844600529775.6821686897570963620276106563975749020470741930784491981584640448287758942163879556",
-  412000i32 => "779898468337095745.1593538697959447240859409037059770362818952551561797640844110272865861059884030867",
-  413000i32 => "861916796950426263.9690441144592179323453714255063171180612295443186364047428324659333513513920089357",
-  414000i32 => "952560615293038640.8074699560480666950703062225815106188853100201632016929374422334938863478146274339",
-  415000i32 => "1052737026378707953.587123922665564490016260051887380151680847703148538936251096649069127906769836532",
-  416000i32 => "1163448529065784511.886670601078417735299039985028082453976813971140075524567696238101905413224579256",
-  417000i32 => "1285803050398641328.155239056801597382197406747942814533130042313151020346270352909689072992541096262",
-  418000i32 => "1421025033004248641.909714371172501692616332071255509594233281598698126442594894878407116400361063672",
-  419000i32 => "1570467688499162151.506591850070750851991453754352748420208707226099877715073069089650664469090603650",
-  420000i32 => "1735626539530867890.503253027839091519311670380349578410870130207896031303333892571899934682900390359",
-  421000i32 => "1918154385973221787.151093403636290855927986041859466356415116879549641157177402464130813366093754106",
-  422000i32 => "2119877845047708401.873494706707465676856335445308914039207283819012029308272770736952137621182260952",
-  423000i32 => "2342815630893045544.946052794216801782462358269529093691266116989244969710359974838276223028172391982",
-  424000i32 => "2589198756512902981.914233829816619933214195243587512936217870517807965231573057028732971196105362925",
-  425000i32 => "2861492860269384326.647422657812782434950351169944827605405460987247532330625276265383492114023943368",
-  426000i32 => "3162422880350961411.191785249525697796374571266059506725102065350495606170560814534522777083984847453",
-  427000i32 => "3495000324140516249.504825427508628033512732912342634035494063966722493097557740948538226054108728103",
-  428000i32 => "3862553405377179176.352972020679864413574192814106780739989204775687100544859792034360715529059865685",
-  429000i32 => "4268760350704623641.248304004954510516221068912678899059520244450584095528253680347894388826364679440",
-  430000i32 => "4717686208915588682.920154195492564682806975092816544192952393764707001200487164991497995458024025340",
-  431000i32 => "5213823531255053038.207185799863963181884490635426868528923554558586083483100294101382485304409280442",
-  432000i32 => "5762137329883463774.644363153756478280472644890549640656764147802926376556626917790522877536780047574",
-  433000i32 => "6368114764414401200.196592389874253297614827649803019346796154124118416323594429213380020920261923095",
-  434000i32 => "7037820053756484398.533277172872372613858577988276511693713162692810382275940522571444020815478266902",
-  435000i32 => "7777955162780689326.565496018403593791342036677357916911669276603640172262251119468603619994534668385",
-  436000i32 => "8595926871124861201.481383913211473424038465255353228982585740612844517224960567396516637841001528930",
-  437000i32 => "9499920895315384862.428792243041289564336477560154395980524064831653878728028990576067162701833505347",
-  438000i32 => "10498983805970881180.36646780886067312114216179441764960843433862850010768201390695219066783170677999",
-  439000i32 => "11603113559860791159.84195990488832208519596081989317571105396928046487373410884344315182245828488384",
-  440000i32 => "12823359552803444216.69585528551368598610114133680648279122835690891289596347952347577451719471337533",
-  441000i32 => "14171933194666432897.41311228695182986357986927738358662031256664028956915510396421446503153105718872",
-  442000i32 => "15662330113030314108.43759136386247113055753131149699473821515556652816469376599449189596138814879398",
-  443000i32 => "17309465208448581038.68399724610425214221214055390646850358730292444878825528268090422300802863863747",
-  444000i32 => "19129821912847073160.93853245419154425690854588079970950359071420286591264880413405358665495333200131",
-  445000i32 => "21141617144741559516.17243293862937693849707724624908061273896654607213486523340101226568876362898997",
-  446000i32 => "23364983612035541095.62381075172715445982359200147517742485013331731886654031795952628998787405468118",
-  447000i32 => "25822171286763356778.13810024295120382447599828681192654361553081895453795768600503956489181517636753",
-  448000i32 => "28537770068003747638.85914928366314640186636514786469468240052857589354544851143512431593679124634838",
-  449000i32 => "31538955861226144428.11094286335300292459588086795231018912629999354049484061150444148970260056331538",
-  450000i32 => "34855762536668018919.84841096670974995719942031464478463583304530370904752327568519742886243165492697",
-  451000i32 => "38521382488321761152.77530097155946228132140439981526481654998247416217950416159203985329217976969148",
-  452000i32 => "42572498801325412046.38842335103792453674530032199265060095734971416965016151829813676488843316284632",
-  453000i32 => "47049652351867452403.98642283453110874568562151971947560716833851793027483203766659082305930739695158",
-  454000i32 => "51997647513297203660.52297865230685024108596980265313465851825852159408028589899035863327772896760655",
-  455000i32 => "57466000528477610455.57634360941984683861732398435095055190151466960708245740172973483603157177799385",
-  456000i32 => "63509435035392536020.75973029930304756476476633438749431135112971543567224136056772291817339257270678",
-  457000i32 => "70188429704899093741.85714282524744545214285967357994255981338002238002650905573075855720512149929229",
-  458000i32 => "77569823471019207021.60364514234014720294748740750996173291812791856377799920066088497353716876840168",
-  459000i32 => "85727484410512396404.42761164858711046816574430577332014363538444920011789480917493044540318301818516",
-  460000i32 => "94743048965431443141.72787069781059311406571285337034862825661270263583497080136955716611573381822351",
-  461000i32 => "104706738906308340281.0294502134352901599904947578601916731749494602564978565020566255447286362310773",
-  462000i32 => "115718264211594436689.2294214485040930288674305638846258622618113066023002543426331086003077556933587",
-  463000i32 => "127887820898771377208.9838098037099221552947692149683649466672254512816346275611453233227936254609991",
-  464000i32 => "141337193792762576556.4819190253249342657448405260590671303646184546928057371275362896862378937834075",
-  465000i32 => "156200975267417636314.9411448076324743456351271128828012650924124037030267084950800975693852100398064",
-  466000i32 => "172627912156423452487.8270152867815479348383237694786420628641003315085956072660657857436149956880716",
-  467000i32 => "190782394312630091255.7636275987930476220223730639774835390203663550548972536979141944287333668633349",
-  468000i32 => "210846099712302583660.8200172702983768401530170457490517424488776943003934530907341910241073320443382",
-  469000i32 => "233019812567407233526.1480712891188498184494489034776307402068053895092710559419293129773497206312493",
-  470000i32 => "257525432640390311709.0002218454280551731804294143794931442159681369954315404142137644303160755863811",
-  471000i32 => "284608195869334315212.4874493162690303656188903356786455541469825763428025549964908771658355290912989",
-  472000i32 => "314539128526030608901.7814570326576824484165357199960264141059793681346176948895346986901712420465220",
-  473000i32 => "347617759466549286979.2704139417922802036254288764610679026317979337803942236093818396713675786186883",
-  474000i32 => "384175117616705052283.3619979718020357880940776175695647072828398565933555565251211937733544066951833",
-  475000i32 => "424577044689258937267.6122672024047588364665680786951122685726998011986637954867576527214494221710372",
-  476000i32 => "469227856284325127449.0320306924894028827225526935892291230767867510686906321220709792048193767639481",
-  477000i32 => "518574388010839428299.2824257764225627001029545921975973100598635652046718040283592496159186598397485",
-  478000i32 => "573110467119980483824.8888842932287509011755807908460614197384789770124340525242753422424180016314124",
-  479000i32 => "633381854399675315336.0507350551814835050739684591159644454912446650183763053362698822938243403444533",
-  480000i32 => "699991705785380738958.5959181314915026435106545593095340251268579961051631884062477945915182108528288",
-  481000i32 => "773606608343306856967.0990319019418486591808587190266596543592053331259504423892122275214534110420798",
-  482000i32 => "854963251030185994110.0957452898696798213743011148125625382029372550346799079010853447408308896018022",
-  483000i32 => "944875796986111695973.7694799093256314856082767538493824592325084593780715260198225222652600101383854",
-  484000i32 => "1044244031137448526609.536674183677291356183136354740432165002024426562572120864257032101691683440774",
-  485000i32 => "1154062364645600650270.257040975095204286401775642459429588849241392918473765082469429714678094393030",
-  486000i32 => "1275429786312170336739.934250168348902553220677677610078567591230260319510055229473721055024169428910",
-  487000i32 => "1409560860527546914711.523767834405648429385121581984842849026831335496265028759900137286327227103985",
-  488000i32 => "1557797881823076912615.779004124768869674902405389785063443723318345048472248315388505687205549150520",
-  489000i32 => "1721624307661485088784.347611803746199553357805659831562849447435227337453912822584800042392900572499",
-  490000i32 => "1902679603891973846193.410771084975259330173031774466085656575659493683002850833401594864119604387154",
-  491000i32 => "2102775651433436550821.729257367661546070756359623132958330118029191900428350584030474127920159944121",
-  492000i32 => "2323914878372952295501.201755326540372521553941935738423736850217704959247990491004970032678899864009",
-  493000i32 => "2568310298933537586092.396188604217721887601983361747667417336060957922819592207994720342845000399585",
-  494000i32 => "2838407659847808997255.958541321092756091986018904798732161526980623813975401843301727784266968367056",
-  495000i32 => "3136909915763726799310.701563510728251639010871494071339081027314718398595358182900405187854042926194",
-  496000i32 => "3466804278615992737026.748879560000677978856684545504637791803835452775506954712067714188627410910981",
-  497000i32 => "3831392111655210526478.656317319761496248300338547192222739881356382353364453078722844652129149687635",
-  498000i32 => "4234321967294359508036.848371452228269074171280089357132133042829729506676300674968905220773282076387",
-  499000i32 => "4679626099393363466402.810363892914238388780509492673139841444144428673404324801818188692237685882918",
-  500000i32 => "5171760815372400971558.161892130124037985614182645270536158254128591842688269894312417504958388727574",
-  501000i32 => "5715651071971054371851.444777219925652972451584919125250481367777603183119211128372347629694418398615",
-  502000i32 => "6316739760937978130149.365277098093263657131032439107946480264358211485683786061785930809814311398200",
-  503000i32 => "6981042177869470851648.883431639885857324353539889510781520762398340713218472896714912711133792338883",
-  504000i32 => "7715206219284840381098.803030554508431455761353974982743429818206915567146224685743572139552820165101",
-  505000i32 => "8526578910350833236857.926659361911900580259624341911828722743425756501431820935311869347072356228055",
-  506000i32 => "9423279929020322666060.923590780543979931774865727669987496286801397208174272241578409190913295468485",
-  507000i32 => "10414282862365907643635.92685483187804881488975200698995985775848479294282643579708680021153046746379",
-  508000i32 => "11509505008267736309301.90829210516122766079670761640911209806284624366258969605938427938859815609742",
-  509000i32 => "12719906621131085789259.03231037965708479963800469366627222773388992347542876192243223098941032767445",
-  510000i32 => "14057600594818795489380.27101008905127660585917074414753031272544577704548257291951354064082105366416",
-  511000i32 => "15535973680432351609526.53939390479974249246749376425796698317069091177477955312862777807540587501870",
-  512000i32 => "17169820452008509903523.94846392420348018144721371959998580003064470519893698885214662464326044113613",
-  513000i32 => "18975491360770999137258.69945964918736518394837887992434599390670648956540305707959097106090293341080",
-  514000i32 => "20971056359565731518775.87617586940837598615581888279874406208808163099006632624032614867415085924466",
-  515000i32 => "23176485734923981515471.45176290378826330971211612050188413945318260108623722329338232531537858793789",
-  516000i32 => "25613849956400484124173.60812655579888363945742208309573961988297062899601712433523292554126288593856",
-  517000i32 => "28307540543145635741986.94183961584603959823596734039895525578084333355482493073703792928591440836749",
-  518000i32 => "31284514157997473471959.82126419042738332450369457689741888247432055306131988798080988692531008467289",
-  519000i32 => "34574562371824669562025.62417758493254572543388826723131659478078980318933605504709902489626987698637",
-  520000i32 => "38210609797742567207241.29447967981252370576954014976968575935908265766142816761653786289649420076795",
-  521000i32 => "42229043578731094214161.63005872524548149599133803129817331133473183527897948325253880293353389794531",
-  522000i32 => "46670077525947372459654.11037826973039652492268949985988689253278731409994481300097373901545556603028",
-  523000i32 => "51578154551786934802129.37715591292789859623267499750147043222755438097607071103683749282543553948238",
-  524000i32 => "57002391424975821390019.27424653054528154113592803862457545987566024061702033836238756019988858021560",
-  525000i32 => "62997070298506548565451.55007592098252150626331645290984293406120575726875930910963224600517866254453",
-  526000i32 => "69622181929302439394301.90381870539883807170700183049875733694347176290839276079480856230060430011099",
-  527000i32 => "76944026025791223312719.83080607376638456979748718842275831748620326795993996935875094571714290145299",
-  528000i32 => "85035874731266911778618.52401876573626491244424862440621514208717830210480807219112718954134259272200",
-  529000i32 => "93978705882739911288766.92468907620502630546185548308197155741816931861132197653479100355097408163170",
-  530000i32 => "103862013383241988326282.4900157795949573216924481465925639288779101177691379559063285998708155792413",
-  531000i32 => "114784702797252840733444.5899633818896626657375074454700774593922374662405345834601116839355928473427",
-  532000i32 => "126856081131771318122590.6097012097492882130653510502031306187866526807203533357359189040882581921204",
-  533000i32 => "140196950708101588429747.6112941393581370831354994763854431151618429114703453640059874562504602055891",
-  534000i32 => "154940818071095154273281.9623068443138895096745341862632735557374857509413657859913459994832250766162",
-  535000i32 => "171235230033807927639359.5453055576482157236740199382011777947518474294479678672570024840007197143449",
-  536000i32 => "189243250227818201278097.7874920000750922620267809925420783911953085627221790568675144892587524959475",
-  537000i32 => "209145090935538507989669.7265623416688589772461329427629662244611269855335190313054703224940447213685",
-  538000i32 => "231139916534813219233086.9773676599234635226520384780369330521663916575056507689916071088733147566776",
-  539000i32 => "255447836603475288371682.9465536376642807980710890885977725225438385053996092198771474076610301897609",
-  540000i32 => "282312108629526203114088.2876270777215041633235269139570093996057176510365975245741426721129421218806",
-  541000i32 => "312001572370196803791005.9877394381146727891257549201175478153832217703266541579542065907528692476885",
-  542000i32 => "344813340221334469822886.5794585988373276843406576004418593178640934881676314654342540451036541208019",
-  543000i32 => "381075770520543157371035.5164297540054810104067315865579665522197251996410122798428510624471914286090",
-  544000i32 => "421151753538915487472866.8930532873390263000494280066566736687714320788113596796138725050467573404256",
-  545000i32 => "465442343045375430202535.3270827275102167685087503369191085297603893958734628045327788793705411939860",
-  546000i32 => "514390769785910851112006.5237667746512763722712041827649647159528762990587258865120430043590009267346",
-  547000i32 => "568486877041925253339663.9972232686339872704031144466897460708195830657944294562724300276897133917236",
-  548000i32 => "628272022655824995225478.6118583209740089516677920895328185985452859736332308548034386783267899119980",
-  549000i32 => "694344496580051938758983.6514352652698216605508222787258640655143892096128747742692971230351465632199",
-  550000i32 => "767365508164787063764794.7733626934149457432172958590179929504223869421308049566339374628076037329372",
-  551000i32 => "848065803101116061181477.5338729412865849676420535955732070422665439985997762633236658771213129842359",
-  552000i32 => "937252976237620769234972.8476387221548515198097778358889971335154557825665024323610669042534039611020",
-  553000i32 => "1035819553452198483431120.590646418384938354326662675009629640098177417507242622405247052418658502444",
-  554000i32 => "1144751923457104113286052.776636505830063259517573831449555001079111485218075485940013770521879353672",
-  555000i32 => "1265140208920776253002510.773940439250709646417834448439528182915446832532563999516220938487003942846",
-  556000i32 => "1398189175690065525208101.054609420056508614907004215842745900318188013757834015192027299459934484274",
-  557000i32 => "1545230289285101564375844.951162866739386756928178636011529618247880928874766429620655954237239995754",
-  558000i32 => "1707735039320176105415925.902205933365380086485456783511765430361700225626723247082103624576466717946",
-  559000i32 => "1887329665192579452991706.994066109001527123048318054197474048586212597848584524586522451613265898246",
-  560000i32 => "2085811430404284791172259.002130748719807955740487132372653071315927668973344268162397163722851343142",
-  561000i32 => "2305166608379061774812711.307972722912956417307167817819253643939914747704299848449422234254683609260",
-  562000i32 => "2547590359765108161713614.742166686238814575733986158220953221785147987328100599209709061031154695709",
-  563000i32 => "2815508700142016593646823.965820186250031047028879995576754621986832658751172802104790078909806036067",
-  564000i32 => "3111602777970269131678070.688209040833583848789868918693712499914952304629321690319940493866426694925",
-  565000i32 => "3438835705740821985751550.192804289789921057577861605255847203855659851331057188937125124588526038899",
-  566000i32 => "3800482212833070318425218.953743289057968722881054069490315709551430382206385011796222040273166752869",
-  567000i32 => "4200161416827262725086608.879011129814491437258202204190619059771545814099153348773354897352199621992",
-  568000i32 => "4641873041224851906174193.790740623256605822198838598746647572425899550631027952548648939146683174707",
-  569000i32 => "5130037442019625211933514.448904192353070426431565063524039646435607998693949652092017357267630176899",
-  570000i32 => "5669539843678902692132219.362127218345204784753473834980976944946341617874296766393213852972796072687",
-  571000i32 => "6265779227219065027893276.519272844353353161887920232170627904342937628073746997537641867785196039621",
-  572000i32 => "6924722359614737974972037.707561745173449159173546416764483962338141814901654283008629989354563492368",
-  573000i32 => "7652963505232005809717069.689617230900944323617206378858872358613819156701289372450870173441820305266",
-  574000i32 => "8457790416837941568784040.432018948420635891538557947341916047349850906176717909648408554432011067076",
-  575000i32 => "9347257266580562820179642.676131976654990541420730651061426301946153339160316472162410719104138743241",
-  576000i32 => "10330265246783927421461765.07005974498620436586490168415396932919338448409447037346822481801333666846",
-  577000i32 => "11416651647157489656063346.82978893542806766731350312507420629383367024993462984419695656666169493383",
-  578000i32 => "12617288299845150308045598.54541469435533648208233676457154420992557867752920016575018929574635267869",
-  579000i32 => "13944190377486539932462743.17554581025248182306957346221382956972265733833185398052931437275145561465",
-  580000i32 => "15410636633069131451425669.42140775933205202376462432672540580515547386642861197870041596122016811307",
-  581000i32 => "17031302284851606725969062.50717408029187976268660186868492228980596439150558687608194028316915146234",
-  582000i32 => "18822405876182360023939382.91305795836534138478791666036849221391313962043182829698978287446952004247",
-  583000i32 => "20801871579888472044581075.85480887729489322839934821126753148179033346039094705536266894458903775013",
-  584000i32 => "22989508571469471624210541.84178506821757047085872990911292493041418283411079556374783145246207135391",
-  585000i32 => "25407209266143441887397384.43286537545457064016449927878577225247153978035686190602002691133053234457",
-  586000i32 => "28079168403569908656676260.71600034827399709141496445910531237966956720990383327589809870160754793110",
-  587000i32 => "31032125172703624792306647.24420891821685062889114638853365763818559262574929612562224028470354484762",
-  588000i32 => "34295630799803661762435520.15456607955091809267106885867732216721456963508777701190605293810476658226",
-  589000i32 => "37902344277440533245636562.48448633999431694041179832562292466354013367956886081428815086712013153452",
-  590000i32 => "41888359193960457051309478.75321232478397282109434707080239095320208019842126489897082695649982519414",
-  591000i32 => "46293564934098540449148040.54044034258648960723663725211203882428203089314354483249938665660269960260",
-  592000i32 => "51162045865396259662170424.82444662342331917429151191296299163411422913151096512035014550610588742705",
-  593000i32 => "56542522505215255468585366.92024445882078325822480161562762648352078319618520154419648276612501843895",
-  594000i32 => "62488839083253338213904108.64149901671064917319875320663318692779529138825991034505127032564266671388",
-  595000i32 => "69060502378763907110076667.15272782779049329933184114466368643398142408441596321172995579033697681320",
-  596000i32 => "76323277224803098999463378.50156023602737630253649368998828345722671189062146984178276452428680422115",
-  597000i32 => "84349844638914885411085049.53759783269852342380242594300040774133165659711157661499506386341739515986",
-  598000i32 => "93220529166388052083300010.73544843251040861721543105268747694666952613791157982258341367197332976589",
-  599000i32 => "103024102714852356051954201.3852248517545126509298211201318737288891733494279589173547291387989094705",
-  600000i32 => "113858672924455575659711711.8614579791357602443179995631963978126220712106000314833443142069185595906",
-  601000i32 => "125832664963839007032958225.6444409075921877779838154056666144616940188123703469319823079033897069823",
-  602000i32 => "139065906577072179919527069.0654937723966054912491979939482375567768492489090130391783833569672948906",
-  603000i32 => "153690827239974455851697390.2911944628227617671067659730165414308197450037401815688826204524072301060",
-  604000i32 => "169853783426181979828372811.9734376305656645088975274295670669495882340126468634216275862708292933762",
-  605000i32 => "187716523245340866712985589.4733015411899580228865388638852040492725078676868190565448957487510036647",
-  606000i32 => "207457805110550995768313184.6838008124590303658425728174640844911034548926290275924423733151619281051",
-  607000i32 => "229275186633607025914644337.5383671221995385605667034808709579591295546780169445754856646605585684231",
-  608000i32 => "253387001650109758412149471.3942650896838094489085084249651835715409190655370112929770398079306196329",
-  609000i32 => "280034545159199522449587192.4738595016419641871818688619015177537984514128376186845567096858944620069",
-  610000i32 => "309484488043334436631022936.5302770887803272276295702486906787841536511531572559117988724837292241158",
-  611000i32 => "342031545733022178122801804.6796116529246435791205293474531795984822902806655663023917021498192270806",
-  612000i32 => "378001427522725994703540477.9820952115456154389959677269471068428081044605759393835403313422027220791",
-  613000i32 => "417754096052735883530302252.3436593700422864150690923945547636163635164207179153888426479365023273803",
-  614000i32 => "461687369575730435539259703.9533983541555874327477115993416205813067614723827719116735662034029858899",
-  615000i32 => "510240903057115917709970328.5142906087985056243302318312002427931160467421177395641669674477852472656",
-  616000i32 => "563900587949345507778790504.0760134132635648707372736029213574071364233659486731934658959438715506219",
-  617000i32 => "623203414670232176891795480.9789441195190020975252355268714846566928489145990046889582520501808758271",
-  618000i32 => "688742846445702371266451654.5884824057349531379243358979356279872004843970482401293718548405433274240",
-  619000i32 => "761174758294832672822340661.9747967066682300661240335625369111891426314755286575959608739836360922563",
-  620000i32 => "841224000590579504951447079.3504955431648770454111289750575138851562506936436508159510236556814038359",
-  621000i32 => "929691652879949852956265567.3257823368264640008023511202557865309373120987430418478567081380620312690",
-  622000i32 => "1027463040555018111551210844.775967507838384290031916031101956372102688049959408203009672666269310098",
-  623000i32 => "1135516594600297790088198043.292216874300544978510784468720668796311724697897498857501255891653920323",
-  624000i32 => "1254933643078923928621283568.412017813520783316955902952800797582069824054940078560115378143854225886",
-  625000i32 => "1386909232344324056884870823.197482694508909648070748177863745135194207365381907985819897032040335430",
-  626000i32 => "1532764086268862972356578535.735909885056678293417036878997902507755302856073195788875564758688812474",
-  627000i32 => "1693957823169463178231595936.490400470606264947238734557820836205150325820418921956914185814433794270",
-  628000i32 => "1872103562696397202481513923.518610315111342713419021259162650361651924506657328768073657142059756782",
-  629000i32 => "2068984068861274472906491756.286777114268892941900316814608523635848479261545596490623784046646528626",
-  630000i32 => "2286569590752904236875454026.707274196155155193802289276599486660856009551858525394268773293523657365",
-  631000i32 => "2527037579479046527742180667.608497409181120743188452954925826339174472259855242433459394919358176827",
-  632000i32 => "2792794478648083306714877665.124977115258313083503354843222188148104183174387907689489358264934240805",
-  633000i32 => "3086499806455249563906581433.635048124294984364439048231447261345842151163578840777284488191207662248",
-  634000i32 => "3411092770370917618031952936.336851284781726532386005420039541932228146521625199640113173980232131439",
-  635000i32 => "3769821680773023847139330585.495540251038032502271905181907513941116180066328287407852961460221066667",
-  636000i32 => "4166276457875697463656322341.035064963888485956255767684472341036412917162419378177935526590997839447",
-  637000i32 => "4604424557261800859827783050.184972490393348748805843981251859807092622101485572563175658261679678354",
-  638000i32 => "5088650673538204038793486188.324877248379763956829981278563442671363049996807210697803371062826910509",
-  639000i32 => "5623800619441553764620717420.428558535120804949554634725730770031774926437972797740746192014208452310",
-  640000i32 => "6215229819507428003772878288.877237538986170969071776374325969052769483668425473718878907123458605399",
-  641000i32 => "6868856903595245764690519770.646093653866861148209427876977499001309439266455505021851745003830212795",
-  642000i32 => "7591222936597265071783158315.829814657389577307560951689128349229352347005031118751918606613832892882",
-  643000i32 => "8389556877063181486580262623.712840079536539705677582548456122372074474301383622708184944843921028892",
-  644000i32 => "9271847919806681799984378432.917977011731431035762338201887021801865924636967768613315194723072988116",
-  645000i32 => "10246925446450617884764777312.54305591675604723151223899900246523894166785916346527391991117999330686",
-  646000i32 => "11324547384002652598739761252.16236179485265023471796181199482498659162673937083440671030554877465840",
-  647000i32 => "12515497855695203351802239540.52997624949452218353838170453660369087659725059767907288266541735574540",
-  648000i32 => "13831695101314306373608995638.21551983868114230812310278595834573107337930499152017178652986739064324",
-  649000i32 => "15286310747012235861967356575.00055334118246894543536987128718148561123186715550770450792488760521635",
-  650000i32 => "16893901618176792937959418182.27102100073192111300627425014542135462645223538914081205055110251675709",
-  651000i32 => "18670555414452743532625852024.91628128114986184808968948496360616293350666934623450065236684110694017",
-  652000i32 => "20634051704734078273342568967.32019666663158579069567242255407306224241717022507378395385305335355263",
-  653000i32 => "22804039853257840535557483116.76557991473449758251592484413125355851175401730704914636900713419887588",
-  654000i32 => "25202235657365466281206258133.50530002539248256615104891276849740890955554906197119806905797207855496",
-  655000i32 => "27852638664751495822007414462.85692862642547605262233628756413564428873425804904511618619468898510062",
-  656000i32 => "30781772344966065809709474943.96903239595059366210154946132628016065029897125490575300989057175950114",
-  657000i32 => "34018949518647752904772560134.32195649544695577044307807974035449961480609493290624585687842176310770",
-  658000i32 => "37596565700725897824333656408.87186656565211285554544262191767986489795379456022618622980077950615052",
-  659000i32 => "41550423293175969288198333289.76255616047098681460444217875117682742162255681515645452520553389123474",
-  660000i32 => "45920089871633324937985590478.11733159244323036374845458693064489516267507141657113053502940597298112",
-  661000i32 => "50749294151359372906129257080.07633923316388066217015666043286967471533037725305572624906639324124885",
-  662000i32 => "56086363595124022284636540989.77976216341822635469565768656203622355905134855191936717398104158313937",
-  663000i32 => "61984708042292897766697419033.31864891459789341936130237604962231195084716158031666108197245463838478",
-  664000i32 => "68503354198957385559145762434.61599566678624778381608100631977859653635930235103471831856841517274410",
-  665000i32 => "75707536337929048126205230078.30187657338995177486606506777991996318735848953435041222456067218229880",
-  666000i32 => "83669349119930862287122668753.60468621939865375525583156332762962156593737095441193429304435091055650",
-  667000i32 => "92468469068985333578392470314.95039015740104010640126096056679665553213243684472920228398690588445944",
-  668000i32 => "102192951922045055232569447990.7910881063813600009653753557720103999220099123446429268358603079840779",
-  669000i32 => "112940113832210206934635974081.3210203411021233712683978998648794297079468116992432387541942449302242",
-  670000i32 => "124817505244028385066935688691.6135263898974280319018731373431318764701342915172382397088129781032869",
-  671000i32 => "137943987186772686192773010149.4845577978344817020703293497309772144237331427229877818824901526227525",
-  672000i32 => "152450920756524936409871273286.9449099184931042754449477989502833768858326622625697120199550700740505",
-  673000i32 => "168483481690609198029898584705.8179868057043305633718513303622670345527743091104810309036258575950597",
-  674000i32 => "186202113189761682160161880208.0207974233247443937168329057069349361822169061758013143820801833793293",
-  675000i32 => "205784131526914542822396736503.4693986310652117825820770669495785898873328748583613642457681470110413",
-  676000i32 => "227425500510457785979024368689.9483680936236217188966937220041168940234492045636304663709213792309696",
-  677000i32 => "251342792559626777235082918163.0815778828326231469624489809709287020465334628060566988900248941935585",
-  678000i32 => "277775356017152801324330861186.9659332237317764058994552410300565282319862800233034421966042443859647",
-  679000i32 => "306987710388199411636581760751.3751146169447128612131800771525374879320047777967224224477201968272271",
-  680000i32 => "339272193475541899492574301158.6015038507223063375338769145836810021609999895451644700392183965974146",
-  681000i32 => "374951886901757186133980256176.5369312764604520049144753788470728544909144784413842177971368693677970",
-  682000i32 => "414383849295103384173620749801.2909809560471143556323519410640480953633442331661863213033259406748236",
-  683000i32 => "457962689494661742353821405298.7690688344721294445795198661175811647852114295305906565382045335898740",
-  684000i32 => "506124515533000194815097826602.3958654246886271607096327595749649073724233713362676475908898039904199",
-  685000i32 => "559351298915149105883257189239.0807791620771660039762340118500809603721167369555655767210458789841241",
-  686000i32 => "618175697868688929091305373765.2726395036185898738535410392704118557489918616635387375143801375480750",
-  687000i32 => "683186387832826958438915141985.2719602269536119483868073051858811346505577883747620479404767835322673",
-  688000i32 => "755033952530450603441486155634.7825333418060695484821519086850348777061947929459598359655552331480593",
-  689000i32 => "834437394577086000781907544539.0378762915467095845165401046879319450373263868563560785915846254711620",
-  690000i32 => "922191330780603844480656632155.9374951224744401608315406407006766742986211753358832821255014273953609",
-  691000i32 => "1019173944137443677620470519707.352310396195857021014381929272035599072163301380911292416848102468660",
-  692000i32 => "1126355772103643091134295936785.018700435103575215667554591332820544288629101720742716459396230423115",
-  693000i32 => "1244809419087840011899656784226.545969182648734176107648912225998517620878443529783438277449025373009",
-  694000i32 => "1375720290362414732610713469292.143589328912266548006265256085663209025525029454665338228805878211654",
-  695000i32 => "1520398454810611361435663213818.536319540768616679586245825810712889584795512071299094253793994591932",
-  696000i32 => "1680291755224117651795911881789.092886534115221394648885089760233295035717786259632410385808835614263",
-  697000i32 => "1857000297350236960155436387763.076208502938595586911228978670303212374400467084130938486280105353759",
-  698000i32 => "2052292462685394489509478836778.251967093821805079654761588566908776167023876952183793624921523555536",
-  699000i32 => "2268122605260359291464793752411.121827055684585260294619504085190914285021670408297385093795449800046",
-  700000i32 => "2506650609514831955255171268580.542547641395785056955688389592565613670182264602647631669283656514421",
-  701000i32 => "2770263504983591795860091728803.541207089121743110611395278440545410422784930701855167104241283573808",
-  702000i32 => "3061599354099598706817000907229.220971558838330938550533454902146540400730568181669649696374106053973",
-  703000i32 => "3383573652167286648104410728816.065919169844535231979500968545069677989932151340026564341545792153355",
-  704000i32 => "3739408503699413258536370426447.958499343498338544198953024816958444396272799242390531195904756337682",
-  705000i32 => "4132664867094828981809823695785.413411808267468965485811469329944904598052160583129252471729269057820",
-  706000i32 => "4567278190340443147422175886383.053943409730535019483435416837219794227453094970511383930950798161831",
-  707000i32 => "5047597794355778006279875912475.718389336937984272208372728895477076987039885663556298571924298229330",
-  708000i32 => "5578430398102414819848118309397.579950951214518214751205941626328286473972507432887213059542547448061",
-  709000i32 => "6165088221028662945938009492027.004674733451940352158007266879410802306004393098368555279414332052414",
-  710000i32 => "6813442144226707724178264560710.973089237746225284052109482523254368988177732908460164939971426527013",
-  711000i32 => "7529980462303721061012720934942.763230390294981272498897855333894066200124299992777971292141385837038",
-  712000i32 => "8321873813916563533894142773197.507172539977995898431983622731937567678148046134763417064854998490612",
-  713000i32 => "9197046940751660380090373276246.627388254296464877801950062823686301024341303442099948774173662970372",
-  714000i32 => "10164257993066168951327655406291.16828686848413312998690256617336587862694576730472637291795799770655",
-  715000i32 => "11233186175427518655634664185838.76373774345094109553203832624796491812822939836754417067449072750778",
-  716000i32 => "12414528609751559617846088145935.81099728270296413331264455005739029044133794829163100379240665190176",
-  717000i32 => "13720107384980146985894110130144.63411994378190458457618074872529638484706237186741411601151175862954",
-  718000i32 => "15162987864680096440149827616705.92945824747230241200287133455276289779894730136517752490528367123143",
-  719000i32 => "16757609436507231804707145112621.97896248146889613718501404712295738728956766279668041431326341036450",
-  720000i32 => "18519930011989151887965860863701.30007141295770330235362203286871034583465907903291508704744626917675",
-  721000i32 => "20467585722684383079842984448972.75577258345636330840380097281614707907407489353444637519085227739909",
-  722000i32 => "22620067410850806460202425215703.69375066246310932111771659299627673715916907459422069384050987516097",
-  723000i32 => "24998915680824520851108068410767.66956082172186505441510734044093414979098985036342601099959815452907",
-  724000i32 => "27627936463053541803850734522644.78939334374851508664719483629264652589779138683207972205859392760375",
-  725000i32 => "30533439248007735676429307931105.65047947507401893115721853617021662385357059495195333167703665450697",
-  726000i32 => "33744500374051423331468390432623.49769441019242820334138856511203015387941379278632800456062242852823",
-  727000i32 => "37293254004088473871941655741211.18224083549700060271279560910823748044356055793686337977529450179288",
-  728000i32 => "41215213702880518031115748762916.19912407371073344259098825709386249898527594695625351270904124218147",
-  729000i32 => "45549627833170083768340321023767.69327538006125147999973270633005032736202050385129571321729856563226",
-  730000i32 => "50339872327176551086600426513290.29827482050661887805252256721005520754830610919283656630206825393035",
-  731000i32 => "55633884764060682297480007604145.84567037373957228231067764085612408914939266228065607040759014882837",
-  732000i32 => "61484644097316129827255869606338.69004345428229136802545226532789520848275261669226573726740571152843",
-  733000i32 => "67950700832880414466138907395977.67244244984233367605873624193896238883375048050739002638753151657742",
-  734000i32 => "75096762963635094285372162460037.50528519250125318201378693382285043297389367205361434797673445632711",
-  735000i32 => "82994343523937683597410655587720.15696811688589609644693340617774362801023693120589021197098213333058",
-  736000i32 => "91722476244480153176127663636625.56571346448536682448562802353125149671856588407769828204492943044587",
-  737000i32 => "101368506469271593063621661846125.6843383369110013557541370415763787271929215470385272335830224266761",
-  738000i32 => "112028965249715873675289683260432.1052702108136266501410334501423673820985919779916729993802990444964",
-  739000i32 => "123810535363136156295586980363634.7160382672019584046955033217461970647629187613940699031997281726509",
-  740000i32 => "136831118923016795715641490317763.7044838946637814699391447222980941342756543938670494601507398818276",
-  741000i32 => "151221017264895479793984683556136.9996144110814372370515867327575088442010755092834897380084922734377",
-  742000i32 => "167124234915418442889654530863836.2286934252839761275650043879250166934421779628967061118907703085681",
-  743000i32 => "184699920693813313282404703901542.0079812651774299689405230285457864190135500907114098842531835580254",
-  744000i32 => "204123960367364133688744727575889.4414084418194318336407367217888366291681112657701147080450729522138",
-  745000i32 => "225590736799124693998615508294358.0406554306163882608043504089884574466012626094193174021643663406217",
-  746000i32 => "249315075202257176331596431775238.3958379492013963542770908630324093117144101430451113743658920782866",
-  747000i32 => "275534392967807036842218861359983.0031362844857946713106723959923755536835447784519493011204710116063",
-  748000i32 => "304511075579959862304496341873492.7715058186392938182953439459981902365508340527779123909052725794706",
-  749000i32 => "336535102395359006029623511211839.3680680660417991930582593547183621659124788817457376622178798793697",
-  750000i32 => "371926948563536052413413232710191.4112780856343505041030341814958154511073311658342840797552846565207",
-  751000i32 => "411040792128942654629895501657036.3442990331426160634867058838113638720757536418508959583104690922763",
-  752000i32 => "454268058409126669161253046629882.2024320448761267792107877745970897649181011469008410525173853344981",
-  753000i32 => "502041337118830717845449572957490.9545459407203308193164053495480521232037006035027706638227364990775",
-  754000i32 => "554838711439984450785568553185376.7048172750111623052568411234942789657600044053009687521393426162899",
-  755000i32 => "613188542360042152461547666199733.8509856969728088818982718500448913975290058409309671119851547018085",
-  756000i32 => "677674756157139968886315288195706.5755728917995443038997856167178914235116572075084559213539997146350",
-  757000i32 => "748942687945705578321368134500883.2897845096992629330674912689787523770172687339002292631078344715872",
-  758000i32 => "827705539760836097909923112310196.0875909589373350356212260670148151927795045819582692764149237406633",
-  759000i32 => "914751517809655057664942277524831.4223997233195545274714513265888747710337959057080425642232930175399",
-  760000i32 => "1010951720314510438581929188066736.688454361934286693335618975498724620462342230956652167673874627902",
-  761000i32 => "1117268854884299425168416186077132.940764845261247868784629642684106848445549553792881577433995570597",
-  762000i32 => "1234766872651570993735935276412876.396497410402758301771082718544742998935162547538950504713884386260",
-  763000i32 => "1364621615587439287760438709101419.472368450505480153098258714781086837121961294190745065588985946071",
-  764000i32 => "1508132583545550028617034894691797.647796645972649421842034084302571824362560772320928622568075730542",
-  765000i32 => "1666735938791845433400146231943419.579149735066007195264137691092762287652444257141498471871351145053",
-  766000i32 => "1842018878160807480751638050601354.010144434703883809236952909182243847386131794380868029479923834563",
-  767000i32 => "2035735516665155054741698721664141.476169520021447787074809922662696142277319055290027738084931383566",
-  768000i32 => "2249824441511590845399952444202109.304503181206116774635365027721037049593662780193692967953915282681",
-  769000i32 => "2486428112191506035369977916072354.074863021868256256123245520729964058844859452629300226420655482380",
-  770000i32 => "2747914300789840483261129121567973.287639248960991406903846289073168003691124147411368265532279509988",
-  771000i32 => "3036899787072441722751085617137701.879264075188012870752881375405858849084444815694767399070113505034",
-  772000i32 => "3356276545476588838725209076925369.941208414756730306792115947150758413668021828035977321677906687584",
-  773000i32 => "3709240686066655883984381036997641.078581935510969785177202675580713059709040728500635961594346946325",
-  774000i32 => "4099324439076739951921725296862204.481673689705876622226644454727198502169664560615706989586730071822",
-  775000i32 => "4530431503120271965294501847548566.684641706993585488795117083044060204760401316222900817699257230028",
-  776000i32 => "5006876110807969004960452350731911.072230667935952071938317623111722376734667318798521482483817479425",
-  777000i32 => "5533426202716835883161102992643264.444235039591339996451939029012843251196478820197305757267051770932",
-  778000i32 => "6115351141766567007057793992821712.040059067059734881225965812033714027325772027658893780676112521390",
-  779000i32 => "6758474445497075362667980191816010.083627181332082100594361952009199361652743256164257449757475001402",
-  780000i32 => "7469232063956690697022559820159240.589323733616131892154498058830872224306000705205316244469766398704",
-  781000i32 => "8254736786407350149451153404329117.931340429695333144393637732263935423779780062425630230816650075292",
-  782000i32 => "9122849421386226536271718990567837.700600453466374103271537696777127459027659646499782214284241999580",
-  783000i32 => "10082257462446483296338404104757921.20897297061363088524848741616298922279473705390515145194244804385",
-  784000i32 => "11142562026811540354560852221306456.17521032520531099809717435319483803247230600477984290757755900430",
-  785000i32 => "12314373936967048251671642825098080.48862856928839355075858656097712683476681340663486605083408381256",
-  786000i32 => "13609419906711204176962464861537753.42219858817941855772258220592209762169584299986360632224115276241",
-  787000i32 => "15040659894302738473224416122392854.83533526469210739086572812903663399690159731170005636701785164569",
-  788000i32 => "16622416797098782367747048679016142.15654185478316040297626215503491084703791896522497039068285329482",
-  789000i32 => "18370519785580245597338298020487276.62213996695073752073769204803691337925163836485766294163182619474",
-  790000i32 => "20302462711156246272998396885137418.90350145722891138387244776644957961078929903741263838295013837554",
-  791000i32 => "22437579172987485027415858315213055.18859931870704664857334122885442203888580823691545480506695080023",
-  792000i32 => "24797235995780831105479600845834920.04275953893312047430907591332532834098639512760503421942861908581",
-  793000i32 => "27405047054752136108609413069902244.48956854894637623107120108786817404730113510575340822891279030725",
-  794000i32 => "30287109587575210418140974703594613.14930595789012813531903773291452948734474504525743812635761527402",
-  795000i32 => "33472265358169690559369292491829435.57967725370055597594226125021330095217008753784088571726778051076",
-  796000i32 => "36992389285881191384311947449059863.12201349084550503908222354408593378713165854390970509914890165931",
-  797000i32 => "40882708428462505707951140063603155.76350689644247109480788964437848273660023975846529036378608864487",
-  798000i32 => "45182154511025255553815510364314616.82737268858044814320124604812199267734792260095508192940192520222",
-  799000i32 => "49933753528837147808841218198020857.59132960214260929627398044469667972093562638904209084202623240424",
-  800000i32 => "55185056322850362586846946569204953.76267199778942316141910390713049678541329130280132852056319496599",
-  801000i32 => "60988614436874429898619347518129438.66275003318254925308531380335496615256326039624910965875232773970",
-  802000i32 => "67402506018455514961381574070564375.77932220582050635630562436326562713172892351339904463792650198918",
-  803000i32 => "74490917026328143933117803923439354.35472423563909991332842023179843982445547496059144956093380814791",
-  804000i32 => "82324783560776774308163250128001388.22960805869539375132314851318677987828600305839302433092919642617",
-  805000i32 => "90982501744922020839858519690268214.72942021882875178170330333531800328548418051243334201866111372147",
-  806000i32 => "100550712260951049100163489868778238.0576423971161046926297738671793570075648452229836951720148009023",
-  807000i32 => "111125167392408649838188596095408498.1840665935750831520965633915280217243611165597768999654968311167",
-  808000i32 => "122811689249331257780002077428868524.2751596279686343446687997599044439629079259121595590578817949869",
-  809000i32 => "135727228765503391800786477998383182.4512836468052441007984974535981004451710535307687627133496023371",
-  810000i32 => "150001036065576334373995906767169422.4844850887035381894919326649970793991825698395705939304819729721",
-  811000i32 => "165775953914304336859020026815515540.6817187069771435951142866945212080758761326811319307282522297212",
-  812000i32 => "183209847191877562669389200192944399.1104514391312715051963538834307185529767752736955459309659147652",
-  813000i32 => "202477182700589636046038378382921883.5988626264045343216595039182372643976115343019794767951304254593",
-  814000i32 => "223770775112493620802027592401270471.4512634517582576686203545540016362705877437634015224901742229656",
-  815000i32 => "247303716530328704765808397401678017.7904095154266377742024778762163241326367563370209802907681003880",
-  816000i32 => "273311508971479290851469027814695639.4777475124278535966698752348183777345595777848459501183884295840",
-  817000i32 => "302054421115446866037396047696040986.4143656168435307705561592089797416345480864036918408415939038513",
-  818000i32 => "333820092899595016122183762678377125.4887205155311122423421873743591658851531055797294233333984769424",
-  819000i32 => "368926414028228537666711115105851574.8677447988983775725168067674038531377756034218543737580699865806",
-  820000i32 => "407724705201209966713414800195301795.3295471721957374094586138858584728331523411954334565044610924470",
-  821000i32 => "450603233897732525387122883692698344.1682040087705555770789450555111956820910557144953441651707611519",
-  822000i32 => "497991099898873858499888422306717733.2699457866737532230421695860917047799245232355747876214519908618",
-  823000i32 => "550362529432654602581185439030834590.6949036426785913331357657312915424892460510476094931535697518720",
-  824000i32 => "608241620914547934551830539251997573.7950036475857363162203325780842747716636037282231680752258262970",
-  825000i32 => "672207589775653000149788550847548250.1122056213419033503344358736591275149292404015366027366466158133",
-  826000i32 => "742900564865282337452207575876847908.5400186354559612998764270235919768619681542692256525788050581986",
-  827000i32 => "821027994434503093185985980595823836.8583885758295963356387739477777482130354336979575723863323287386",
-  828000i32 => "907371725807452360898527000860628157.4087549350668025255157709484358817107705628993191942655335042526",
-  829000i32 => "1002795829589065898512056443952064205.997443497409269431692895536400844384221870531864654216303241042",
-  830000i32 => "1108255246708684445707129454590947914.826575561106345849443265053137502718120124019117175615024464931",
-  831000i32 => "1224805344833395756946887976661215496.297648188889458002098891893470974769212964992178359641558044609",
-  832000i32 => "1353612479785337628175387453068629361.168583212943721279888575018369420113253665215276237584673441821",
-  833000i32 => "1495965667654598042893685902681675211.879320837080021368700396383245786357666715710752780539670645731",
-  834000i32 => "1653289484414450971355029413942607309.348694187868549451329390234389674601445915465431423718291889704",
-  835000i32 => "1827158322129692873224524657150558095.173668581813008687800086285580557333221885743056199307966243698",
-  836000i32 => "2019312144424725942019932145590033914.264243930435056215188993929066667234018250168058529398054794462",
-  837000i32 => "2231673898881627971656215477936606464.840600054218271952093631488306546446827403081249397665081041471",
-  838000i32 => "2466368760619901404276488892577199210.279503444202940985144988024341922105599964847175678038405736287",
-  839000i32 => "2725745399634841778316249745922731501.393658013496089199036893541873722286059352084771342709369922122",
-  840000i32 => "3012399484723895320202604759066639971.437695407886879612869973177611337354399476094254743923834508641",
-  841000i32 => "3329199659212659649653613763469772862.779706432675326551707081273595271296451060470802250808991522037",
-  842000i32 => "3679316248428307503149973586444620654.471815127161445239419062267444383477267299712726539680722007756",
-  843000i32 => "4066252986205723710154325516972874714.974792317930593995144590102678822525130736224794647345416579941",
-  844000i32 => "4493882077924115993306231934557181642.172296843853510530402526292820013817155770339316677376496402283",
-  845000i32 => "4966482950961636804941239496006679017.258741690458905195390960912006697262907608556865209269643549604",
-  846000i32 => "5488785080356779022800490331540586050.552489173988977980066714436623612883018305749581392602671427687",
-  847000i32 => "6066015318247265817185982040853656793.847770835114637726940409247044649828299122999255509963898229336",
-  848000i32 => "6703950200727963061705118930565128953.529800402236138625509692522799292153583439942502115966572642622",
-  849000i32 => "7408973755580039944199861657024204091.500760322215701076316152181043028688420167340060845360960081669",
-  850000i32 => "8188141389372662254667583260910285617.730894813276387137212633044992717257369995751168521976001521692",
-  851000i32 => "9049250493276817530458564191701784855.396404100998185010792055891353265722977877787894546144566668213",
-  852000i32 => "10000918474167271193623153394438881083.04288398464035761689514044790868422169657613380565376373600183",
-  853000i32 => "11052668991895995168321951056811391862.56609039691201035534758683193984865427834539664124805813706725",
-  854000i32 => "12215027265742313651113004233787089332.04648513859611920895600148373407199797128133404849226074960132",
-  855000i32 => "13499625403803296081595383172845531470.73856078779943816780034374478984801605971548260074519372624329",
-  856000i32 => "14919318809390843448976799251767787779.12541503449945711388029074596263073796385615223509458012154685",
-  857000i32 => "16488314829353225707874129015994634063.03597344836374713538450897101583278845036109786116582638502216",
-  858000i32 => "18222314931747861423363263757998054715.75857497562400103317873995023590884466013402061121855577106249",
-  859000i32 => "20138671835684874701351285584814804665.91333550866158051622086102678885359406829119350544451745031670",
-  860000i32 => "22256563165792339468064895862908679000.27502126410480254153543744010695623439916607915531843672392617",
-  861000i32 => "24597183369121538468618458908446076270.76084403046718201192218254908867924571625640156725813760794763",
-  862000i32 => "27183955815068911025580386805538291852.66843394606034074082338562136380759863427105206420145686405738",
-  863000i32 => "30042767200869563548580680420868796215.37831318099291150856312403149520443678424326442982757613462796",
-  864000i32 => "33202226608436533431175565496493606234.68200280554418909882215271884286718418520854885766600560553209",
-  865000i32 => "36693951805014260687973233445648823859.68806109375808646140772856179869799972475190704983716271711138",
-  866000i32 => "40552885652752684498554207755620929300.62150528307961223046632508169349811283167981544091111217174354",
-  867000i32 => "44817645793618423036079755816279137712.55825529256778822033575303367842477237876734273685457439653390",
-  868000i32 => "49530911109056924949130552395320917309.46323374227340185266033180381144138114127537303298938449029610",
-  869000i32 => "54739848821836716316800661207380154831.13796665984478973905957216247174002717999691360899521503074605",
-  870000i32 => "60496586514226779399566282799478646344.68604637721019291554773640376953483701396277583332769313658509",
-  871000i32 => "66858733786150871288820371228004562431.13469513882801187550178884726243689231741380745791164573018435",
-  872000i32 => "73889958773726446154933936291318647370.71230080149023667777287963410722969906517954129978379516685300",
-  873000i32 => "81660625297602066295625476889521426639.02127562604014129355230453426799989723427637691990102897164791",
-  874000i32 => "90248497017249863514207435370012958368.95145789561171213553030170674719149883821389069577474001148653",
-  875000i32 => "99739515637920620450213915247645079687.96835089531285039986905659839311334102433920289097360874308129",
-  876000i32 => "110228660958038809604436269246953870998.0723477703899981206234066621633769573827185905105031440326304",
-  877000i32 => "121820901363819589030591716560126513436.0922723841083622663209833714563906003560805456638281566595596",
-  878000i32 => "134632244283025361638200123959950147301.4224058706723361334828863441374300490043684359918851378101878",
-  879000i32 => "148790897110104054595171965678354282887.8345369877105451595644852164682945537306001971770943613496073",
-  880000i32 => "164438550220475348845977430117945241298.3194337660026907907366025619928886815656208297834803282230806",
-  881000i32 => "181731794913518035513464231715166493496.3502553931825582035340407454670410450055432677301146648292904",
-  882000i32 => "200843690474088326637289098307358217290.9459504771703545557390320436889671197690606857353618273252930",
-  883000i32 => "221965496034677972479080647219024787805.7992611593412141445323241117002844241860742488765894475365087",
-  884000i32 => "245308584569536171915862588143994209205.3682289737237514083612674896381506143342172242384309116929105",
-  885000i32 => "271106558174734753819626813245216126854.5146624658553373621589429015504214375936985598105892511099162",
-  886000i32 => "299617585802491879028339529823885545826.7220561431124222220672809975426022785120685347243032226607752",
-  887000i32 => "331126986844243696487400943263055312001.0421456212919281661117652525679855599085731803206689634895683",
-};
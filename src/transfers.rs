@@ -0,0 +1,52 @@
+use crate::utils::ZERO_ADDRESS;
+use substreams::scalar::BigInt;
+
+// Change of the total supply of a token from one of its `Transfer` events: mints come from the zero
+// address and burns go to it
+pub fn supply_delta(from: &[u8], to: &[u8], value: &BigInt) -> Option<BigInt> {
+    match (from == ZERO_ADDRESS, to == ZERO_ADDRESS) {
+        (true, false) => Some(value.clone()),
+        (false, true) => Some(value.neg()),
+        _ => None,
+    }
+}
+
+// Change of the balance of `holder` from a `Transfer`, `None` when it isn't one of its sides
+pub fn balance_delta(holder: &[u8], from: &[u8], to: &[u8], value: &BigInt) -> Option<BigInt> {
+    match (from == holder, to == holder) {
+        (false, true) => Some(value.clone()),
+        (true, false) => Some(value.neg()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::transfers::{balance_delta, supply_delta};
+    use crate::utils::ZERO_ADDRESS;
+    use substreams::hex;
+    use substreams::scalar::BigInt;
+
+    #[test]
+    fn test_supply_delta() {
+        let holder = hex!("1f98431c8ad98523631ae4a59f267346ea31f984");
+        let value = BigInt::from(1000);
+
+        assert_eq!(Some(BigInt::from(1000)), supply_delta(&ZERO_ADDRESS, &holder, &value));
+        assert_eq!(Some(BigInt::from(-1000)), supply_delta(&holder, &ZERO_ADDRESS, &value));
+        assert_eq!(None, supply_delta(&holder, &holder, &value));
+        assert_eq!(None, supply_delta(&ZERO_ADDRESS, &ZERO_ADDRESS, &value));
+    }
+
+    #[test]
+    fn test_balance_delta() {
+        let pool = hex!("8ad599c3a0ff1de082011efddc58f1908eb6e6d8");
+        let holder = hex!("1f98431c8ad98523631ae4a59f267346ea31f984");
+        let value = BigInt::from(1000);
+
+        assert_eq!(Some(BigInt::from(1000)), balance_delta(&pool, &holder, &pool, &value));
+        assert_eq!(Some(BigInt::from(-1000)), balance_delta(&pool, &pool, &holder, &value));
+        assert_eq!(None, balance_delta(&pool, &pool, &pool, &value));
+        assert_eq!(None, balance_delta(&pool, &holder, &ZERO_ADDRESS, &value));
+    }
+}
@@ -0,0 +1,207 @@
+use crate::config::Config;
+use crate::guards;
+use crate::pb::uniswap::TwapCheckpoint;
+use substreams::store::{StoreGet, StoreGetProto};
+
+// Default windows, in seconds, over which the time-weighted average ticks are computed: 5m, 30m, 1h and 24h.
+// They are overridden with the `twap_windows` param.
+pub const DEFAULT_TWAP_WINDOWS: [u64; 4] = [300, 1800, 3600, 86400];
+
+// Each window is split in buckets, and the last checkpoint of each bucket is kept in the store. The
+// tick cumulative at the start of a window is exact unless the target time falls before the last
+// checkpoint of its bucket, in which case the error is bounded by the size of the bucket.
+pub const BUCKETS_PER_WINDOW: u64 = 6;
+
+// Number of buckets kept in the store for each window. The buckets are stored in a ring of as many slots, the
+// slot of a bucket being reused by the bucket `RETAINED_BUCKETS` later: the store stays bounded whatever the gaps
+// between the blocks, and a slot still holding the checkpoint of an older bucket is ignored.
+pub const RETAINED_BUCKETS: u64 = BUCKETS_PER_WINDOW + 2;
+
+// Windows kept in the store: the `twap_windows` emitted by `map_pool_twaps` and the window of the price guards
+pub fn stored_windows(config: &Config) -> Vec<u64> {
+    let mut windows = config.twap_windows.clone();
+    windows.push(guards::GUARD_TWAP_WINDOW);
+    windows.sort();
+    windows.dedup();
+    windows
+}
+
+pub fn bucket_size(window: u64) -> u64 {
+    window / BUCKETS_PER_WINDOW
+}
+
+pub fn bucket_id(window: u64, timestamp: u64) -> u64 {
+    timestamp / bucket_size(window)
+}
+
+pub fn bucket_slot(bucket: u64) -> u64 {
+    bucket % RETAINED_BUCKETS
+}
+
+pub fn bucket_key(window: u64, bucket: u64, pool_address: &str) -> String {
+    format!("twap:{window}:{}:{pool_address}:last", bucket_slot(bucket))
+}
+
+// Last checkpoint of the pool in `bucket`, if the slot of the bucket wasn't left to an older bucket
+fn bucket_checkpoint(
+    store: &StoreGetProto<TwapCheckpoint>,
+    pool_address: &str,
+    window: u64,
+    bucket: u64,
+) -> Option<TwapCheckpoint> {
+    store
+        .get_last(bucket_key(window, bucket, pool_address))
+        .filter(|checkpoint| bucket_id(window, checkpoint.timestamp) == bucket)
+}
+
+// Tick cumulative at `timestamp`, given the last checkpoint at or before `timestamp`
+pub fn extrapolate_forward(checkpoint: &TwapCheckpoint, timestamp: u64) -> i64 {
+    let elapsed = timestamp as i64 - checkpoint.timestamp as i64;
+    checkpoint.tick_cumulative + checkpoint.tick_after as i64 * elapsed
+}
+
+// Tick cumulative at `timestamp`, given the first checkpoint at or after `timestamp`
+pub fn extrapolate_backward(checkpoint: &TwapCheckpoint, timestamp: u64) -> i64 {
+    let elapsed = checkpoint.timestamp as i64 - timestamp as i64;
+    checkpoint.tick_cumulative - checkpoint.tick_before as i64 * elapsed
+}
+
+// Same rounding as the OracleLibrary: the arithmetic mean tick is rounded to negative infinity
+pub fn arithmetic_mean_tick(tick_cumulative_end: i64, tick_cumulative_start: i64, window: u64) -> i32 {
+    let delta = tick_cumulative_end - tick_cumulative_start;
+    let window = window as i64;
+
+    let mut mean_tick = delta / window;
+    if delta < 0 && delta % window != 0 {
+        mean_tick -= 1;
+    }
+
+    mean_tick as i32
+}
+
+// Finds the tick cumulative of the pool at `target`, looking at the previous checkpoint of the pool
+// and at the checkpoints indexed in the buckets around `target`. Returns `None` when the pool has no
+// history before `target`.
+pub fn tick_cumulative_at(
+    store: &StoreGetProto<TwapCheckpoint>,
+    pool_address: &String,
+    window: u64,
+    target: u64,
+    now: u64,
+) -> Option<i64> {
+    // at ordinal 0, the latest checkpoint is still the one of a previous block
+    let previous = store.get_at(0, format!("pool:{pool_address}:latest"))?;
+    if previous.timestamp <= target {
+        return Some(extrapolate_forward(&previous, target));
+    }
+
+    let target_bucket = bucket_id(window, target);
+    if let Some(last) = bucket_checkpoint(store, pool_address, window, target_bucket) {
+        if last.timestamp <= target {
+            return Some(extrapolate_forward(&last, target));
+        }
+        return extrapolate_backward_if_initialized(&last, target);
+    }
+
+    // nothing happened in the target bucket, the last checkpoint of an earlier bucket is exact
+    let oldest_bucket = bucket_id(window, now).saturating_sub(RETAINED_BUCKETS - 1);
+    for bucket in (oldest_bucket..target_bucket).rev() {
+        if let Some(last) = bucket_checkpoint(store, pool_address, window, bucket) {
+            return Some(extrapolate_forward(&last, target));
+        }
+    }
+
+    for bucket in (target_bucket + 1)..bucket_id(window, now) {
+        if let Some(last) = bucket_checkpoint(store, pool_address, window, bucket) {
+            return extrapolate_backward_if_initialized(&last, target);
+        }
+    }
+
+    None
+}
+
+// Time-weighted average tick of the pool over the `window` ending at `now`, computed from the checkpoints of
+// the previous blocks only so that the updates of the current block can be compared with it
+pub fn previous_blocks_twap_tick(
+    store: &StoreGetProto<TwapCheckpoint>,
+    pool_address: &String,
+    window: u64,
+    now: u64,
+) -> Option<i32> {
+    let previous = store.get_at(0, format!("pool:{pool_address}:latest"))?;
+    let tick_cumulative_now = extrapolate_forward(&previous, now);
+    let tick_cumulative_start = tick_cumulative_at(store, pool_address, window, now.saturating_sub(window), now)?;
+
+    Some(arithmetic_mean_tick(tick_cumulative_now, tick_cumulative_start, window))
+}
+
+fn extrapolate_backward_if_initialized(checkpoint: &TwapCheckpoint, target: u64) -> Option<i64> {
+    // the oracle starts at zero when the pool is initialized, there is nothing before it
+    if checkpoint.tick_cumulative == 0 {
+        return None;
+    }
+    Some(extrapolate_backward(checkpoint, target))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::pb::uniswap::TwapCheckpoint;
+    use crate::twap::{
+        arithmetic_mean_tick, bucket_id, bucket_key, extrapolate_backward, extrapolate_forward, stored_windows,
+    };
+
+    fn checkpoint(timestamp: u64, tick_cumulative: i64, tick_before: i32, tick_after: i32) -> TwapCheckpoint {
+        TwapCheckpoint {
+            pool_address: "8ad599c3a0ff1de082011efddc58f1908eb6e6d8".to_string(),
+            timestamp,
+            tick_cumulative,
+            tick_before,
+            tick_after,
+        }
+    }
+
+    #[test]
+    fn test_extrapolate_forward() {
+        let c = checkpoint(1_000, 50_000, 10, 20);
+        assert_eq!(50_000, extrapolate_forward(&c, 1_000));
+        assert_eq!(50_200, extrapolate_forward(&c, 1_010));
+    }
+
+    #[test]
+    fn test_extrapolate_backward() {
+        let c = checkpoint(1_000, 50_000, 10, 20);
+        assert_eq!(49_900, extrapolate_backward(&c, 990));
+    }
+
+    #[test]
+    fn test_arithmetic_mean_tick() {
+        assert_eq!(200, arithmetic_mean_tick(60_000, 0, 300));
+        assert_eq!(-200, arithmetic_mean_tick(-60_000, 0, 300));
+        // rounds to negative infinity
+        assert_eq!(-201, arithmetic_mean_tick(-60_001, 0, 300));
+        assert_eq!(200, arithmetic_mean_tick(60_001, 0, 300));
+    }
+
+    #[test]
+    fn test_bucket_id() {
+        assert_eq!(0, bucket_id(300, 49));
+        assert_eq!(1, bucket_id(300, 50));
+        assert_eq!(5, bucket_id(86400, 72_000));
+    }
+
+    #[test]
+    fn test_bucket_key() {
+        let pool = "8ad599c3a0ff1de082011efddc58f1908eb6e6d8";
+        assert_eq!(format!("twap:300:3:{pool}:last"), bucket_key(300, 3, pool));
+        // the slot is reused 8 buckets later
+        assert_eq!(bucket_key(300, 3, pool), bucket_key(300, 11, pool));
+    }
+
+    #[test]
+    fn test_stored_windows() {
+        let config = Config::from_params("twap_windows=600,60").unwrap();
+        assert_eq!(vec![60, 600, 1800], stored_windows(&config));
+        assert_eq!(vec![300, 1800, 3600, 86400], stored_windows(&Config::default()));
+    }
+}
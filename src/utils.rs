@@ -1,8 +1,11 @@
 use crate::ethpb::v2::TransactionTrace;
 use crate::pb::uniswap::events;
 use crate::pb::AdjustedAmounts;
+use crate::uniswap::erc20_token::MetadataStatus;
 use crate::uniswap::events::Transaction;
-use crate::{storage, Erc20Token, StorageChange, WHITELIST_TOKENS};
+use crate::whitelist::Whitelist;
+use crate::{storage, Erc20Token, StorageChange};
+use std::collections::HashSet;
 use std::ops::{Add, Mul};
 use std::string::ToString;
 use substreams::prelude::StoreGetBigDecimal;
@@ -10,11 +13,10 @@ use substreams::scalar::{BigDecimal, BigInt};
 use substreams::store::StoreGet;
 use substreams::{hex, key, log, Hex};
 
-pub const UNISWAP_V3_FACTORY: [u8; 20] = hex!("1f98431c8ad98523631ae4a59f267346ea31f984");
-
 pub const ZERO_ADDRESS: [u8; 20] = hex!("0000000000000000000000000000000000000000");
-pub const NON_FUNGIBLE_POSITION_MANAGER: [u8; 20] = hex!("c36442b4a4522e871399cd717abdd847ab11fe88");
-pub const ERROR_POOL: [u8; 20] = hex!("8fe8d9bb8eeba3ed688069c3d6b556c9ca258248");
+
+// An entry leaving a `;` separated list of an appender store is appended again with this prefix
+pub const REMOVED_ENTRY_PREFIX: &str = "-";
 
 const DGD_TOKEN_ADDRESS: [u8; 20] = hex!("e0b7927c4af23765cb51314a0e0521a9645f0e2a");
 const AAVE_TOKEN_ADDRESS: [u8; 20] = hex!("7fc66500c84a76ad7e9c93437bfc5ac33e2ddae9");
@@ -34,6 +36,8 @@ pub fn get_static_uniswap_tokens(token_address: &[u8]) -> Option<Erc20Token> {
             decimals: 9,
             total_supply: "".to_string(), // subgraph doesn't check the total supply
             whitelist_pools: vec![],
+            metadata_status: MetadataStatus::Overridden as i32,
+            metadata_fallback_reason: "".to_string(),
         }),
         x if x == AAVE_TOKEN_ADDRESS => Some(Erc20Token {
             address: Hex(&AAVE_TOKEN_ADDRESS).to_string(),
@@ -42,6 +46,8 @@ pub fn get_static_uniswap_tokens(token_address: &[u8]) -> Option<Erc20Token> {
             decimals: 18,
             total_supply: "".to_string(), // subgraph doesn't check the total supply
             whitelist_pools: vec![],
+            metadata_status: MetadataStatus::Overridden as i32,
+            metadata_fallback_reason: "".to_string(),
         }),
         x if x == LIF_TOKEN_ADDRESS => Some(Erc20Token {
             address: Hex(&LIF_TOKEN_ADDRESS).to_string(),
@@ -50,6 +56,8 @@ pub fn get_static_uniswap_tokens(token_address: &[u8]) -> Option<Erc20Token> {
             decimals: 18,
             total_supply: "".to_string(), // subgraph doesn't check the total supply
             whitelist_pools: vec![],
+            metadata_status: MetadataStatus::Overridden as i32,
+            metadata_fallback_reason: "".to_string(),
         }),
         x if x == SVD_TOKEN_ADDRESS => Some(Erc20Token {
             address: Hex(&SVD_TOKEN_ADDRESS).to_string(),
@@ -58,6 +66,8 @@ pub fn get_static_uniswap_tokens(token_address: &[u8]) -> Option<Erc20Token> {
             decimals: 18,
             total_supply: "".to_string(), // subgraph doesn't check the total supply
             whitelist_pools: vec![],
+            metadata_status: MetadataStatus::Overridden as i32,
+            metadata_fallback_reason: "".to_string(),
         }),
         x if x == THEDAO_TOKEN_ADDRESS => Some(Erc20Token {
             address: Hex(&THEDAO_TOKEN_ADDRESS).to_string(),
@@ -66,6 +76,8 @@ pub fn get_static_uniswap_tokens(token_address: &[u8]) -> Option<Erc20Token> {
             decimals: 16,
             total_supply: "".to_string(), // subgraph doesn't check the total supply
             whitelist_pools: vec![],
+            metadata_status: MetadataStatus::Overridden as i32,
+            metadata_fallback_reason: "".to_string(),
         }),
         x if x == HPB_TOKEN_ADDRESS => Some(Erc20Token {
             address: Hex(&HPB_TOKEN_ADDRESS).to_string(),
@@ -74,11 +86,25 @@ pub fn get_static_uniswap_tokens(token_address: &[u8]) -> Option<Erc20Token> {
             decimals: 18,
             total_supply: "".to_string(), // subgraph doesn't check the total supply
             whitelist_pools: vec![],
+            metadata_status: MetadataStatus::Overridden as i32,
+            metadata_fallback_reason: "".to_string(),
         }),
         _ => None,
     }
 }
 
+// Placeholder for a token whose contract can't be read, without decimals its amounts are in raw token units
+pub fn placeholder_token(token_address: &str) -> Erc20Token {
+    Erc20Token {
+        address: token_address.to_string(),
+        name: "Unknown Token".to_string(),
+        symbol: "UNKNOWN".to_string(),
+        decimals: 0,
+        metadata_status: MetadataStatus::Placeholder as i32,
+        ..Default::default()
+    }
+}
+
 pub fn extract_pool_fee_growth_global_updates(
     log_ordinal: u64,
     pool_address: &Vec<u8>,
@@ -134,6 +160,7 @@ pub fn get_tracked_amount_usd(
     amount0_abs: &BigDecimal,
     amount1_abs: &BigDecimal,
     eth_price_in_usd: &BigDecimal,
+    whitelist: &Whitelist,
 ) -> BigDecimal {
     let price0_usd = token0_derived_eth_price.clone().mul(eth_price_in_usd.clone());
     let price1_usd = token1_derived_eth_price.clone().mul(eth_price_in_usd.clone());
@@ -142,7 +169,7 @@ pub fn get_tracked_amount_usd(
     log::info!("price1_usd: {}", price1_usd);
 
     // both are whitelist tokens, return sum of both amounts
-    if WHITELIST_TOKENS.contains(&token0_id.as_str()) && WHITELIST_TOKENS.contains(&token1_id.as_str()) {
+    if whitelist.contains(token0_id) && whitelist.contains(token1_id) {
         return amount0_abs
             .clone()
             .mul(price0_usd)
@@ -150,12 +177,12 @@ pub fn get_tracked_amount_usd(
     }
 
     // take double value of the whitelisted token amount
-    if WHITELIST_TOKENS.contains(&token0_id.as_str()) && !WHITELIST_TOKENS.contains(&token1_id.as_str()) {
+    if whitelist.contains(token0_id) && !whitelist.contains(token1_id) {
         return amount0_abs.clone().mul(price0_usd).mul(BigDecimal::from(2 as i32));
     }
 
     // take double value of the whitelisted token amount
-    if !WHITELIST_TOKENS.contains(&token0_id.as_str()) && WHITELIST_TOKENS.contains(&token1_id.as_str()) {
+    if !whitelist.contains(token0_id) && whitelist.contains(token1_id) {
         return amount1_abs.clone().mul(price1_usd).mul(BigDecimal::from(2 as i32));
     }
 
@@ -171,6 +198,7 @@ pub fn get_adjusted_amounts(
     token0_derived_eth_price: &BigDecimal,
     token1_derived_eth_price: &BigDecimal,
     bundle_eth_price_usd: &BigDecimal,
+    whitelist: &Whitelist,
 ) -> AdjustedAmounts {
     log::info!("token0_addr {:}", token0_addr);
     log::info!("token1_addr {:}", token1_addr);
@@ -193,18 +221,18 @@ pub fn get_adjusted_amounts(
         .mul(token0_derived_eth_price.clone())
         .add(token1_amount.clone().mul(token1_derived_eth_price.clone()));
 
-    if WHITELIST_TOKENS.contains(&token0_addr.as_str()) && WHITELIST_TOKENS.contains(&token1_addr.as_str()) {
+    if whitelist.contains(token0_addr) && whitelist.contains(token1_addr) {
         eth = eth_untracked.clone()
     }
 
-    if WHITELIST_TOKENS.contains(&token0_addr.as_str()) && !WHITELIST_TOKENS.contains(&token1_addr.as_str()) {
+    if whitelist.contains(token0_addr) && !whitelist.contains(token1_addr) {
         eth = token0_amount
             .clone()
             .mul(token0_derived_eth_price.clone())
             .mul(BigDecimal::from(2 as i32));
     }
 
-    if !WHITELIST_TOKENS.contains(&token0_addr.as_str()) && WHITELIST_TOKENS.contains(&token1_addr.as_str()) {
+    if !whitelist.contains(token0_addr) && whitelist.contains(token1_addr) {
         eth = token1_amount
             .clone()
             .mul(token1_derived_eth_price.clone())
@@ -290,3 +318,33 @@ pub fn token_windows_id_fields(key: &String) -> (&str, &str, &str) {
 
     return (table_name, time_id, token_address);
 }
+
+// Entries of a list of an appender store in the order they were appended, without the ones which left it
+pub fn resolve_appended<'a>(entries: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let mut resolved: Vec<&str> = vec![];
+    let mut listed: HashSet<&str> = HashSet::new();
+    for entry in entries {
+        match entry.strip_prefix(REMOVED_ENTRY_PREFIX) {
+            Some(removed) => {
+                if listed.remove(removed) {
+                    resolved.retain(|listed_entry| *listed_entry != removed);
+                }
+            }
+            None if !entry.is_empty() && listed.insert(entry) => resolved.push(entry),
+            None => {}
+        }
+    }
+    resolved.into_iter().map(str::to_string).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::resolve_appended;
+
+    #[test]
+    fn test_resolve_appended() {
+        assert_eq!(vec!["a", "b"], resolve_appended(["a", "b", "a", ""]));
+        assert_eq!(vec!["b", "a"], resolve_appended(["a", "b", "-a", "a"]));
+        assert_eq!(vec!["b"], resolve_appended(["-c", "a", "b", "-a"]));
+    }
+}
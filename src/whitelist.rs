@@ -0,0 +1,127 @@
+use crate::config::Config;
+use crate::price;
+use crate::utils;
+use crate::Pool;
+use std::ops::Mul;
+use substreams::scalar::BigDecimal;
+use substreams::store::{StoreGet, StoreGetBigDecimal, StoreGetBigInt, StoreGetProto, StoreGetRaw, StoreGetString};
+
+// Tokens join the whitelist once the ETH value of their TVL has stayed above `whitelist_eth_tvl` for
+// `whitelist_min_blocks`, and leave it as soon as it falls below. The block it went above the threshold is
+// kept under `token:{token}` in `store_whitelist_candidates`. The `whitelist_tokens` never leave it.
+//
+// The TVL is valued like `totalValueLockedETH` in `store_derived_tvl`, the `store_token_tvl` amount times the
+// derived ETH price of the token. `store_derived_tvl` itself is downstream of the pricing, which depends on the
+// whitelist, so the price is the guarded one of the deepest pool of the token against the native token.
+
+// Stores read to value the TVL of a token in the native token, all of them upstream of the pricing
+pub struct TvlStores<'a> {
+    pub token_tvl_store: &'a StoreGetBigDecimal,
+    pub pools_store: &'a StoreGetProto<Pool>,
+    pub pool_liquidities_store: &'a StoreGetBigInt,
+    pub token_pools_store: &'a StoreGetRaw,
+    pub prices_store: &'a StoreGetBigDecimal,
+}
+
+// ETH value of the TVL of a token at an ordinal, zero without a priced pool against the native token
+pub fn eth_tvl(ord: u64, token_address: &str, stores: &TvlStores, config: &Config) -> BigDecimal {
+    let eth_price = match price::native_pair_eth_price(
+        ord,
+        token_address,
+        stores.pools_store,
+        stores.pool_liquidities_store,
+        stores.token_pools_store,
+        stores.prices_store,
+        config,
+    ) {
+        None => return BigDecimal::zero(),
+        Some(price) => price,
+    };
+    stores
+        .token_tvl_store
+        .get_at(ord, format!("token:{token_address}"))
+        .unwrap_or_default()
+        .mul(eth_price)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Crossing {
+    Above,
+    Below,
+}
+
+pub fn threshold_crossing(old: &BigDecimal, new: &BigDecimal, threshold: &BigDecimal) -> Option<Crossing> {
+    match (old >= threshold, new >= threshold) {
+        (false, true) => Some(Crossing::Above),
+        (true, false) => Some(Crossing::Below),
+        _ => None,
+    }
+}
+
+// A token above the threshold since the block `since` is whitelisted from `since + min_blocks` on
+pub fn promoted(since: Option<u64>, block_number: u64, min_blocks: u64) -> bool {
+    matches!(since, Some(since) if since + min_blocks <= block_number)
+}
+
+pub fn parse_since(value: Option<String>) -> Option<u64> {
+    value.map(|block_number| block_number.parse::<u64>().unwrap())
+}
+
+// Whitelist at a block: the `whitelist_tokens` and the tokens promoted by then
+pub struct Whitelist<'a> {
+    pub block_number: u64,
+    pub candidates_store: &'a StoreGetString,
+    pub config: &'a Config,
+}
+
+impl Whitelist<'_> {
+    pub fn contains(&self, token_address: &str) -> bool {
+        is_whitelisted(token_address, self.block_number, self.candidates_store, self.config)
+    }
+}
+
+pub fn is_whitelisted(
+    token_address: &str,
+    block_number: u64,
+    candidates_store: &StoreGetString,
+    config: &Config,
+) -> bool {
+    if config.is_whitelisted(token_address) {
+        return true;
+    }
+    let since = parse_since(candidates_store.get_last(format!("token:{token_address}")));
+    promoted(since, block_number, config.whitelist_min_blocks)
+}
+
+// Pools of a token against a whitelisted token, a pool leaving them is appended to `store_tokens_whitelist_pools`
+// with the `utils::REMOVED_ENTRY_PREFIX`
+pub fn whitelist_pools(tokens_whitelist_pools_store: &StoreGetRaw, token_address: &str) -> Vec<String> {
+    let entries = price::token_pools(tokens_whitelist_pools_store, token_address);
+    utils::resolve_appended(entries.iter().map(String::as_str))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::whitelist::{promoted, threshold_crossing, Crossing};
+    use substreams::scalar::BigDecimal;
+
+    #[test]
+    fn test_threshold_crossing() {
+        let threshold = BigDecimal::from(1000);
+        let crossing =
+            |old: i32, new: i32| threshold_crossing(&BigDecimal::from(old), &BigDecimal::from(new), &threshold);
+
+        assert_eq!(Some(Crossing::Above), crossing(0, 1000));
+        assert_eq!(Some(Crossing::Below), crossing(1500, 999));
+        assert_eq!(None, crossing(1500, 2000));
+        assert_eq!(None, crossing(10, 20));
+    }
+
+    #[test]
+    fn test_promoted() {
+        assert!(!promoted(None, 100, 10));
+        assert!(!promoted(Some(95), 100, 10));
+        assert!(promoted(Some(90), 100, 10));
+        assert!(promoted(Some(100), 100, 0));
+    }
+}